@@ -19,6 +19,7 @@ rustPlatform.buildRustPackage (finalAttrs: {
     ".toml"
     ".lock"
     ".nix"
+    ".nix.in" # Fragments of lon.nix
     ".json" # Test fixtures
   ];
 
@@ -1,22 +1,51 @@
-use std::{
-    env,
-    fs::{self, File},
-    io,
-};
+//! Record information about the build that `lon version` reports.
 
-use sha2::{Digest, Sha256};
+use std::{env, path::Path, process::Command};
 
-const LON_NIX_FILENAME: &str = "lon.nix";
+/// Run a command and return its trimmed stdout if it succeeds.
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    Some(stdout.trim().to_string()).filter(|stdout| !stdout.is_empty())
+}
 
-fn main() {
-    let mut out_path = env::var_os("OUT_DIR").expect("Failed to read OUT_DIR");
-    out_path.push(format!("{LON_NIX_FILENAME}.sha256"));
+/// Run git in the repository of Lon.
+///
+/// The repository is set explicitly so that a source tree without its own repository (e.g. a
+/// vendored copy) never reports the revision of a repository it happens to be inside of.
+fn git(args: &[&str]) -> Option<String> {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").ok()?;
+    let git_dir = Path::new(&manifest_dir).join("../../.git");
+    let git_dir = git_dir.to_str()?;
+    output("git", &[&["--git-dir", git_dir], args].concat())
+}
 
-    let mut file = File::open(format!("src/{LON_NIX_FILENAME}")).expect("Failed to read lon.nix");
+fn main() {
+    // Builds without a git checkout (e.g. with Nix) can supply the revision themselves.
+    println!("cargo:rerun-if-env-changed=LON_GIT_SHA");
+    let git_sha = env::var("LON_GIT_SHA")
+        .ok()
+        .filter(|sha| !sha.is_empty())
+        .or_else(|| git(&["rev-parse", "HEAD"]));
+    if let Some(git_sha) = git_sha {
+        println!("cargo:rustc-env=LON_GIT_SHA={git_sha}");
+    }
 
-    let mut hasher = Sha256::new();
-    io::copy(&mut file, &mut hasher).expect("Failed to hash lon.nix");
-    let hash = hasher.finalize();
+    // Rebuild when another commit is checked out or a commit is made on the current branch.
+    let branch = git(&["rev-parse", "--symbolic-full-name", "HEAD"]);
+    for reference in ["HEAD"].into_iter().chain(branch.as_deref()) {
+        if let Some(path) =
+            git(&["rev-parse", "--git-path", reference]).filter(|path| Path::new(path).exists())
+        {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
 
-    fs::write(out_path, hash).expect("Failed to write lon.nix.sha256");
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    if let Some(version) = output(&rustc, &["--version"]) {
+        println!("cargo:rustc-env=LON_RUSTC_VERSION={version}");
+    }
 }
@@ -0,0 +1,152 @@
+//! Find risks to the reproducibility and freshness of the locked sources.
+
+use std::fmt;
+
+use nix_compat::nixhash::{HashAlgo, NixHash};
+
+use crate::{
+    sources::{Source, SourceKind, Sources},
+    time,
+};
+
+/// Branches that are developed on continuously.
+///
+/// Tracking them is fine as long as the source is updated regularly.
+const MOVING_BRANCHES: &[&str] = &["master", "main"];
+
+/// How urgent it is to address a finding.
+///
+/// Ordered from most to least urgent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    High,
+    Medium,
+    Low,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self {
+            Self::High => "high",
+            Self::Medium => "medium",
+            Self::Low => "low",
+        };
+        write!(f, "{severity}")
+    }
+}
+
+/// A risk found for a source together with how to address it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub name: String,
+    pub problem: String,
+    pub hint: String,
+}
+
+/// Audit all sources.
+///
+/// Sources whose locked revision is older than `max_age` seconds at `now` while tracking a
+/// moving branch are reported. The findings are sorted by severity and name.
+pub fn audit(sources: &Sources, now: u64, max_age: u64) -> Vec<Finding> {
+    let mut findings = sources
+        .names()
+        .into_iter()
+        .filter_map(|name| Some((name, sources.get(name)?)))
+        .flat_map(|(name, source)| audit_source(name, source, now, max_age))
+        .collect::<Vec<_>>();
+    findings.sort_by(|a, b| (a.severity, &a.name).cmp(&(b.severity, &b.name)));
+    findings
+}
+
+fn audit_source(name: &str, source: &Source, now: u64, max_age: u64) -> Vec<Finding> {
+    let finding = |severity, problem: String, hint: String| Finding {
+        severity,
+        name: name.into(),
+        problem,
+        hint,
+    };
+    let mut findings = Vec::new();
+
+    if let Some(algo @ (HashAlgo::Md5 | HashAlgo::Sha1)) = source.hash().map(NixHash::algo) {
+        findings.push(finding(
+            Severity::High,
+            format!("is locked with the weak hash algorithm {algo}"),
+            "Remove it and add it again to lock it with SHA-256".into(),
+        ));
+    }
+
+    if source.kind() == SourceKind::Git && source.last_updated().is_none() {
+        findings.push(finding(
+            Severity::Medium,
+            "doesn't record lastModified, so builtins.fetchGit can't fetch it shallowly".into(),
+            "It is recorded the next time the source is locked, e.g. by lon update".into(),
+        ));
+    }
+
+    // The commit date is only known for git sources. Otherwise, fall back to the last update by
+    // Lon.
+    let age = source
+        .last_updated()
+        .or(source.meta().last_checked)
+        .map(|last| now.saturating_sub(last));
+    if let (Some(branch), Some(age)) = (source.branch(), age)
+        && MOVING_BRANCHES.contains(&branch)
+        && age > max_age
+        && !source.meta().frozen
+    {
+        findings.push(finding(
+            Severity::Low,
+            format!(
+                "tracks {branch}, but was last updated {} ago",
+                time::format_duration(age)
+            ),
+            format!("Run lon update {name} or freeze it if the old revision is intentional"),
+        ));
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::Result;
+
+    use crate::lock;
+
+    #[test]
+    fn audit_sources() -> Result<()> {
+        let lock = serde_json::from_str::<lock::v1::Lock>(include_str!("../tests/lon.lock"))?;
+        let mut sources = Sources::from(lock);
+
+        // The locked revision of lanzaboote is from 2024-07-30.
+        let now = 1_722_329_086 + 100 * 24 * 60 * 60;
+        let max_age = 90 * 24 * 60 * 60;
+
+        let findings = audit(&sources, now, max_age);
+        assert_eq!(
+            findings
+                .iter()
+                .map(|f| (f.severity, f.name.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (Severity::Medium, "lanzaboote-2"),
+                (Severity::Low, "lanzaboote"),
+            ]
+        );
+        assert_eq!(
+            findings[1].problem,
+            "tracks master, but was last updated 100d ago"
+        );
+
+        // Frozen sources are old on purpose.
+        if let Some(source) = sources.get_mut("lanzaboote") {
+            source.meta_mut().frozen = true;
+        }
+        assert_eq!(audit(&sources, now, max_age).len(), 1);
+
+        Ok(())
+    }
+}
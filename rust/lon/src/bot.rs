@@ -1,21 +1,133 @@
 use anyhow::Result;
+use reqwest::Url;
+
+use crate::git::{CommitFilter, RevList};
 
 mod forgejo;
 mod github;
 mod gitlab;
+mod run_report;
+mod state;
 
 pub use forgejo::Forgejo;
 pub use github::GitHub;
 pub use gitlab::GitLab;
+pub use run_report::{RunReport, SourceOutcome, SourceReport};
+pub use state::BotState;
 
 pub trait Forge {
     /// Open a PR on the forge.
     ///
     /// Specify the source branch for the PR and the name of the dependency that is being updated.
+    /// The PR targets the base branch or, if there is none, the default branch of the repository.
+    /// The labels of the source are added to the PR on top of the labels configured for all PRs.
     fn open_pull_request(
         &self,
         source_branch: &str,
+        base_branch: Option<&str>,
         name: &str,
+        labels: &[String],
         body: Option<String>,
     ) -> Result<String>;
+
+    /// Return the title of the PR for the update of a source.
+    fn pull_request_title(&self, name: &str) -> String {
+        format!("lon: update {name}")
+    }
+
+    /// Return the labels added to the PR for the update of a source.
+    ///
+    /// These are the labels configured for all PRs together with the labels of the source.
+    fn pull_request_labels(&self, source_labels: &[String]) -> Vec<String>;
+
+    /// Open an issue on the forge or update the body of an open issue with the same title.
+    ///
+    /// Returns the URL of the issue.
+    fn open_issue(&self, title: &str, body: &str) -> Result<String>;
+
+    /// List the newest commits between two revisions of a repository via the API of the forge.
+    ///
+    /// Returns `None` if the repository isn't hosted on this forge or if the API didn't list the
+    /// newest commit so that its first-parent history can't be followed. The commits are then
+    /// listed via git instead.
+    fn compare_commits(
+        &self,
+        _url: &str,
+        _old_revision: &str,
+        _new_revision: &str,
+        _num_commits: usize,
+        _filter: CommitFilter,
+    ) -> Result<Option<RevList>> {
+        Ok(None)
+    }
+}
+
+/// Return the path of a repository (e.g. `group/project`) if its URL points to the same host as
+/// the API of a forge.
+fn repository_path(url: &str, api_url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let api_url = Url::parse(api_url).ok()?;
+    if !matches!(url.scheme(), "http" | "https")
+        || url.host_str() != api_url.host_str()
+        || url.port_or_known_default() != api_url.port_or_known_default()
+    {
+        return None;
+    }
+    let path = url.path().trim_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    (!path.is_empty()).then(|| path.into())
+}
+
+/// Combine the labels for all PRs with the labels of a single source.
+///
+/// The order is kept and duplicates are removed.
+fn merge_labels(labels: &[String], source_labels: &[String]) -> Vec<String> {
+    let mut merged = Vec::with_capacity(labels.len() + source_labels.len());
+    for label in labels.iter().chain(source_labels) {
+        if !merged.contains(label) {
+            merged.push(label.clone());
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_source_labels() {
+        let labels = vec!["dependencies".to_string(), "lon".to_string()];
+        let source_labels = vec!["high-priority".to_string(), "lon".to_string()];
+        assert_eq!(
+            merge_labels(&labels, &source_labels),
+            vec!["dependencies", "lon", "high-priority"]
+        );
+        assert_eq!(merge_labels(&[], &source_labels), source_labels);
+    }
+
+    #[test]
+    fn find_repository_path() {
+        let api_url = "https://gitlab.example.com/api/v4";
+        assert_eq!(
+            repository_path("https://gitlab.example.com/group/sub/project.git", api_url),
+            Some("group/sub/project".into())
+        );
+        assert_eq!(
+            repository_path("https://gitlab.example.com/group/project/", api_url),
+            Some("group/project".into())
+        );
+        assert_eq!(
+            repository_path("https://gitlab.example.com:8443/group/project", api_url),
+            None
+        );
+        assert_eq!(
+            repository_path("https://gitlab.com/group/project", api_url),
+            None
+        );
+        assert_eq!(
+            repository_path("git@gitlab.example.com:group/project.git", api_url),
+            None
+        );
+    }
 }
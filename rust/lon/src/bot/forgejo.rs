@@ -1,5 +1,3 @@
-use std::env;
-
 use anyhow::{Context, Result, bail};
 use reqwest::{
     blocking::Client,
@@ -7,7 +5,12 @@ use reqwest::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{bot::Forge, config::required_env};
+use crate::{
+    bot::{Forge, merge_labels, repository_path},
+    config::{BotConfig, ensure_online, list_env, required_env},
+    git::{self, CommitFilter, RevList},
+    http,
+};
 
 pub struct Forgejo {
     // Defined by CI
@@ -46,9 +49,63 @@ struct Labels {
     labels: Vec<String>,
 }
 
+#[derive(Serialize)]
+struct Issue<'a> {
+    title: &'a str,
+    body: &'a str,
+}
+
+#[derive(Serialize)]
+struct IssueUpdate<'a> {
+    body: &'a str,
+}
+
+#[derive(Deserialize)]
+struct IssueResponse {
+    html_url: String,
+    number: i64,
+    title: String,
+}
+
+#[derive(Deserialize)]
+struct Comparison {
+    commits: Vec<ComparedCommit>,
+}
+
+#[derive(Deserialize)]
+struct ComparedCommit {
+    sha: String,
+    commit: CommitDetails,
+    #[serde(default)]
+    parents: Vec<CommitParent>,
+}
+
+#[derive(Deserialize)]
+struct CommitParent {
+    sha: String,
+}
+
+#[derive(Deserialize)]
+struct CommitDetails {
+    message: String,
+    author: CommitSignature,
+    committer: CommitSignature,
+}
+
+#[derive(Deserialize)]
+struct CommitSignature {
+    name: String,
+    /// ISO 8601 timestamp
+    date: String,
+}
+
 impl Forgejo {
-    pub fn from_env() -> Result<Self> {
-        let labels = env::var("LON_LABELS").unwrap_or_default();
+    pub fn from_env(config: &BotConfig) -> Result<Self> {
+        ensure_online("access the Forgejo API")?;
+
+        let labels = list_env("LON_LABELS")
+            .or(config.labels.clone())
+            .unwrap_or_default();
         let token = required_env("LON_TOKEN")?;
 
         let mut headers = header::HeaderMap::new();
@@ -64,10 +121,9 @@ impl Forgejo {
             api_url: required_env("GITHUB_API_URL")?,
             repository: required_env("GITHUB_REPOSITORY")?,
 
-            labels: labels.split(',').map(ToString::to_string).collect(),
+            labels,
 
-            client: Client::builder()
-                .user_agent("LonBot")
+            client: http::client_builder()?
                 .default_headers(headers)
                 .build()
                 .context("Failed to build the HTTP client")?,
@@ -100,10 +156,8 @@ impl Forgejo {
         Ok(repository)
     }
 
-    fn add_labels(&self, index: i64) -> Result<()> {
-        let labels = Labels {
-            labels: self.labels.clone(),
-        };
+    fn add_labels(&self, index: i64, labels: Vec<String>) -> Result<()> {
+        let labels = Labels { labels };
 
         let url = format!("{}/issues/{index}/labels", self.repo_api_url());
 
@@ -121,16 +175,61 @@ impl Forgejo {
 
         Ok(())
     }
+
+    /// Find the open issue with a title.
+    ///
+    /// All pages are searched because newer issues can push it off the first page.
+    fn find_open_issue(&self, url: &str, title: &str) -> Result<Option<IssueResponse>> {
+        for page in 1.. {
+            let res = self
+                .client
+                .get(url)
+                .query(&[
+                    ("state", "open".to_string()),
+                    ("type", "issues".to_string()),
+                    ("limit", "50".to_string()),
+                    ("page", page.to_string()),
+                ])
+                .send()
+                .with_context(|| format!("Failed to send GET request to {url}"))?;
+
+            let status = res.status();
+            if !status.is_success() {
+                bail!("Failed to list issues at {url}: {status}:\n{}", res.text()?)
+            }
+
+            // The instance can cap the limit, so only an empty page marks the end.
+            let issues = res.json::<Vec<IssueResponse>>()?;
+            if issues.is_empty() {
+                break;
+            }
+
+            if let Some(issue) = issues.into_iter().find(|issue| issue.title == title) {
+                return Ok(Some(issue));
+            }
+        }
+        Ok(None)
+    }
 }
 
 impl Forge for Forgejo {
-    fn open_pull_request(&self, branch: &str, name: &str, body: Option<String>) -> Result<String> {
-        let repository = self.get_repository()?;
+    fn open_pull_request(
+        &self,
+        branch: &str,
+        base_branch: Option<&str>,
+        name: &str,
+        labels: &[String],
+        body: Option<String>,
+    ) -> Result<String> {
+        let base = match base_branch {
+            Some(base_branch) => base_branch.into(),
+            None => self.get_repository()?.default_branch,
+        };
 
         let pull_request = PullRequest {
             head: branch.into(),
-            base: repository.default_branch.clone(),
-            title: format!("lon: update {name}"),
+            base,
+            title: self.pull_request_title(name),
             body,
         };
 
@@ -153,8 +252,115 @@ impl Forge for Forgejo {
 
         let pull_request_response = res.json::<PullRequestResponse>()?;
 
-        self.add_labels(pull_request_response.number)?;
+        self.add_labels(
+            pull_request_response.number,
+            self.pull_request_labels(labels),
+        )?;
 
         Ok(pull_request_response.html_url)
     }
+
+    fn pull_request_labels(&self, source_labels: &[String]) -> Vec<String> {
+        merge_labels(&self.labels, source_labels)
+    }
+
+    fn open_issue(&self, title: &str, body: &str) -> Result<String> {
+        let url = format!("{}/issues", self.repo_api_url());
+
+        if let Some(issue) = self.find_open_issue(&url, title)? {
+            let url = format!("{url}/{}", issue.number);
+
+            let res = self
+                .client
+                .patch(&url)
+                .json(&IssueUpdate { body })
+                .send()
+                .with_context(|| format!("Failed to send PATCH request to {url}"))?;
+
+            let status = res.status();
+            if !status.is_success() {
+                bail!(
+                    "Failed to update issue at {url}: {status}:\n{}",
+                    res.text()?
+                )
+            }
+
+            return Ok(issue.html_url);
+        }
+
+        let res = self
+            .client
+            .post(&url)
+            .json(&Issue { title, body })
+            .send()
+            .with_context(|| format!("Failed to send POST request to {url}"))?;
+
+        let status = res.status();
+        if !status.is_success() {
+            bail!("Failed to open issue at {url}: {status}:\n{}", res.text()?)
+        }
+
+        let issue = res.json::<IssueResponse>()?;
+
+        if !self.labels.is_empty() {
+            self.add_labels(issue.number, self.labels.clone())?;
+        }
+
+        Ok(issue.html_url)
+    }
+
+    fn compare_commits(
+        &self,
+        url: &str,
+        old_revision: &str,
+        new_revision: &str,
+        num_commits: usize,
+        filter: CommitFilter,
+    ) -> Result<Option<RevList>> {
+        let Some(path) = repository_path(url, &self.api_url) else {
+            return Ok(None);
+        };
+
+        let url = format!(
+            "{}/repos/{path}/compare/{old_revision}...{new_revision}",
+            self.api_url
+        );
+
+        let res = self
+            .client
+            .get(&url)
+            .send()
+            .with_context(|| format!("Failed to send GET request to {url}"))?;
+
+        let status = res.status();
+        if !status.is_success() {
+            bail!(
+                "Failed to compare commits at {url}: {status}:\n{}",
+                res.text()?
+            )
+        }
+
+        let comparison = res.json::<Comparison>()?;
+        let Some(commits) = filter.apply(
+            comparison.commits,
+            new_revision,
+            |c| &c.sha,
+            |c| c.parents.iter().map(|p| p.sha.as_str()).collect(),
+        ) else {
+            return Ok(None);
+        };
+        let total = commits.len();
+
+        let commits = git::newest_commits(commits, |c| &c.commit.committer.date, num_commits)
+            .into_iter()
+            .map(|c| {
+                // Only keep the date to match the short date format of git.
+                let date = &c.commit.committer.date;
+                let date = date.get(..10).unwrap_or(date);
+                git::Commit::from_str(&c.sha, &c.commit.message)
+                    .with_author(&c.commit.author.name, date)
+            });
+
+        Ok(Some(RevList::from_commits(commits).with_total(total)))
+    }
 }
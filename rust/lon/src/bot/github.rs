@@ -1,8 +1,12 @@
 use std::env;
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 
-use crate::{bot::Forge, config::required_env, http::GitHubRepoApi};
+use crate::{
+    bot::{Forge, merge_labels},
+    config::{BotConfig, list_env, required_env},
+    http::{self, GitHubRepoApi},
+};
 
 pub struct GitHub {
     // Defined by the user
@@ -13,28 +17,66 @@ pub struct GitHub {
 }
 
 impl GitHub {
-    pub fn from_env() -> Result<Self> {
+    pub fn from_env(config: &BotConfig) -> Result<Self> {
         let repository = required_env("GITHUB_REPOSITORY")?;
-        let labels = env::var("LON_LABELS").unwrap_or_default();
-        let token = required_env("LON_TOKEN")?;
+        let labels = list_env("LON_LABELS")
+            .or(config.labels.clone())
+            .unwrap_or_default();
+        let Some(token) = token_from_env().or_else(http::find_github_token) else {
+            bail!(
+                "Failed to read LON_TOKEN or GITHUB_TOKEN from environment. No token was found via `gh auth token` or `git credential fill` either"
+            );
+        };
 
         Ok(Self {
-            labels: labels.split(',').map(ToString::to_string).collect(),
+            labels,
 
             github_repo_api: GitHubRepoApi::builder(&repository).token(&token).build()?,
         })
     }
 }
 
+/// Read the token from `LON_TOKEN` or, as GitHub Actions calls it, `GITHUB_TOKEN`.
+fn token_from_env() -> Option<String> {
+    if let Ok(token) = env::var("LON_TOKEN") {
+        log::debug!("Using the token from LON_TOKEN");
+        return Some(token);
+    }
+    let token = env::var("GITHUB_TOKEN").ok()?;
+    log::info!("LON_TOKEN is not set. Using the token from GITHUB_TOKEN");
+    Some(token)
+}
+
 impl Forge for GitHub {
-    fn open_pull_request(&self, branch: &str, name: &str, body: Option<String>) -> Result<String> {
-        let pull_request_response =
-            self.github_repo_api
-                .open_pull_request(branch, &format!("lon: update {name}"), body)?;
+    fn open_pull_request(
+        &self,
+        branch: &str,
+        base_branch: Option<&str>,
+        name: &str,
+        labels: &[String],
+        body: Option<String>,
+    ) -> Result<String> {
+        let pull_request_response = self.github_repo_api.open_pull_request(
+            branch,
+            base_branch,
+            &self.pull_request_title(name),
+            body,
+        )?;
 
-        self.github_repo_api
-            .add_labels_to_issue(pull_request_response.number, &self.labels)?;
+        self.github_repo_api.add_labels_to_issue(
+            pull_request_response.number,
+            &self.pull_request_labels(labels),
+        )?;
 
         Ok(pull_request_response.html_url)
     }
+
+    fn pull_request_labels(&self, source_labels: &[String]) -> Vec<String> {
+        merge_labels(&self.labels, source_labels)
+    }
+
+    fn open_issue(&self, title: &str, body: &str) -> Result<String> {
+        self.github_repo_api
+            .open_or_update_issue(title, body, &self.labels)
+    }
 }
@@ -1,9 +1,15 @@
-use std::env;
+use std::{env, sync::OnceLock};
 
 use anyhow::{Context, Result, bail};
+use reqwest::blocking::Client;
 use serde::{Deserialize, Serialize};
 
-use crate::{bot::Forge, config::required_env};
+use crate::{
+    bot::{Forge, merge_labels, repository_path},
+    config::{BotConfig, ensure_online, flag_env, list_env, required_env},
+    git::{self, CommitFilter, RevList},
+    http,
+};
 
 pub struct GitLab {
     // Defined by CI
@@ -14,42 +20,141 @@ pub struct GitLab {
     // Defined by the user
     labels: Vec<String>,
     token: String,
+    reviewers: Vec<String>,
+    assignees: Vec<String>,
+    draft: bool,
+
+    // Internal
+    client: Client,
+    /// The IDs of the reviewers and assignees, once they were resolved
+    user_ids: OnceLock<UserIds>,
+}
+
+struct UserIds {
+    reviewers: Vec<u64>,
+    assignees: Vec<u64>,
+}
+
+#[derive(Deserialize)]
+struct User {
+    id: u64,
 }
 
 impl GitLab {
-    pub fn from_env() -> Result<Self> {
-        let labels = env::var("LON_LABELS").unwrap_or_default();
+    pub fn from_env(config: &BotConfig) -> Result<Self> {
+        ensure_online("access the GitLab API")?;
+
+        let labels = list_env("LON_LABELS")
+            .or(config.labels.clone())
+            .unwrap_or_default();
+        let reviewers = list_env("LON_REVIEWERS")
+            .or(config.reviewers.clone())
+            .unwrap_or_default();
+        let assignees = list_env("LON_ASSIGNEES")
+            .or(config.assignees.clone())
+            .unwrap_or_default();
+
         Ok(Self {
             api_url: required_env("CI_API_V4_URL")?,
             project_id: required_env("CI_PROJECT_ID")?,
             default_branch: required_env("CI_DEFAULT_BRANCH")?,
 
-            labels: labels.split(',').map(ToString::to_string).collect(),
+            labels,
             token: required_env("LON_TOKEN")?,
+            reviewers,
+            assignees,
+            draft: if env::var_os("LON_DRAFT").is_some() {
+                flag_env("LON_DRAFT")
+            } else {
+                config.draft.unwrap_or(false)
+            },
+
+            client: http::client_builder()?
+                .build()
+                .context("Failed to build the HTTP client")?,
+            user_ids: OnceLock::new(),
         })
     }
 
     fn project_api_url(&self) -> String {
         format!("{}/projects/{}", self.api_url, self.project_id)
     }
+
+    /// Return the IDs of the reviewers and assignees.
+    ///
+    /// They are only resolved when the first Merge Request or issue is opened so that runs that
+    /// don't open any (e.g. with --dry-run) don't look them up. Failures aren't remembered.
+    fn user_ids(&self) -> Result<&UserIds> {
+        if let Some(user_ids) = self.user_ids.get() {
+            return Ok(user_ids);
+        }
+        let user_ids = UserIds {
+            reviewers: self.resolve_user_ids(&self.reviewers)?,
+            assignees: self.resolve_user_ids(&self.assignees)?,
+        };
+        Ok(self.user_ids.get_or_init(|| user_ids))
+    }
+
+    /// Resolve a list of usernames to their user IDs.
+    fn resolve_user_ids(&self, usernames: &[String]) -> Result<Vec<u64>> {
+        usernames
+            .iter()
+            .map(|username| self.resolve_user_id(username))
+            .collect()
+    }
+
+    fn resolve_user_id(&self, username: &str) -> Result<u64> {
+        let url = format!("{}/users", self.api_url);
+
+        let res = self
+            .client
+            .get(&url)
+            .query(&[("username", username)])
+            .bearer_auth(&self.token)
+            .send()
+            .with_context(|| format!("Failed to send GET request to {url}"))?;
+
+        let status = res.status();
+        if !status.is_success() {
+            bail!("Failed to look up user {username} at {url}: {status}")
+        }
+
+        let users = res.json::<Vec<User>>()?;
+
+        let Some(user) = users.first() else {
+            bail!("User {username} doesn't exist")
+        };
+
+        Ok(user.id)
+    }
 }
 
 impl Forge for GitLab {
-    fn open_pull_request(&self, branch: &str, name: &str, body: Option<String>) -> Result<String> {
+    fn open_pull_request(
+        &self,
+        branch: &str,
+        base_branch: Option<&str>,
+        name: &str,
+        labels: &[String],
+        body: Option<String>,
+    ) -> Result<String> {
+        let user_ids = self.user_ids()?;
         let merge_request = MergeRequest {
             source_branch: branch.into(),
-            target_branch: self.default_branch.clone(),
-            title: format!("lon: update {name}"),
+            target_branch: base_branch.unwrap_or(&self.default_branch).into(),
+            title: self.pull_request_title(name),
             body,
             remove_source_branch: true,
             allow_collaboration: true,
-            labels: self.labels.join(","),
+            labels: self.pull_request_labels(labels).join(","),
+            reviewer_ids: user_ids.reviewers.clone(),
+            assignee_ids: user_ids.assignees.clone(),
         };
 
         let url = format!("{}/merge_requests", self.project_api_url());
 
-        let client = reqwest::blocking::Client::new();
-        let res = client
+        let res = self
+            .client
             .post(&url)
             .json(&merge_request)
             .bearer_auth(&self.token)
@@ -65,6 +170,133 @@ impl Forge for GitLab {
 
         Ok(res_json.web_url)
     }
+
+    fn pull_request_title(&self, name: &str) -> String {
+        let title = format!("lon: update {name}");
+        if self.draft {
+            format!("Draft: {title}")
+        } else {
+            title
+        }
+    }
+
+    fn pull_request_labels(&self, source_labels: &[String]) -> Vec<String> {
+        merge_labels(&self.labels, source_labels)
+    }
+
+    fn open_issue(&self, title: &str, body: &str) -> Result<String> {
+        let url = format!("{}/issues", self.project_api_url());
+
+        let res = self
+            .client
+            .get(&url)
+            .query(&[("state", "opened"), ("in", "title"), ("search", title)])
+            .bearer_auth(&self.token)
+            .send()
+            .with_context(|| format!("Failed to send GET request to {url}"))?;
+
+        let status = res.status();
+        if !status.is_success() {
+            bail!("Failed to list issues at {url}: {status}")
+        }
+
+        let issues = res.json::<Vec<IssueResponse>>()?;
+
+        // The search also matches titles that only contain the title.
+        if let Some(issue) = issues.into_iter().find(|issue| issue.title == title) {
+            let url = format!("{url}/{}", issue.iid);
+
+            let res = self
+                .client
+                .put(&url)
+                .json(&IssueUpdate { description: body })
+                .bearer_auth(&self.token)
+                .send()
+                .with_context(|| format!("Failed to send PUT request to {url}"))?;
+
+            let status = res.status();
+            if !status.is_success() {
+                bail!("Failed to update issue at {url}: {status}")
+            }
+
+            return Ok(issue.web_url);
+        }
+
+        let issue = Issue {
+            title,
+            description: body,
+            labels: self.labels.join(","),
+            assignee_ids: self.user_ids()?.assignees.clone(),
+        };
+
+        let res = self
+            .client
+            .post(&url)
+            .json(&issue)
+            .bearer_auth(&self.token)
+            .send()
+            .with_context(|| format!("Failed to send POST request to {url}"))?;
+
+        let status = res.status();
+        if !status.is_success() {
+            bail!("Failed to open issue at {url}: {status}")
+        }
+
+        Ok(res.json::<IssueResponse>()?.web_url)
+    }
+
+    fn compare_commits(
+        &self,
+        url: &str,
+        old_revision: &str,
+        new_revision: &str,
+        num_commits: usize,
+        filter: CommitFilter,
+    ) -> Result<Option<RevList>> {
+        let Some(path) = repository_path(url, &self.api_url) else {
+            return Ok(None);
+        };
+
+        let url = format!(
+            "{}/projects/{}/repository/compare",
+            self.api_url,
+            encode_project_path(&path)
+        );
+
+        let res = self
+            .client
+            .get(&url)
+            .query(&[("from", old_revision), ("to", new_revision)])
+            .bearer_auth(&self.token)
+            .send()
+            .with_context(|| format!("Failed to send GET request to {url}"))?;
+
+        let status = res.status();
+        if !status.is_success() {
+            bail!("Failed to compare commits at {url}: {status}")
+        }
+
+        let comparison = res.json::<Comparison>()?;
+        let Some(commits) = filter.apply(
+            comparison.commits,
+            new_revision,
+            |c| &c.id,
+            |c| c.parent_ids.iter().map(String::as_str).collect(),
+        ) else {
+            return Ok(None);
+        };
+        let total = commits.len();
+
+        let commits = git::newest_commits(commits, |c| &c.committed_date, num_commits)
+            .into_iter()
+            .map(|c| {
+                // Only keep the date to match the short date format of git.
+                let date = c.committed_date.get(..10).unwrap_or(&c.committed_date);
+                git::Commit::from_str(&c.id, &c.message).with_author(&c.author_name, date)
+            });
+
+        Ok(Some(RevList::from_commits(commits).with_total(total)))
+    }
 }
 
 #[derive(Serialize)]
@@ -77,9 +309,97 @@ struct MergeRequest {
     remove_source_branch: bool,
     allow_collaboration: bool,
     labels: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    reviewer_ids: Vec<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    assignee_ids: Vec<u64>,
 }
 
 #[derive(Deserialize)]
 struct MergeRequestResponse {
     web_url: String,
 }
+
+#[derive(Serialize)]
+struct Issue<'a> {
+    title: &'a str,
+    description: &'a str,
+    labels: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    assignee_ids: Vec<u64>,
+}
+
+#[derive(Serialize)]
+struct IssueUpdate<'a> {
+    description: &'a str,
+}
+
+#[derive(Deserialize)]
+struct IssueResponse {
+    iid: u64,
+    title: String,
+    web_url: String,
+}
+
+#[derive(Deserialize)]
+struct Comparison {
+    commits: Vec<Commit>,
+}
+
+#[derive(Deserialize)]
+struct Commit {
+    id: String,
+    message: String,
+    author_name: String,
+    /// ISO 8601 timestamp
+    committed_date: String,
+    #[serde(default)]
+    parent_ids: Vec<String>,
+}
+
+/// Encode the path of a project so that it can be used as the project ID in API URLs.
+///
+/// GitLab expects the full path including all subgroups as a single path segment, e.g.
+/// `group%2Fsubgroup%2Frepo`. Everything but unreserved characters is percent-encoded.
+fn encode_project_path(path: &str) -> String {
+    path.bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+                char::from(byte).to_string()
+            } else {
+                format!("%{byte:02X}")
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_nested_project_paths() {
+        assert_eq!(encode_project_path("group/repo"), "group%2Frepo");
+        assert_eq!(
+            encode_project_path("group/subgroup/repo"),
+            "group%2Fsubgroup%2Frepo"
+        );
+        assert_eq!(
+            encode_project_path("group/sub-group/sub_group.2/my.repo"),
+            "group%2Fsub-group%2Fsub_group.2%2Fmy.repo"
+        );
+    }
+
+    #[test]
+    fn encode_nested_project_path_from_url() {
+        let api_url = "https://gitlab.example.com/api/v4";
+        let path = repository_path(
+            "https://gitlab.example.com/group/subgroup/subsubgroup/repo.git",
+            api_url,
+        );
+        assert_eq!(
+            path.as_deref().map(encode_project_path),
+            Some("group%2Fsubgroup%2Fsubsubgroup%2Frepo".into())
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use std::{fs::File, io::Write, path::Path, time::Duration};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::sources::UpdateSummary;
+
+/// Structured report of a bot run written via `lon bot --report-file`.
+///
+/// This is meant to be consumed by dashboards, so fields are only ever added to it.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunReport {
+    /// Unix timestamp of the start of the run
+    pub started_at: u64,
+    pub duration_seconds: f64,
+    /// URL of the issue listing all available updates if only reporting them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issue: Option<String>,
+    /// All sources sorted by name
+    pub sources: Vec<SourceReport>,
+    /// Why the run failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl RunReport {
+    /// Write the report as JSON.
+    pub fn to_file(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let mut file =
+            File::create(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        serde_json::to_writer_pretty(&mut file, self).context("Failed to serialize the report")?;
+        file.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+/// What happened to a source during a bot run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceOutcome {
+    /// An update was committed and pushed
+    Updated,
+    /// An update is available, but it was only reported
+    Available,
+    Unchanged,
+    Frozen,
+    /// The source wasn't due for an update check
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceReport {
+    pub name: String,
+    pub outcome: SourceOutcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_revision: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_revision: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pull_request: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_seconds: f64,
+}
+
+impl SourceReport {
+    pub fn new(name: &str, outcome: SourceOutcome, duration: Duration) -> Self {
+        Self {
+            name: name.into(),
+            outcome,
+            old_revision: None,
+            new_revision: None,
+            pull_request: None,
+            error: None,
+            duration_seconds: duration.as_secs_f64(),
+        }
+    }
+
+    /// Add the revisions of an update.
+    pub fn with_update(mut self, summary: &UpdateSummary) -> Self {
+        self.old_revision = Some(summary.old_revision.to_string());
+        self.new_revision = Some(summary.new_revision.to_string());
+        self
+    }
+
+    pub fn with_pull_request(mut self, pull_request: Option<String>) -> Self {
+        self.pull_request = pull_request;
+        self
+    }
+
+    pub fn with_error(mut self, error: &anyhow::Error) -> Self {
+        self.error = Some(format!("{error:#}"));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::git::Revision;
+
+    #[test]
+    fn serialize_run_report() -> Result<()> {
+        let summary = UpdateSummary::new(
+            Revision::new("2d068ae5c6516b2d04562de50a58c682540de9bf"),
+            Revision::new("292fa7d4f6519c074f0a50394dbbe69859bb6043"),
+        );
+        let report = RunReport {
+            started_at: 1_700_000_000,
+            duration_seconds: 2.5,
+            issue: None,
+            error: None,
+            sources: vec![
+                SourceReport::new("frozen", SourceOutcome::Frozen, Duration::ZERO),
+                SourceReport::new("nixpkgs", SourceOutcome::Updated, Duration::from_secs(2))
+                    .with_update(&summary)
+                    .with_pull_request(Some("https://example.com/pulls/1".into())),
+            ],
+        };
+
+        assert_eq!(
+            serde_json::to_value(&report)?,
+            serde_json::json!({
+                "startedAt": 1_700_000_000,
+                "durationSeconds": 2.5,
+                "sources": [
+                    {
+                        "name": "frozen",
+                        "outcome": "frozen",
+                        "durationSeconds": 0.0,
+                    },
+                    {
+                        "name": "nixpkgs",
+                        "outcome": "updated",
+                        "oldRevision": "2d068ae5c6516b2d04562de50a58c682540de9bf",
+                        "newRevision": "292fa7d4f6519c074f0a50394dbbe69859bb6043",
+                        "pullRequest": "https://example.com/pulls/1",
+                        "durationSeconds": 2.0,
+                    },
+                ],
+            })
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{ErrorKind, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::sources::Sources;
+
+/// What the bot remembers between runs.
+///
+/// The update branches only contain the lock of the sources that were updated, so whatever the
+/// bot learns from a check without an update would otherwise be lost.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BotState {
+    /// Unix timestamp of the last successful update check of each source with an update interval
+    #[serde(default)]
+    pub last_checked: BTreeMap<String, u64>,
+}
+
+impl BotState {
+    /// Read the state from a file.
+    ///
+    /// A missing file is the state of the first run.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", path.display()));
+            }
+        };
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to deserialize the bot state {}", path.display()))
+    }
+
+    /// Write the state as JSON, creating the parent directories if necessary.
+    pub fn to_file(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let mut file =
+            File::create(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        serde_json::to_writer_pretty(&mut file, self)
+            .context("Failed to serialize the bot state")?;
+        file.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Apply the remembered checks to the sources so that their schedule takes them into account.
+    ///
+    /// A check recorded in the lock file itself (e.g. by `lon update`) is kept if it is newer.
+    pub fn apply(&self, sources: &mut Sources) {
+        for (name, last_checked) in &self.last_checked {
+            if let Some(source) = sources.get_mut(name) {
+                let meta = source.meta_mut();
+                meta.last_checked = meta.last_checked.max(Some(*last_checked));
+            }
+        }
+    }
+
+    /// Remember a successful update check of a source if it has an update interval.
+    ///
+    /// Checks of sources without an interval don't matter for their schedule.
+    pub fn record_check(&mut self, sources: &Sources, name: &str, now: u64) {
+        if sources
+            .get(name)
+            .is_some_and(|source| source.meta().update_interval.is_some())
+        {
+            self.last_checked.insert(name.into(), now);
+        }
+    }
+
+    /// Forget the sources that no longer exist.
+    pub fn retain(&mut self, sources: &Sources) {
+        self.last_checked.retain(|name, _| sources.contains(name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_state() -> Result<()> {
+        let tmpdir = tempfile::tempdir()?;
+        let path = tmpdir.path().join("lon/state.json");
+        assert!(BotState::from_file(&path)?.last_checked.is_empty());
+
+        let mut state = BotState::default();
+        state.last_checked.insert("nixpkgs".into(), 1_722_329_086);
+        state.to_file(&path)?;
+        assert_eq!(BotState::from_file(&path)?.last_checked, state.last_checked);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+//! Information about the build of Lon.
+
+use std::fmt;
+
+use serde::Serialize;
+
+/// The version of Lon together with how it was built.
+///
+/// This is printed by `lon version --json` for scripts, so fields are only ever added to it.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    /// The git revision Lon was built from, if known
+    pub git_sha: Option<&'static str>,
+    /// The version of the compiler Lon was built with, if known
+    pub rustc: Option<&'static str>,
+}
+
+impl BuildInfo {
+    /// Return the version of the running binary.
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: option_env!("LON_GIT_SHA"),
+            rustc: option_env!("LON_RUSTC_VERSION"),
+        }
+    }
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "lon {}", self.version)?;
+        if let Some(git_sha) = self.git_sha {
+            write!(f, " ({})", git_sha.get(..7).unwrap_or(git_sha))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::Result;
+
+    #[test]
+    fn serialize_version() -> Result<()> {
+        let build_info = BuildInfo {
+            version: "0.8.0",
+            git_sha: Some("292fa7d4f6519c074f0a50394dbbe69859bb6043"),
+            rustc: None,
+        };
+
+        assert_eq!(build_info.to_string(), "lon 0.8.0 (292fa7d)");
+        assert_eq!(
+            serde_json::to_value(&build_info)?,
+            serde_json::json!({
+                "version": "0.8.0",
+                "git_sha": "292fa7d4f6519c074f0a50394dbbe69859bb6043",
+                "rustc": null,
+            })
+        );
+
+        Ok(())
+    }
+}
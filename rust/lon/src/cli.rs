@@ -1,20 +1,40 @@
 use std::{
-    env,
+    env, fmt,
+    fs::{self, File},
+    io,
     path::{Path, PathBuf},
     process::ExitCode,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
 };
 
-use anyhow::{Context, Result, bail};
+use anyhow::{Context, Result, anyhow, bail};
 use clap::{Args, Parser, Subcommand, ValueEnum};
+use dialoguer::{Confirm, Input, Select, console::Term};
+use nix_compat::nixhash::{HashAlgo, NixHash};
+use tempfile::TempDir;
 
 use crate::{
-    bot::{Forge, Forgejo, GitHub, GitLab},
+    audit::{self, Finding},
+    bot::{BotState, Forge, Forgejo, GitHub, GitLab, RunReport, SourceOutcome, SourceReport},
+    build_info::BuildInfo,
     commit_message::CommitMessage,
-    git,
+    config::{self, Config},
+    error::LonError,
+    export::{self, Exportable},
+    git::{self, CommitFilter, RevList, Transport},
     init::{Convertible, niv},
-    lock::Lock,
-    lon_nix::LonNix,
-    sources::{GitHubSource, GitSource, Source, Sources},
+    lon_nix::{LonNix, NixStyle},
+    manifest::{Entry, GitEntry, GitHubEntry, Manifest},
+    nix::GitFetchOptions,
+    report,
+    sources::{
+        self, ArchiveSource, BitbucketSource, ChannelSource, FlakeSource, ForgejoSource,
+        GitHubReleaseSource, GitHubSource, GitSource, PathSource, Source, SourceKind, Sources,
+        UpdateSummary, UrlSource,
+    },
+    time,
 };
 
 /// The default log level.
@@ -22,18 +42,47 @@ use crate::{
 /// 2 corresponds to the level INFO.
 const DEFAULT_LOG_LEVEL: usize = 2;
 
+/// The exit code when a command succeeded but there was nothing to do.
+///
+/// 1 is used for errors and 2 for invalid usage.
+const EXIT_NOTHING_TO_DO: u8 = 3;
+
 #[derive(Parser)]
 #[command(version)]
 pub struct Cli {
-    /// Silence all output
+    /// Silence all output except for the results
     #[arg(short, long)]
     quiet: bool,
     /// Verbose mode (-v, -vv, etc.)
     #[arg(short, long, action = clap::ArgAction::Count)]
     verbose: u8,
     /// The directory containing lon.{nix,lock}
+    ///
+    /// For `lon bot`, this can also be the URL of a git repository that contains lon.{nix,lock}
+    /// at its root. It is then cloned to a temporary directory.
     #[arg(short, long)]
     directory: Option<PathBuf>,
+    /// Fail instead of accessing the network
+    #[arg(long)]
+    offline: bool,
+    /// How to calculate the hash of git sources
+    #[arg(long, value_enum, default_value_t)]
+    hasher: Hasher,
+    /// Fetch git repositories over this transport regardless of their URL
+    ///
+    /// SSH URLs (e.g. `git@github.com:nixos/nixpkgs.git`) are fetched over HTTPS or vice versa.
+    /// The URLs in the lock file are not changed. Can also be set with `LON_GIT_TRANSPORT`.
+    #[arg(long, value_enum)]
+    git_transport: Option<Transport>,
+    /// Print results in a stable line-oriented format for scripts
+    #[arg(long)]
+    porcelain: bool,
+    /// When to color the output
+    ///
+    /// If you do not supply this, `NO_COLOR`, `CLICOLOR` and `CLICOLOR_FORCE` are honored.
+    /// Otherwise, the output is colored when stderr is a terminal.
+    #[arg(long, value_enum)]
+    color: Option<Color>,
     #[clap(subcommand)]
     commands: Commands,
 }
@@ -43,10 +92,7 @@ enum Commands {
     /// Initialize lon.{nix,lock}
     Init(InitArgs),
     /// Add a new source
-    Add {
-        #[clap(subcommand)]
-        commands: AddCommands,
-    },
+    Add(AddArgs),
     /// Update an existing source to the newest revision
     Update(UpdateArgs),
     /// Modify an existing source
@@ -54,36 +100,184 @@ enum Commands {
     /// When you only change the branch, the newest revision from that branch is locked.
     ///
     /// When you change the revision, the source is locked to this revision.
+    ///
+    /// When you supply a reference, the source is locked to the revision it points to while the
+    /// tracked branch stays the same. The next update returns to the newest revision of the
+    /// branch.
     Modify(ModifyArgs),
+    /// Lock a revision of an existing source together with its hash without fetching it
+    ///
+    /// Use this when the hash was computed elsewhere, e.g. where Lon can't prefetch sources.
+    Set(SetArgs),
     /// Remove an existing source
-    Remove(SourceArgs),
+    Remove(RemoveArgs),
     /// Freeze an existing source
     Freeze(SourceArgs),
     /// Unfreeze an existing source
     Unfreeze(SourceArgs),
+    /// Only update an existing source when it is named explicitly
+    ///
+    /// `lon update` without a name and the bot skip the source.
+    SetManual(SourceArgs),
+    /// Update an existing source together with all others again
+    UnsetManual(SourceArgs),
+    /// Report sources that fetch exactly the same
+    Dedup(DedupArgs),
+    /// List all sources and when their locked revision was last updated
+    List(ListArgs),
+    /// Show what an existing source is locked at
+    Show(SourceArgs),
+    /// Report risks to the reproducibility and freshness of the sources
+    Audit(AuditArgs),
+    /// Check that lon.lock is valid and lon.nix is up to date
+    Check,
+    /// Export the sources to the lock file of another tool
+    Export(ExportArgs),
+    /// Show what an update of a source would change without performing it
+    Diff(DiffArgs),
+    /// Print the commit message of an update of a source without performing it
+    CommitMessage(CommitMessageArgs),
+    /// Print the version of Lon and how it was built
+    Version(VersionArgs),
 
     /// Bot that opens PRs for updates
-    Bot {
-        #[clap(subcommand)]
-        commands: BotCommands,
-    },
+    Bot(BotArgs),
 }
 
 #[derive(Args)]
 struct InitArgs {
     /// The type of lock file to initalize from
+    ///
+    /// If you do not supply this, it is detected from the contents of the lock file.
     #[arg(long, value_enum)]
     from: Option<LockFileType>,
     /// Path to the lock file to initialize from
     #[arg(long)]
     source: Option<PathBuf>,
+    /// Only create lon.lock, not lon.nix
+    ///
+    /// For when you bring your own Nix code to read the lock. Other commands don't create lon.nix
+    /// either as long as it doesn't exist.
+    #[arg(long, visible_alias = "no-nix")]
+    bare: bool,
+    /// The form of the expression lon.nix evaluates to
+    ///
+    /// It is stored as nix-style in lon.toml so that other commands keep generating lon.nix in
+    /// this style. [default: nix-style in lon.toml or attrset]
+    #[arg(long, value_enum, conflicts_with = "bare")]
+    nix_style: Option<NixStyle>,
 }
 
-#[derive(Clone, ValueEnum)]
+#[derive(Clone, Debug, PartialEq, Eq, ValueEnum)]
 enum LockFileType {
     Niv,
 }
 
+impl LockFileType {
+    /// Detect the type of a lock file from its structure.
+    fn detect(contents: &str) -> Result<Self> {
+        let supported = Self::value_variants()
+            .iter()
+            .filter_map(|t| Some(t.to_possible_value()?.get_name().to_string()))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let value = serde_json::from_str::<serde_json::Value>(contents).with_context(|| {
+            format!("Failed to detect the lock file type. Supported types are: {supported}")
+        })?;
+        let Some(object) = value.as_object() else {
+            bail!("Failed to detect the lock file type. Supported types are: {supported}");
+        };
+
+        if object.contains_key("nodes") && object.contains_key("root") {
+            bail!(
+                "Initializing from a flake.lock is not supported. Supported types are: {supported}"
+            );
+        }
+        if object.contains_key("pins") {
+            bail!("Initializing from npins is not supported. Supported types are: {supported}");
+        }
+        // Niv maps the name of each source to its attributes.
+        if object.values().all(serde_json::Value::is_object) {
+            return Ok(Self::Niv);
+        }
+        bail!("Failed to detect the lock file type. Supported types are: {supported}")
+    }
+}
+
+#[derive(Clone, Default, ValueEnum)]
+enum Hasher {
+    /// Use nix-prefetch-git
+    #[default]
+    Nix,
+    /// Check out the source with git and serialize it to a NAR without Nix
+    Builtin,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Color {
+    /// Color the output when it is written to a terminal
+    Auto,
+    Always,
+    Never,
+}
+
+impl Color {
+    /// Choose whether to color the output.
+    ///
+    /// An explicit choice takes precedence over the conventional environment variables
+    /// (<https://no-color.org> and <https://bixense.com/clicolors>).
+    fn choice(color: Option<Self>, env: impl Fn(&str) -> Option<String>) -> stderrlog::ColorChoice {
+        let enabled = |key| env(key).is_some_and(|value| !value.is_empty() && value != "0");
+        let color = color.unwrap_or_else(|| {
+            if env("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+                Self::Never
+            } else if enabled("CLICOLOR_FORCE") {
+                Self::Always
+            } else if env("CLICOLOR").as_deref() == Some("0") {
+                Self::Never
+            } else {
+                Self::Auto
+            }
+        });
+        match color {
+            // Only colors the output if stderr is a terminal.
+            Self::Auto => stderrlog::ColorChoice::Auto,
+            Self::Always => stderrlog::ColorChoice::Always,
+            Self::Never => stderrlog::ColorChoice::Never,
+        }
+    }
+}
+
+#[derive(Clone, Default, ValueEnum)]
+enum HashAlgoArg {
+    #[default]
+    Sha256,
+    Sha512,
+}
+
+impl From<&HashAlgoArg> for HashAlgo {
+    fn from(value: &HashAlgoArg) -> Self {
+        match value {
+            HashAlgoArg::Sha256 => Self::Sha256,
+            HashAlgoArg::Sha512 => Self::Sha512,
+        }
+    }
+}
+
+#[derive(Args)]
+#[command(args_conflicts_with_subcommands = true, arg_required_else_help = true)]
+struct AddArgs {
+    #[clap(subcommand)]
+    commands: Option<AddCommands>,
+    /// Add all sources listed in a manifest file (TOML)
+    #[arg(long)]
+    from: Option<PathBuf>,
+    /// Prompt for the source type and all values not supplied on the command line
+    #[arg(short, long, global = true)]
+    interactive: bool,
+}
+
 #[derive(Subcommand)]
 #[clap(rename_all = "lower")]
 enum AddCommands {
@@ -96,33 +290,261 @@ enum AddCommands {
     /// It's fetched as a tarball which is more efficient than checking out the
     /// repository.
     GitHub(AddGitHubArgs),
+    /// Add a bitbucket source
+    ///
+    /// It's fetched as a tarball which is more efficient than checking out the
+    /// repository.
+    Bitbucket(AddBitbucketArgs),
+    /// Add a forgejo or gitea source (e.g. from Codeberg)
+    ///
+    /// It's fetched as a tarball which is more efficient than checking out the
+    /// repository.
+    Forgejo(AddForgejoArgs),
+    /// Add an asset of a github release
+    ///
+    /// The asset is fetched as a single file unless --unpack is supplied. Updates follow the
+    /// newest release.
+    #[command(name = "github-release")]
+    GitHubRelease(AddGitHubReleaseArgs),
+    /// Add a git repository hosted on any forge that serves archives of revisions
+    ///
+    /// It's fetched as a tarball from the archive URL. The branch is still resolved with git.
+    Archive(AddArchiveArgs),
+    /// Add a nix channel, e.g. nixos-24.05
+    ///
+    /// The current release of the channel is locked. Updates follow the channel to its newest
+    /// release.
+    Channel(AddChannelArgs),
+    /// Add anything nix can fetch via a flake reference (e.g. git+https://... or tarball:...)
+    ///
+    /// It's locked with `nix flake prefetch`. Updates resolve the flake reference again.
+    Flake(AddFlakeArgs),
+    /// Add a file or archive from any URL
+    ///
+    /// The file is fetched as it is unless --unpack is supplied. Updates don't change the source.
+    /// Supply --url to lon modify to lock another URL.
+    Url(AddUrlArgs),
+    /// Add a local directory, e.g. a checkout of a fork during development
+    ///
+    /// The directory is used as it is instead of being fetched.
+    Path(AddPathArgs),
 }
 
-#[derive(Args)]
+#[derive(Args, Default)]
 struct AddGitArgs {
-    /// Name of the source
-    name: String,
     /// URL to the repository
+    ///
+    /// For backwards compatibility, the name of the source followed by the URL is accepted as
+    /// well.
+    #[arg(
+        default_value = "",
+        hide_default_value = true,
+        required_unless_present = "interactive"
+    )]
     url: String,
     /// Branch to track
-    branch: String,
+    ///
+    /// A glob pattern (e.g. release/*) tracks the highest matching branch.
+    ///
+    /// If you do not supply this, default-branch from lon.toml is used. Otherwise, the default
+    /// branch of the repository is detected.
+    branch: Option<String>,
+    /// Branch to track when the name is supplied before the URL
+    #[arg(hide = true)]
+    legacy_branch: Option<String>,
+    /// Name of the source
+    ///
+    /// If you do not supply this, the last path component of the URL without .git is used as the
+    /// source name.
+    #[arg(short, long)]
+    name: Option<String>,
     /// Revision to lock
     #[arg(short, long)]
     revision: Option<String>,
+    #[command(flatten)]
+    fetch_options: GitFetchArgs,
+    /// Freeze the source
+    #[arg(long, default_value_t = false)]
+    frozen: bool,
+    /// Subdirectory of the repository to expose as the source
+    #[arg(long)]
+    path: Option<String>,
+    #[command(flatten)]
+    bot: AddBotArgs,
+}
+
+impl AddGitArgs {
+    /// Accept the old argument order `<name> <url> [branch]`.
+    ///
+    /// The first argument is taken as URL if it looks like one, i.e. it contains a `/` or `:`.
+    fn normalize(mut self) -> Result<Self> {
+        // With --interactive, the URL may be left to be prompted for.
+        if self.url.is_empty() || looks_like_url(&self.url) {
+            if let Some(argument) = self.legacy_branch {
+                bail!("Unexpected argument {argument}");
+            }
+            return Ok(self);
+        }
+
+        if self.name.is_some() {
+            bail!("Supply the name either via --name or before the URL, not both");
+        }
+        let Some(url) = self.branch.take() else {
+            bail!("{} is not a URL to a repository", self.url);
+        };
+        self.name = Some(std::mem::replace(&mut self.url, url));
+        self.branch = self.legacy_branch.take();
+        Ok(self)
+    }
+
+    /// Return the supplied name or infer it from the URL.
+    fn name(&self) -> Result<String> {
+        self.name
+            .clone()
+            .or_else(|| name_from_url(&self.url))
+            .with_context(|| format!("Failed to infer a name from {}. Supply --name", self.url))
+    }
+}
+
+/// Return whether an argument is a URL (including scp-like syntax and paths) instead of a name.
+fn looks_like_url(argument: &str) -> bool {
+    argument.contains(['/', ':'])
+}
+
+/// Infer the name of a source from the last path component of its URL without `.git`.
+fn name_from_url(url: &str) -> Option<String> {
+    url.trim_end_matches('/')
+        .trim_end_matches(".git")
+        .rsplit(['/', ':'])
+        .next()
+        .filter(|name| !name.is_empty())
+        .map(ToString::to_string)
+}
+
+#[derive(Args, Default)]
+struct GitFetchArgs {
     /// Fetch submodules
     #[arg(long)]
     submodules: bool,
+    /// Only fetch the submodule at this path (not supported yet, see --submodules)
+    #[arg(long = "submodule", value_name = "PATH")]
+    submodule_paths: Vec<String>,
+    /// Keep the .git directory
+    #[arg(long)]
+    leave_dot_git: bool,
+    /// Fetch the whole history instead of only the revision
+    #[arg(long)]
+    deep_clone: bool,
+}
+
+/// How the bot treats a new source.
+#[derive(Args, Default)]
+struct AddBotArgs {
+    /// Minimum time between update checks by the bot (e.g. 12h, 1d, 1w)
+    #[arg(long, value_parser = time::parse_duration)]
+    update_interval: Option<u64>,
+    /// Label the bot adds to Pull Requests for this source (can be repeated)
+    #[arg(long = "label")]
+    labels: Vec<String>,
+}
+
+impl From<&GitFetchArgs> for GitFetchOptions {
+    fn from(value: &GitFetchArgs) -> Self {
+        Self {
+            submodules: value.submodules,
+            leave_dot_git: value.leave_dot_git,
+            deep_clone: value.deep_clone,
+        }
+    }
+}
+
+#[derive(Args, Default)]
+struct AddGitHubArgs {
+    /// An identifier made up of {owner}/{repo}, e.g. nixos/nixpkgs
+    #[arg(
+        default_value = "",
+        hide_default_value = true,
+        required_unless_present = "interactive"
+    )]
+    identifier: String,
+    /// Branch to track
+    ///
+    /// A glob pattern (e.g. release/*) tracks the highest matching branch.
+    ///
+    /// If you do not supply this, default-branch from lon.toml is used.
+    branch: Option<String>,
+    /// Name of the source
+    ///
+    /// If you do not supply this, the repository name is used as the source name.
+    #[arg(short, long)]
+    name: Option<String>,
+    /// Revision to lock
+    #[arg(short, long)]
+    revision: Option<String>,
+    /// Hash algorithm to lock the source with
+    #[arg(long, value_enum, default_value_t = HashAlgoArg::Sha256)]
+    hash_algo: HashAlgoArg,
     /// Freeze the source
     #[arg(long, default_value_t = false)]
     frozen: bool,
+    /// Subdirectory of the repository to expose as the source
+    #[arg(long)]
+    path: Option<String>,
+    #[command(flatten)]
+    bot: AddBotArgs,
 }
 
-#[derive(Args)]
-struct AddGitHubArgs {
-    /// An identifier made up of {owner}/{repo}, e.g. nixos/nixpkgs
+#[derive(Args, Default)]
+struct AddBitbucketArgs {
+    /// An identifier made up of {workspace}/{repo}, e.g. atlassian/python-bitbucket
+    #[arg(
+        default_value = "",
+        hide_default_value = true,
+        required_unless_present = "interactive"
+    )]
+    identifier: String,
+    /// Branch to track
+    ///
+    /// A glob pattern (e.g. release/*) tracks the highest matching branch.
+    ///
+    /// If you do not supply this, default-branch from lon.toml is used.
+    branch: Option<String>,
+    /// Name of the source
+    ///
+    /// If you do not supply this, the repository name is used as the source name.
+    #[arg(short, long)]
+    name: Option<String>,
+    /// Revision to lock
+    #[arg(short, long)]
+    revision: Option<String>,
+    /// Freeze the source
+    #[arg(long, default_value_t = false)]
+    frozen: bool,
+    /// Subdirectory of the repository to expose as the source
+    #[arg(long)]
+    path: Option<String>,
+    #[command(flatten)]
+    bot: AddBotArgs,
+}
+
+#[derive(Args, Default)]
+struct AddForgejoArgs {
+    /// An identifier made up of {owner}/{repo}, e.g. forgejo/forgejo
+    #[arg(
+        default_value = "",
+        hide_default_value = true,
+        required_unless_present = "interactive"
+    )]
     identifier: String,
     /// Branch to track
-    branch: String,
+    ///
+    /// A glob pattern (e.g. release/*) tracks the highest matching branch.
+    ///
+    /// If you do not supply this, default-branch from lon.toml is used.
+    branch: Option<String>,
+    /// Host of the Forgejo or Gitea instance
+    #[arg(long, default_value = sources::FORGEJO_HOST)]
+    host: String,
     /// Name of the source
     ///
     /// If you do not supply this, the repository name is used as the source name.
@@ -134,17 +556,252 @@ struct AddGitHubArgs {
     /// Freeze the source
     #[arg(long, default_value_t = false)]
     frozen: bool,
+    /// Subdirectory of the repository to expose as the source
+    #[arg(long)]
+    path: Option<String>,
+    #[command(flatten)]
+    bot: AddBotArgs,
+}
+
+#[derive(Args, Default)]
+struct AddArchiveArgs {
+    /// URL to the git repository, e.g. `https://codeberg.org/forgejo/forgejo.git`
+    #[arg(
+        default_value = "",
+        hide_default_value = true,
+        required_unless_present = "interactive"
+    )]
+    url: String,
+    /// Branch to track
+    ///
+    /// A glob pattern (e.g. release/*) tracks the highest matching branch.
+    ///
+    /// If you do not supply this, default-branch from lon.toml is used. Otherwise, the default
+    /// branch of the repository is detected.
+    branch: Option<String>,
+    /// URL of the archive of a revision
+    ///
+    /// Either a preset (github, forgejo, gitea, gitlab, sourcehut, cgit) or a template like
+    /// `https://{host}/{owner}/{repo}/archive/{rev}.tar.gz`. {host}, {owner}, {repo} and {path}
+    /// are taken from the URL of the repository.
+    #[arg(
+        long,
+        value_parser = sources::archive_url_template,
+        required_unless_present = "interactive"
+    )]
+    archive_url: Option<String>,
+    /// Name of the source
+    ///
+    /// If you do not supply this, the last path component of the URL without .git is used as the
+    /// source name.
+    #[arg(short, long)]
+    name: Option<String>,
+    /// Revision to lock
+    #[arg(short, long)]
+    revision: Option<String>,
+    /// Freeze the source
+    #[arg(long, default_value_t = false)]
+    frozen: bool,
+    /// Subdirectory of the repository to expose as the source
+    #[arg(long)]
+    path: Option<String>,
+    #[command(flatten)]
+    bot: AddBotArgs,
 }
 
 #[derive(Args)]
 struct UpdateArgs {
     /// Name of the source
     ///
-    /// If this is omitted, all sources are updated.
+    /// If this is omitted, all sources are updated except those set to manual.
     name: Option<String>,
+    #[command(flatten)]
+    git: CommitArgs,
+    #[command(flatten)]
+    filter: CommitFilterArgs,
+    /// Remove sources whose repository or branch no longer exists instead of failing
+    ///
+    /// Sources are only removed if the server says so. Network failures and authentication errors
+    /// still fail. A remote repository that can't be found may only be private, so removing its
+    /// source has to be confirmed on a terminal.
+    #[arg(long)]
+    prune: bool,
+    /// Write the updates of the other sources even if some sources fail to update
+    ///
+    /// By default, nothing is written or committed if any source fails.
+    #[arg(long)]
+    keep_going: bool,
+    /// Only report how far frozen sources are behind their branch without changing anything
+    #[arg(long, conflicts_with_all = ["commit", "prune"])]
+    only_frozen_check: bool,
+}
+
+#[derive(Args)]
+struct CommitArgs {
     /// Whether to commit lon.{nix,lock}.
     #[arg(short, long, default_value_t = false)]
     commit: bool,
+    /// Sign the commit
+    #[arg(long, requires = "commit")]
+    sign: bool,
+    /// Number of commits to list in the commit message for each updated source
+    #[arg(long, requires = "commit", default_value_t = 0)]
+    list_commits: usize,
+    /// Only write the subject line of the commit message
+    ///
+    /// For repositories that reject long commit messages. Can also be enabled with
+    /// `LON_NO_COMMIT_BODY=1`.
+    #[arg(long, requires = "commit")]
+    no_commit_body: bool,
+}
+
+#[derive(Args, Default)]
+struct AddGitHubReleaseArgs {
+    /// An identifier made up of {owner}/{repo}, e.g. nixos/nixpkgs
+    #[arg(
+        default_value = "",
+        hide_default_value = true,
+        required_unless_present = "interactive"
+    )]
+    identifier: String,
+    /// Glob pattern the name of the asset is matched against (e.g. 'foo-*.tar.gz')
+    ///
+    /// Use a wildcard for the version so that the pattern still matches after an update.
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        required_unless_present = "interactive"
+    )]
+    asset: String,
+    /// Unpack the asset instead of locking it as a single file
+    ///
+    /// Use this for archives (e.g. .tar.gz or .zip) that you want to use as a directory.
+    #[arg(long, default_value_t = false)]
+    unpack: bool,
+    /// Tag of the release to lock
+    ///
+    /// If you do not supply this, the newest release is locked.
+    #[arg(long)]
+    tag: Option<String>,
+    /// Name of the source
+    ///
+    /// If you do not supply this, the repository name is used as the source name.
+    #[arg(short, long)]
+    name: Option<String>,
+    /// Hash algorithm to lock the source with
+    #[arg(long, value_enum, default_value_t = HashAlgoArg::Sha256)]
+    hash_algo: HashAlgoArg,
+    /// Freeze the source
+    #[arg(long, default_value_t = false)]
+    frozen: bool,
+    #[command(flatten)]
+    bot: AddBotArgs,
+}
+
+#[derive(Args, Default)]
+struct AddChannelArgs {
+    /// Name of the source
+    #[arg(
+        default_value = "",
+        hide_default_value = true,
+        required_unless_present = "interactive"
+    )]
+    name: String,
+    /// Name of the channel (e.g. nixos-24.05 or nixpkgs-unstable)
+    #[arg(
+        default_value = "",
+        hide_default_value = true,
+        required_unless_present = "interactive"
+    )]
+    channel: String,
+    /// Freeze the source
+    #[arg(long, default_value_t = false)]
+    frozen: bool,
+    #[command(flatten)]
+    bot: AddBotArgs,
+}
+
+#[derive(Args, Default)]
+struct AddFlakeArgs {
+    /// Name of the source
+    #[arg(
+        default_value = "",
+        hide_default_value = true,
+        required_unless_present = "interactive"
+    )]
+    name: String,
+    /// Flake reference to lock (e.g. github:nixos/nixpkgs/nixos-unstable)
+    #[arg(
+        default_value = "",
+        hide_default_value = true,
+        required_unless_present = "interactive"
+    )]
+    flake_ref: String,
+    /// Freeze the source
+    #[arg(long, default_value_t = false)]
+    frozen: bool,
+    #[command(flatten)]
+    bot: AddBotArgs,
+}
+
+#[derive(Args, Default)]
+struct AddUrlArgs {
+    /// Name of the source
+    #[arg(
+        default_value = "",
+        hide_default_value = true,
+        required_unless_present = "interactive"
+    )]
+    name: String,
+    /// URL to the file
+    #[arg(
+        default_value = "",
+        hide_default_value = true,
+        required_unless_present = "interactive"
+    )]
+    url: String,
+    /// Unpack the file instead of locking it as a single file
+    ///
+    /// Use this for archives (e.g. .tar.gz or .zip) that you want to use as a directory.
+    #[arg(long, default_value_t = false)]
+    unpack: bool,
+    /// Hash algorithm to lock the source with
+    #[arg(long, value_enum, default_value_t = HashAlgoArg::Sha256)]
+    hash_algo: HashAlgoArg,
+    /// Freeze the source
+    #[arg(long, default_value_t = false)]
+    frozen: bool,
+    #[command(flatten)]
+    bot: AddBotArgs,
+}
+
+#[derive(Args, Default)]
+struct AddPathArgs {
+    /// Name of the source
+    #[arg(
+        default_value = "",
+        hide_default_value = true,
+        required_unless_present = "interactive"
+    )]
+    name: String,
+    /// Path to the directory relative to lon.nix
+    #[arg(
+        default_value = "",
+        hide_default_value = true,
+        required_unless_present = "interactive"
+    )]
+    path: String,
+    /// Lock the hash of the directory
+    ///
+    /// lon.nix then copies the directory to the store and fails if its content changed.
+    #[arg(long, default_value_t = false)]
+    hash: bool,
+    /// Freeze the source
+    #[arg(long, default_value_t = false)]
+    frozen: bool,
+    #[command(flatten)]
+    bot: AddBotArgs,
 }
 
 #[derive(Args)]
@@ -152,51 +809,308 @@ struct ModifyArgs {
     /// Name of the source
     name: String,
     /// Branch to track
+    ///
+    /// A glob pattern (e.g. release/*) tracks the highest matching branch.
     #[arg(short, long)]
     branch: Option<String>,
     /// Revision to lock
+    ///
+    /// Anything but a full commit hash (e.g. a tag name) is resolved to the revision it points
+    /// to like --ref.
     #[arg(short, long)]
     revision: Option<String>,
+    /// Reference to lock the revision of without changing the tracked branch (e.g.
+    /// refs/pull/123/head)
+    #[arg(long = "ref", conflicts_with = "revision")]
+    reference: Option<String>,
+    /// Lock the newest revision of the tracked branch again
+    ///
+    /// Resumes following the branch after a revision was locked via --revision or --ref.
+    #[arg(long, conflicts_with_all = ["revision", "reference"])]
+    unlock: bool,
+    /// URL to lock instead (only URL sources)
+    #[arg(long, conflicts_with_all = ["branch", "revision", "reference", "unlock"])]
+    url: Option<String>,
+    #[command(flatten)]
+    submodules: SubmoduleArgs,
+    /// Minimum time between update checks by the bot (e.g. 12h, 1d, 1w)
+    #[arg(long, value_parser = time::parse_duration)]
+    update_interval: Option<u64>,
+    /// Label the bot adds to Pull Requests for this source (can be repeated)
+    ///
+    /// Replaces the existing labels of the source.
+    #[arg(long = "label", conflicts_with = "no_labels")]
+    labels: Vec<String>,
+    /// Remove all labels of the source
+    #[arg(long)]
+    no_labels: bool,
 }
 
 #[derive(Args)]
-struct SourceArgs {
+struct SetArgs {
     /// Name of the source
     name: String,
+    /// Full commit hash of the revision to lock
+    #[arg(short, long)]
+    revision: String,
+    /// Hash of the source at the revision in the SRI format (e.g. sha256-...)
+    #[arg(long)]
+    hash: String,
 }
 
-#[derive(Subcommand)]
-#[clap(rename_all = "lower")]
-enum BotCommands {
-    /// Run the bot for GitLab
-    GitLab,
-    /// Run the bot for GitHub
-    GitHub,
-    /// Run the bot for Forgejo
-    Forgejo,
+#[derive(Args)]
+struct SubmoduleArgs {
+    /// Fetch submodules (only git sources)
+    #[arg(long, conflicts_with = "no_submodules")]
+    submodules: bool,
+    /// Don't fetch submodules (only git sources)
+    #[arg(long)]
+    no_submodules: bool,
+    /// Only fetch the submodule at this path (not supported yet, see --submodules)
+    #[arg(
+        long = "submodule",
+        value_name = "PATH",
+        conflicts_with = "no_submodules"
+    )]
+    submodule_paths: Vec<String>,
 }
 
-impl Cli {
-    pub fn init(module: &str) -> ExitCode {
-        let cli = Self::parse();
-
-        let _ = stderrlog::new()
-            .module(module)
-            .show_level(false)
-            .quiet(cli.quiet)
-            .verbosity(DEFAULT_LOG_LEVEL + usize::from(cli.verbose))
-            .init();
+/// Fail if only some of the submodules of a source should be fetched.
+///
+/// Both `builtins.fetchGit` and nix-prefetch-git fetch either all submodules or none, so the
+/// locked hash wouldn't match what lon.nix fetches.
+fn ensure_all_submodules(paths: &[String]) -> Result<()> {
+    if !paths.is_empty() {
+        bail!(
+            "Fetching only some submodules ({}) is not supported because the Nix fetchers always fetch all of them. Supply --submodules to fetch all submodules instead",
+            paths.join(", ")
+        );
+    }
+    Ok(())
+}
 
-        let directory = match cli.directory {
-            Some(directory) => directory,
+#[derive(Args)]
+struct SourceArgs {
+    /// Name of the source
+    name: String,
+}
+
+#[derive(Args)]
+struct RemoveArgs {
+    /// Name of the source
+    #[arg(required_unless_present = "all")]
+    name: Option<String>,
+    /// Remove all sources
+    #[arg(long, conflicts_with = "name")]
+    all: bool,
+    /// Don't ask for confirmation before removing all sources
+    #[arg(short, long, requires = "all")]
+    yes: bool,
+}
+
+#[derive(Args)]
+struct DedupArgs {
+    /// Remove duplicates, keeping the first source of each group (sorted by name)
+    #[arg(long)]
+    remove: bool,
+}
+
+#[derive(Args)]
+struct DiffArgs {
+    /// Name of the source
+    name: String,
+    /// Number of commits to list between the locked and the newest revision
+    #[arg(long, default_value_t = 20)]
+    commits: usize,
+    /// Only list commits touching this path. Can be passed multiple times [default: the path of
+    /// the source]
+    #[arg(long = "path")]
+    paths: Vec<String>,
+    #[command(flatten)]
+    filter: CommitFilterArgs,
+}
+
+#[derive(Args)]
+struct CommitMessageArgs {
+    /// Name of the source
+    name: String,
+    /// Number of commits to list between the locked and the newest revision [default:
+    /// list-commits of the bot in lon.toml or 0]
+    #[arg(long)]
+    commits: Option<usize>,
+    #[command(flatten)]
+    filter: CommitFilterArgs,
+}
+
+/// Options for which commits of an update are listed.
+#[derive(Args)]
+struct CommitFilterArgs {
+    /// Don't list merge commits
+    #[arg(long, global = true, conflicts_with = "first_parent")]
+    no_merges: bool,
+    /// Only list the commits on the first-parent history, skipping the commits of merged branches
+    #[arg(long, global = true)]
+    first_parent: bool,
+}
+
+impl CommitFilterArgs {
+    fn filter(&self) -> CommitFilter {
+        if self.first_parent {
+            CommitFilter::FirstParent
+        } else if self.no_merges {
+            CommitFilter::NoMerges
+        } else {
+            CommitFilter::All
+        }
+    }
+
+    /// Like `filter`, but falling back to `LON_FIRST_PARENT` and `LON_NO_MERGES`.
+    fn bot_filter(&self) -> CommitFilter {
+        match self.filter() {
+            CommitFilter::All if config::flag_env("LON_FIRST_PARENT") => CommitFilter::FirstParent,
+            CommitFilter::All if config::flag_env("LON_NO_MERGES") => CommitFilter::NoMerges,
+            filter => filter,
+        }
+    }
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    /// The type of lock file to export to
+    #[arg(long, value_enum)]
+    format: LockFileType,
+    /// Path to write the lock file to instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct AuditArgs {
+    /// Report sources tracking master or main whose locked revision is older than this
+    #[arg(long, value_parser = time::parse_duration, default_value = "90d")]
+    max_age: u64,
+}
+
+#[derive(Args)]
+struct VersionArgs {
+    /// Print the version, git revision and compiler as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args)]
+struct ListArgs {
+    /// Only list sources whose locked revision is older than this (e.g. 30d, 12w)
+    ///
+    /// Sources for which this is unknown are always listed.
+    #[arg(long, value_parser = time::parse_duration)]
+    stale: Option<u64>,
+    /// Only list sources of this type
+    #[arg(long = "type", value_enum)]
+    kind: Option<SourceKind>,
+}
+
+#[derive(Args)]
+struct BotArgs {
+    #[clap(subcommand)]
+    commands: BotCommands,
+    /// Check all sources regardless of their update interval
+    #[arg(long, global = true)]
+    ignore_schedule: bool,
+    /// Number of sources to update concurrently [default: 1]
+    #[arg(short, long, global = true)]
+    jobs: Option<usize>,
+    /// Open a single issue listing all available updates instead of a PR per source
+    ///
+    /// No branches are created or pushed. An open issue of a previous run is updated instead of
+    /// opening a new one. Can also be enabled with `LON_REPORT_ONLY=1`.
+    #[arg(long, global = true)]
+    report: bool,
+    /// Write a JSON report of the run to this file
+    ///
+    /// It lists the outcome of each source together with the old and new revision, the URL of
+    /// the PR and how long it took.
+    #[arg(long, global = true)]
+    report_file: Option<PathBuf>,
+    /// File the bot remembers its update checks in between runs
+    ///
+    /// Update intervals rely on it. Keep it between runs (e.g. in the cache of your CI). Can also
+    /// be set with `LON_STATE_FILE`. [default: lon/state.json in the git directory]
+    #[arg(long, global = true)]
+    state_file: Option<PathBuf>,
+    /// Print the PRs that would be opened instead of opening them
+    ///
+    /// Updates are detected and their PRs are described as usual, but nothing is committed,
+    /// pushed or opened. With --report, the issue is printed instead.
+    #[arg(long, global = true)]
+    dry_run: bool,
+    #[command(flatten)]
+    branches: BotBranchArgs,
+    /// Can also be selected with `LON_NO_MERGES=1` or `LON_FIRST_PARENT=1`.
+    #[command(flatten)]
+    filter: CommitFilterArgs,
+}
+
+/// Options for the branches the bot pushes. They have no effect with `--report`.
+#[derive(Args)]
+struct BotBranchArgs {
+    /// Sign the commits
+    #[arg(long, global = true)]
+    sign: bool,
+    /// Run even if the working tree has uncommitted changes
+    #[arg(long, global = true)]
+    allow_dirty: bool,
+    /// Overwrite update branches even if they were changed on the remote during the run
+    ///
+    /// By default, branches are pushed with --force-with-lease.
+    #[arg(long, global = true)]
+    force: bool,
+    /// Branch the PRs are opened against [default: the default branch of the repository]
+    ///
+    /// The update branches are based on the current checkout, so check out this branch before
+    /// running the bot. Can also be set with `LON_BASE_BRANCH`.
+    #[arg(long, global = true)]
+    base: Option<String>,
+}
+
+#[derive(Subcommand)]
+#[clap(rename_all = "lower")]
+enum BotCommands {
+    /// Run the bot for GitLab
+    GitLab,
+    /// Run the bot for GitHub
+    GitHub,
+    /// Run the bot for Forgejo
+    Forgejo,
+}
+
+impl Cli {
+    pub fn init(module: &str) -> ExitCode {
+        let cli = Self::parse();
+
+        let _ = stderrlog::new()
+            .module(module)
+            .show_level(false)
+            .quiet(cli.quiet)
+            .color(Color::choice(cli.color, |key| std::env::var(key).ok()))
+            .verbosity(DEFAULT_LOG_LEVEL + usize::from(cli.verbose))
+            .init();
+
+        config::set_offline(cli.offline);
+        config::set_builtin_hash(matches!(cli.hasher, Hasher::Builtin));
+        config::set_git_transport(cli.git_transport);
+        report::set_porcelain(cli.porcelain);
+
+        let directory = match cli.directory {
+            Some(directory) => directory,
             None => match std::env::var("LON_DIRECTORY") {
                 Ok(dir) => PathBuf::from(dir),
-                Err(_) => std::env::current_dir().unwrap_or_default(),
+                Err(_) => default_directory(matches!(cli.commands, Commands::Init(_))),
             },
         };
 
-        match cli.commands.call(directory) {
-            Ok(()) => ExitCode::SUCCESS,
+        match cli.commands.call(directory, cli.quiet) {
+            Ok(outcome) => outcome.into(),
             Err(err) => {
                 // When at least one -v is added, the source of the error is also printed.
                 if DEFAULT_LOG_LEVEL + usize::from(cli.verbose) >= 3 {
@@ -210,340 +1124,2385 @@ impl Cli {
     }
 }
 
-impl Commands {
-    pub fn call(self, directory: impl AsRef<Path>) -> Result<()> {
-        match self {
-            Self::Init(args) => init(directory, &args),
-            Self::Add { commands } => match commands {
-                AddCommands::Git(args) => add_git(directory, &args),
-                AddCommands::GitHub(args) => add_github(directory, &args),
-            },
-            Self::Update(args) => update(directory, &args),
-            Self::Modify(args) => modify(directory, &args),
-            Self::Remove(args) => remove(directory, &args),
-            Self::Freeze(args) => freeze(directory, &args),
-            Self::Unfreeze(args) => unfreeze(directory, &args),
-
-            Self::Bot { commands } => match commands {
-                BotCommands::GitLab => bot(directory, &GitLab::from_env()?),
-                BotCommands::GitHub => bot(directory, &GitHub::from_env()?),
-                BotCommands::Forgejo => bot(directory, &Forgejo::from_env()?),
-            },
-        }
+/// Determine the directory if neither `--directory` nor `LON_DIRECTORY` is set.
+///
+/// Lon is initialized in the current directory. All other commands use the nearest directory
+/// upwards that already contains Lon.
+fn default_directory(init: bool) -> PathBuf {
+    let current_dir = std::env::current_dir().unwrap_or_default();
+    if init {
+        current_dir
+    } else {
+        Config::find_directory(&current_dir)
+    }
+}
+
+/// The outcome of a command that succeeded.
+pub enum Outcome {
+    /// The command did what it was asked to do.
+    Done,
+    /// The command succeeded but there was nothing to do (e.g. no updates were available).
+    NothingToDo,
+}
+
+impl From<Outcome> for ExitCode {
+    fn from(value: Outcome) -> Self {
+        match value {
+            Outcome::Done => ExitCode::SUCCESS,
+            Outcome::NothingToDo => ExitCode::from(EXIT_NOTHING_TO_DO),
+        }
+    }
+}
+
+/// Totals of updating multiple sources.
+#[derive(Default)]
+struct UpdateTotals {
+    updated: usize,
+    unchanged: usize,
+    frozen: usize,
+    /// Sources that weren't due for an update check or are only updated manually
+    skipped: usize,
+    /// Sources that were removed because they no longer exist upstream
+    pruned: usize,
+    /// Names of the sources that failed to update
+    failed: Vec<String>,
+}
+
+impl UpdateTotals {
+    /// Add the totals of another run.
+    fn merge(&mut self, other: Self) {
+        self.updated += other.updated;
+        self.unchanged += other.unchanged;
+        self.frozen += other.frozen;
+        self.skipped += other.skipped;
+        self.pruned += other.pruned;
+        self.failed.extend(other.failed);
+    }
+
+    /// Print the totals.
+    ///
+    /// In the porcelain format, only the failed sources are printed because the others are
+    /// already reported individually.
+    fn report(&self, quiet: bool) {
+        if report::porcelain() {
+            for name in &self.failed {
+                report::failed(name);
+            }
+        } else if !quiet {
+            println!("{self}");
+        }
+    }
+
+    /// Fail if any source failed to update.
+    fn ensure_success(&self) -> Result<()> {
+        if !self.failed.is_empty() {
+            bail!("Failed to update {}", self.failed.join(", "));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for UpdateTotals {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Updated {}, unchanged {}, frozen {}",
+            self.updated, self.unchanged, self.frozen
+        )?;
+        if self.skipped > 0 {
+            write!(f, ", skipped {}", self.skipped)?;
+        }
+        if self.pruned > 0 {
+            write!(f, ", pruned {}", self.pruned)?;
+        }
+        write!(f, ", failed {}", self.failed.len())
+    }
+}
+
+impl Commands {
+    /// Clone the repository if the directory is the URL of a git repository.
+    ///
+    /// Only the bot can run against a clone because it pushes its changes. Changes of all other
+    /// commands would be lost with the clone. Returns the temporary directory that must be kept
+    /// alive together with the path to the clone.
+    fn clone_remote_directory(&self, directory: &Path) -> Result<Option<(TempDir, PathBuf)>> {
+        let Some(url) = directory.to_str().filter(|url| git::is_remote_url(url)) else {
+            return Ok(None);
+        };
+        if !matches!(self, Self::Bot(_)) {
+            bail!("The directory can only be the URL of a git repository for lon bot");
+        }
+
+        let tmp_dir = TempDir::new()?;
+        let repository = tmp_dir.path().join("repository");
+        log::info!("Cloning {url}...");
+        git::clone(url, &repository)?;
+
+        Ok(Some((tmp_dir, repository)))
+    }
+
+    /// Run the command.
+    ///
+    /// Summaries of runs that update many sources are not printed when `quiet` is set.
+    pub fn call(self, directory: impl AsRef<Path>, quiet: bool) -> Result<Outcome> {
+        // The version doesn't depend on the directory, so it can be printed anywhere.
+        if let Self::Version(args) = &self {
+            return version(args).map(|()| Outcome::Done);
+        }
+
+        // Keep the clone of a remote repository until the command has finished.
+        let clone = self.clone_remote_directory(directory.as_ref())?;
+        let directory = match &clone {
+            Some((_, repository)) => repository.as_path(),
+            None => directory.as_ref(),
+        };
+
+        let config = Config::read(directory)?;
+        config::set_nar_hash(config.nar_hash);
+        config::set_mirrors(config.mirrors(directory));
+        if config::git_transport().is_none()
+            && let Ok(transport) = std::env::var("LON_GIT_TRANSPORT")
+        {
+            config::set_git_transport(Some(
+                Transport::parse(&transport).context("Failed to parse LON_GIT_TRANSPORT")?,
+            ));
+        }
+
+        let result = match self {
+            Self::Update(args) => return update(directory, &args, &config, quiet),
+            Self::Diff(args) => return diff(directory, &args, &config),
+            Self::CommitMessage(args) => return commit_message(directory, &args, &config),
+            Self::Bot(args) => {
+                return match args.commands {
+                    BotCommands::GitLab => bot(
+                        directory,
+                        &GitLab::from_env(&config.bot)?,
+                        &args,
+                        &config,
+                        quiet,
+                    ),
+                    BotCommands::GitHub => bot(
+                        directory,
+                        &GitHub::from_env(&config.bot)?,
+                        &args,
+                        &config,
+                        quiet,
+                    ),
+                    BotCommands::Forgejo => bot(
+                        directory,
+                        &Forgejo::from_env(&config.bot)?,
+                        &args,
+                        &config,
+                        quiet,
+                    ),
+                };
+            }
+
+            Self::Init(args) => init(directory, &args, &config),
+            Self::Add(mut args) => {
+                if let Some(AddCommands::Git(git_args)) = args.commands {
+                    args.commands = Some(AddCommands::Git(git_args.normalize()?));
+                }
+                let commands = if args.interactive {
+                    if args.from.is_some() {
+                        bail!("--interactive can't be combined with --from");
+                    }
+                    Some(prompt_add(args.commands, &config)?)
+                } else {
+                    args.commands
+                };
+                match (commands, args.from) {
+                    (Some(AddCommands::Git(args)), _) => add_git(directory, &args, &config),
+                    (Some(AddCommands::GitHub(args)), _) => add_github(directory, &args, &config),
+                    (Some(AddCommands::Bitbucket(args)), _) => {
+                        add_bitbucket(directory, &args, &config)
+                    }
+                    (Some(AddCommands::Forgejo(args)), _) => add_forgejo(directory, &args, &config),
+                    (Some(AddCommands::GitHubRelease(args)), _) => {
+                        add_github_release(directory, &args, &config)
+                    }
+                    (Some(AddCommands::Archive(args)), _) => add_archive(directory, &args, &config),
+                    (Some(AddCommands::Channel(args)), _) => add_channel(directory, &args, &config),
+                    (Some(AddCommands::Flake(args)), _) => add_flake(directory, &args, &config),
+                    (Some(AddCommands::Url(args)), _) => add_url(directory, &args, &config),
+                    (Some(AddCommands::Path(args)), _) => add_path(directory, &args, &config),
+                    (None, Some(path)) => add_from_manifest(directory, &path, &config),
+                    (None, None) => bail!("Either supply a source type or a manifest via --from"),
+                }
+            }
+            Self::Modify(args) => modify(directory, &args, &config),
+            Self::Set(args) => set(directory, &args, &config),
+            Self::Remove(args) => remove(directory, &args, &config),
+            Self::Freeze(args) => freeze(directory, &args, &config),
+            Self::Unfreeze(args) => unfreeze(directory, &args, &config),
+            Self::SetManual(args) => set_manual(directory, &args, true, &config),
+            Self::UnsetManual(args) => set_manual(directory, &args, false, &config),
+            Self::Dedup(args) => dedup(directory, &args, &config),
+            Self::List(args) => list(directory, &args, &config),
+            Self::Show(args) => show(directory, &args, &config),
+            Self::Audit(args) => audit(directory, &args, &config),
+            Self::Check => check(directory, &config),
+            Self::Export(args) => export(directory, &args, &config),
+            // Printed before the directory is read.
+            Self::Version(_) => unreachable!(),
+        };
+        result.map(|()| Outcome::Done)
+    }
+}
+
+fn init(directory: impl AsRef<Path>, args: &InitArgs, config: &Config) -> Result<()> {
+    let changed_config;
+    let nix_style = args.nix_style.filter(|style| *style != config.nix_style);
+    let config = if let Some(nix_style) = nix_style {
+        log::info!(
+            "Setting nix-style = \"{}\" in lon.toml...",
+            nix_style.name()
+        );
+        Config::write_nix_style(&directory, nix_style)?;
+        changed_config = Config::read(&directory)?;
+        &changed_config
+    } else {
+        config
+    };
+
+    let nix_name = config.nix_file().display();
+    let lock_name = config.lock_file().display();
+
+    // A new lock file doesn't contain any sources to check against the fetchers yet.
+    let existing_sources = || {
+        let path = config.lock_path(&directory);
+        if path.exists() {
+            Sources::read(path)
+        } else {
+            Ok(Sources::default())
+        }
+    };
+
+    if args.bare {
+        log::debug!("Not writing {nix_name}");
+    } else if config.nix_path(&directory).exists() {
+        if nix_style.is_some() {
+            LonNix::update(&directory, config, &existing_sources()?)?;
+        } else {
+            log::info!("{nix_name} already exists");
+        }
+    } else {
+        log::info!("Writing {nix_name}...");
+        LonNix::write(&directory, config, &existing_sources()?)?;
+    }
+
+    if config.lock_path(&directory).exists() {
+        log::info!("{lock_name} already exists");
+        return Ok(());
+    }
+
+    if args.from.is_none() && args.source.is_none() {
+        log::info!("Writing empty {lock_name}...");
+        let sources = Sources::default();
+        sources.write(config.lock_path(&directory))?;
+        return Ok(());
+    }
+
+    let Some(path) = &args.source else {
+        bail!("No path to initialize from is provided");
+    };
+
+    let lock_file_type = if let Some(lock_file_type) = &args.from {
+        lock_file_type.clone()
+    } else {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let lock_file_type = LockFileType::detect(&contents)?;
+        log::info!("Detected lock file type {lock_file_type:?}");
+        lock_file_type
+    };
+
+    let lock_file = match lock_file_type {
+        LockFileType::Niv => niv::LockFile::from_file(path)?,
+    };
+
+    log::info!("Initializing {lock_name} from {path:?}");
+
+    let sources = lock_file.convert()?;
+    sources.write(config.lock_path(&directory))?;
+
+    Ok(())
+}
+
+/// What `lon add` needs to know about a source besides how to construct it.
+struct NewSource<'a> {
+    name: String,
+    /// Appended to the error if the name is already taken, e.g. to suggest another name
+    hint: Option<String>,
+    bot: &'a AddBotArgs,
+}
+
+/// Add a source via `insert`, write the lock and update lon.nix.
+fn add_source(
+    directory: impl AsRef<Path>,
+    config: &Config,
+    insert: impl FnOnce(&mut Sources) -> Result<(String, String)>,
+) -> Result<()> {
+    let mut sources = Sources::read(config.lock_path(&directory))?;
+
+    let (name, revision) = insert(&mut sources)?;
+
+    sources.write(config.lock_path(&directory))?;
+    LonNix::update(&directory, config, &sources)?;
+
+    report::added(&name, &revision);
+
+    Ok(())
+}
+
+/// Add a source to the sources and return its name and the locked revision.
+///
+/// The source is only constructed once its name is known to be free because this usually
+/// accesses the network.
+fn insert_source(
+    sources: &mut Sources,
+    new: NewSource,
+    config: &Config,
+    source: impl FnOnce() -> Result<Source>,
+) -> Result<(String, String)> {
+    let NewSource { name, hint, bot } = new;
+
+    if sources.contains(&name) {
+        match hint {
+            Some(hint) => bail!("Source {name} already exists. {hint}"),
+            None => bail!("Source {name} already exists"),
+        }
+    }
+
+    log::info!("Adding {name}...");
+
+    let mut source = source()?;
+    let meta = source.meta_mut();
+    meta.update_interval = bot.update_interval;
+    meta.labels.clone_from(&bot.labels);
+    source.lock_nar_hash()?;
+
+    if let Some(existing) = sources.find_identical(&source) {
+        log::warn!("Source {existing} already fetches exactly the same as {name}");
+    }
+    LonNix::warn_incompatible(&name, &source, config);
+
+    let revision = source.locked();
+    sources.add(&name, source);
+
+    Ok((name, revision))
+}
+
+/// Return the hint for a name inferred from the repository of a forge that is already taken.
+fn forge_name_hint(name: Option<&String>, owner: &str, repo: &str) -> Option<String> {
+    name.is_none()
+        .then(|| format!("Supply --name {owner}-{repo} to add it under a different name"))
+}
+
+fn add_git(directory: impl AsRef<Path>, args: &AddGitArgs, config: &Config) -> Result<()> {
+    add_source(directory, config, |sources| {
+        add_git_source(sources, args, config)
+    })
+}
+
+/// Add a git source to the sources and return its name and the locked revision.
+fn add_git_source(
+    sources: &mut Sources,
+    args: &AddGitArgs,
+    config: &Config,
+) -> Result<(String, String)> {
+    ensure_all_submodules(&args.fetch_options.submodule_paths)?;
+
+    let new = NewSource {
+        name: args.name()?,
+        hint: args
+            .name
+            .is_none()
+            .then(|| "Supply --name to add it under a different name".into()),
+        bot: &args.bot,
+    };
+
+    insert_source(sources, new, config, || {
+        Ok(Source::Git(GitSource::new(
+            &args.url,
+            &branch_or_detect(args.branch.as_ref(), config, &args.url)?,
+            args.revision.as_ref(),
+            (&args.fetch_options).into(),
+            args.frozen,
+            args.path.as_ref(),
+        )?))
+    })
+}
+
+fn add_github(directory: impl AsRef<Path>, args: &AddGitHubArgs, config: &Config) -> Result<()> {
+    add_source(directory, config, |sources| {
+        add_github_source(sources, args, config)
+    })
+}
+
+/// Add a GitHub source to the sources and return its name and the locked revision.
+fn add_github_source(
+    sources: &mut Sources,
+    args: &AddGitHubArgs,
+    config: &Config,
+) -> Result<(String, String)> {
+    let (owner, repo) = parse_identifier(&args.identifier, "github.com")?;
+
+    let new = NewSource {
+        name: args.name.clone().unwrap_or(repo.to_string()),
+        hint: forge_name_hint(args.name.as_ref(), owner, repo),
+        bot: &args.bot,
+    };
+
+    insert_source(sources, new, config, || {
+        Ok(Source::GitHub(GitHubSource::new(
+            owner,
+            repo,
+            branch_or_default(args.branch.as_ref(), config)?,
+            args.revision.as_ref(),
+            args.frozen,
+            args.path.as_ref(),
+            (&args.hash_algo).into(),
+        )?))
+    })
+}
+
+fn add_bitbucket(
+    directory: impl AsRef<Path>,
+    args: &AddBitbucketArgs,
+    config: &Config,
+) -> Result<()> {
+    let (workspace, repo) = parse_identifier(&args.identifier, "bitbucket.org")?;
+
+    let new = NewSource {
+        name: args.name.clone().unwrap_or(repo.to_string()),
+        hint: forge_name_hint(args.name.as_ref(), workspace, repo),
+        bot: &args.bot,
+    };
+
+    add_source(directory, config, |sources| {
+        insert_source(sources, new, config, || {
+            Ok(Source::Bitbucket(BitbucketSource::new(
+                workspace,
+                repo,
+                branch_or_default(args.branch.as_ref(), config)?,
+                args.revision.as_ref(),
+                args.frozen,
+                args.path.as_ref(),
+            )?))
+        })
+    })
+}
+
+fn add_forgejo(directory: impl AsRef<Path>, args: &AddForgejoArgs, config: &Config) -> Result<()> {
+    let (owner, repo) = parse_identifier(&args.identifier, &args.host)?;
+
+    let new = NewSource {
+        name: args.name.clone().unwrap_or(repo.to_string()),
+        hint: forge_name_hint(args.name.as_ref(), owner, repo),
+        bot: &args.bot,
+    };
+
+    add_source(directory, config, |sources| {
+        insert_source(sources, new, config, || {
+            Ok(Source::Forgejo(ForgejoSource::new(
+                &args.host,
+                owner,
+                repo,
+                branch_or_default(args.branch.as_ref(), config)?,
+                args.revision.as_ref(),
+                args.frozen,
+                args.path.as_ref(),
+            )?))
+        })
+    })
+}
+
+fn add_archive(directory: impl AsRef<Path>, args: &AddArchiveArgs, config: &Config) -> Result<()> {
+    let new = NewSource {
+        name: args
+            .name
+            .clone()
+            .or_else(|| name_from_url(&args.url))
+            .with_context(|| format!("Failed to infer a name from {}. Supply --name", args.url))?,
+        hint: None,
+        bot: &args.bot,
+    };
+
+    add_source(directory, config, |sources| {
+        insert_source(sources, new, config, || {
+            Ok(Source::Archive(ArchiveSource::new(
+                &args.url,
+                args.archive_url
+                    .as_ref()
+                    .context("No archive URL supplied. Supply --archive-url")?,
+                &branch_or_detect(args.branch.as_ref(), config, &args.url)?,
+                args.revision.as_ref(),
+                args.frozen,
+                args.path.as_ref(),
+            )?))
+        })
+    })
+}
+
+fn add_github_release(
+    directory: impl AsRef<Path>,
+    args: &AddGitHubReleaseArgs,
+    config: &Config,
+) -> Result<()> {
+    let (owner, repo) = parse_identifier(&args.identifier, "github.com")?;
+
+    let new = NewSource {
+        name: args.name.clone().unwrap_or(repo.to_string()),
+        hint: forge_name_hint(args.name.as_ref(), owner, repo),
+        bot: &args.bot,
+    };
+
+    add_source(directory, config, |sources| {
+        insert_source(sources, new, config, || {
+            Ok(Source::GitHubRelease(GitHubReleaseSource::new(
+                owner,
+                repo,
+                args.tag.as_ref(),
+                &args.asset,
+                args.unpack,
+                args.frozen,
+                (&args.hash_algo).into(),
+            )?))
+        })
+    })
+}
+
+fn add_channel(directory: impl AsRef<Path>, args: &AddChannelArgs, config: &Config) -> Result<()> {
+    let new = NewSource {
+        name: args.name.clone(),
+        hint: None,
+        bot: &args.bot,
+    };
+
+    add_source(directory, config, |sources| {
+        insert_source(sources, new, config, || {
+            Ok(Source::Channel(ChannelSource::new(
+                &args.channel,
+                args.frozen,
+            )?))
+        })
+    })
+}
+
+fn add_flake(directory: impl AsRef<Path>, args: &AddFlakeArgs, config: &Config) -> Result<()> {
+    let new = NewSource {
+        name: args.name.clone(),
+        hint: None,
+        bot: &args.bot,
+    };
+
+    add_source(directory, config, |sources| {
+        insert_source(sources, new, config, || {
+            Ok(Source::Flake(FlakeSource::new(
+                &args.flake_ref,
+                args.frozen,
+            )?))
+        })
+    })
+}
+
+fn add_url(directory: impl AsRef<Path>, args: &AddUrlArgs, config: &Config) -> Result<()> {
+    let new = NewSource {
+        name: args.name.clone(),
+        hint: None,
+        bot: &args.bot,
+    };
+
+    add_source(directory, config, |sources| {
+        insert_source(sources, new, config, || {
+            Ok(Source::Url(UrlSource::new(
+                &args.url,
+                args.unpack,
+                args.frozen,
+                (&args.hash_algo).into(),
+            )?))
+        })
+    })
+}
+
+fn add_path(directory: impl AsRef<Path>, args: &AddPathArgs, config: &Config) -> Result<()> {
+    let new = NewSource {
+        name: args.name.clone(),
+        hint: None,
+        bot: &args.bot,
+    };
+
+    // lon.nix resolves the path relative to its own directory, which may be a subdirectory.
+    let nix_path = config.nix_path(&directory);
+    let nix_directory = nix_path.parent().unwrap_or(directory.as_ref());
+
+    add_source(&directory, config, |sources| {
+        insert_source(sources, new, config, || {
+            Ok(Source::Path(PathSource::new(
+                nix_directory,
+                &args.path,
+                args.hash,
+                args.frozen,
+            )?))
+        })
+    })
+}
+
+/// Add all sources from a manifest.
+///
+/// A source that fails to be added doesn't abort the others. The lock is written once at the end
+/// with all sources that were added successfully.
+fn add_from_manifest(directory: impl AsRef<Path>, path: &Path, config: &Config) -> Result<()> {
+    let manifest = Manifest::from_file(path)?;
+
+    let mut sources = Sources::read(config.lock_path(&directory))?;
+
+    let mut added = 0;
+    let mut failed = Vec::new();
+
+    for entry in manifest.sources {
+        let result = match entry {
+            Entry::Git(entry) => {
+                let args = AddGitArgs::from(entry);
+                add_git_source(&mut sources, &args, config)
+                    .map_err(|err| (args.name.unwrap_or(args.url), err))
+            }
+            Entry::GitHub(entry) => {
+                let args = AddGitHubArgs::from(entry);
+                add_github_source(&mut sources, &args, config)
+                    .map_err(|err| (args.name.unwrap_or(args.identifier), err))
+            }
+        };
+
+        match result {
+            Ok((name, revision)) => {
+                report::added(&name, &revision);
+                added += 1;
+            }
+            Err((name, err)) => {
+                log::error!("Failed to add {name}: {err:#}");
+                failed.push(name);
+            }
+        }
+    }
+
+    if added > 0 {
+        sources.write(config.lock_path(&directory))?;
+        LonNix::update(&directory, config, &sources)?;
+    }
+
+    if !failed.is_empty() {
+        bail!(
+            "Failed to add {} of {} sources: {}",
+            failed.len(),
+            added + failed.len(),
+            failed.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+impl From<GitEntry> for AddGitArgs {
+    fn from(value: GitEntry) -> Self {
+        Self {
+            url: value.url,
+            branch: value.branch,
+            legacy_branch: None,
+            name: Some(value.name),
+            revision: value.revision,
+            fetch_options: GitFetchArgs {
+                submodules: value.options.submodules,
+                submodule_paths: Vec::new(),
+                leave_dot_git: value.options.leave_dot_git,
+                deep_clone: value.options.deep_clone,
+            },
+            frozen: value.frozen,
+            path: value.path,
+            bot: AddBotArgs::default(),
+        }
+    }
+}
+
+impl From<GitHubEntry> for AddGitHubArgs {
+    fn from(value: GitHubEntry) -> Self {
+        Self {
+            identifier: value.identifier,
+            branch: value.branch,
+            name: value.name,
+            revision: value.revision,
+            hash_algo: HashAlgoArg::Sha256,
+            frozen: value.frozen,
+            path: value.path,
+            bot: AddBotArgs::default(),
+        }
+    }
+}
+
+/// Complete the arguments of `lon add` by prompting for them.
+///
+/// Only values that weren't supplied on the command line are prompted for. If no source type is
+/// supplied, it is prompted for as well.
+fn prompt_add(commands: Option<AddCommands>, config: &Config) -> Result<AddCommands> {
+    if !Term::stderr().is_term() {
+        bail!("Interactive mode requires a terminal");
+    }
+
+    let mut commands = match commands {
+        Some(commands) => commands,
+        None => select_add_command()?,
+    };
+
+    match &mut commands {
+        AddCommands::Git(args) => {
+            if args.url.is_empty() {
+                args.url = prompt("URL to the repository", None)?;
+            }
+            if args.branch.is_none() {
+                let default = prompt_default_branch(&args.url, config);
+                args.branch = Some(prompt("Branch to track", default)?);
+            }
+            if args.name.is_none() {
+                args.name = Some(prompt("Name of the source", name_from_url(&args.url))?);
+            }
+        }
+        AddCommands::GitHub(args) => {
+            prompt_forge_args(
+                "github.com",
+                "Repository ({owner}/{repo})",
+                &mut args.identifier,
+                &mut args.branch,
+                &mut args.name,
+                config,
+            )?;
+        }
+        AddCommands::Bitbucket(args) => {
+            prompt_forge_args(
+                "bitbucket.org",
+                "Repository ({workspace}/{repo})",
+                &mut args.identifier,
+                &mut args.branch,
+                &mut args.name,
+                config,
+            )?;
+        }
+        AddCommands::Forgejo(args) => {
+            prompt_forge_args(
+                &args.host,
+                "Repository ({owner}/{repo})",
+                &mut args.identifier,
+                &mut args.branch,
+                &mut args.name,
+                config,
+            )?;
+        }
+        AddCommands::Archive(args) => prompt_archive_args(args, config)?,
+        AddCommands::Channel(args) => {
+            if args.channel.is_empty() {
+                args.channel = prompt("Channel to track (e.g. nixos-24.05)", None)?;
+            }
+            if args.name.is_empty() {
+                args.name = prompt("Name of the source", Some("nixpkgs".into()))?;
+            }
+        }
+        AddCommands::Flake(args) => {
+            if args.flake_ref.is_empty() {
+                args.flake_ref = prompt("Flake reference (e.g. github:nixos/nixpkgs)", None)?;
+            }
+            if args.name.is_empty() {
+                args.name = prompt("Name of the source", None)?;
+            }
+        }
+        AddCommands::Url(args) => {
+            if args.url.is_empty() {
+                args.url = prompt("URL to the file", None)?;
+            }
+            if args.name.is_empty() {
+                args.name = prompt("Name of the source", None)?;
+            }
+        }
+        AddCommands::Path(args) => {
+            if args.path.is_empty() {
+                args.path = prompt("Path to the directory relative to lon.nix", None)?;
+            }
+            if args.name.is_empty() {
+                args.name = prompt("Name of the source", None)?;
+            }
+        }
+        AddCommands::GitHubRelease(args) => {
+            if args.identifier.is_empty() {
+                args.identifier = prompt("Repository ({owner}/{repo})", None)?;
+            }
+            let (_, repo) = parse_identifier(&args.identifier, "github.com")?;
+            if args.asset.is_empty() {
+                args.asset = prompt("Asset to lock (e.g. foo-*.tar.gz)", None)?;
+            }
+            if args.name.is_none() {
+                args.name = Some(prompt("Name of the source", Some(repo.into()))?);
+            }
+        }
+    }
+
+    Ok(commands)
+}
+
+/// Prompt for the type of a new source.
+fn select_add_command() -> Result<AddCommands> {
+    let types = [
+        "github",
+        "git",
+        "bitbucket",
+        "forgejo",
+        "github-release",
+        "archive",
+        "channel",
+        "flake",
+        "url",
+        "path",
+    ];
+    let selection = Select::new()
+        .with_prompt("Type of the source")
+        .items(&types)
+        .default(0)
+        .interact()?;
+    let commands = match types[selection] {
+        "git" => AddCommands::Git(AddGitArgs::default()),
+        "bitbucket" => AddCommands::Bitbucket(AddBitbucketArgs::default()),
+        "forgejo" => AddCommands::Forgejo(AddForgejoArgs {
+            host: sources::FORGEJO_HOST.into(),
+            ..AddForgejoArgs::default()
+        }),
+        "github-release" => AddCommands::GitHubRelease(AddGitHubReleaseArgs::default()),
+        "archive" => AddCommands::Archive(AddArchiveArgs::default()),
+        "channel" => AddCommands::Channel(AddChannelArgs::default()),
+        "flake" => AddCommands::Flake(AddFlakeArgs::default()),
+        "url" => AddCommands::Url(AddUrlArgs::default()),
+        "path" => AddCommands::Path(AddPathArgs::default()),
+        _ => AddCommands::GitHub(AddGitHubArgs::default()),
+    };
+    Ok(commands)
+}
+
+/// Prompt for the missing arguments of an archive source.
+fn prompt_archive_args(args: &mut AddArchiveArgs, config: &Config) -> Result<()> {
+    if args.url.is_empty() {
+        args.url = prompt("URL to the repository", None)?;
+    }
+    if args.branch.is_none() {
+        let default = prompt_default_branch(&args.url, config);
+        args.branch = Some(prompt("Branch to track", default)?);
+    }
+    if args.archive_url.is_none() {
+        let archive_url = prompt(
+            "Archive URL (a preset like forgejo or a template containing {rev})",
+            None,
+        )?;
+        args.archive_url = Some(sources::archive_url_template(&archive_url)?);
+    }
+    if args.name.is_none() {
+        args.name = Some(prompt("Name of the source", name_from_url(&args.url))?);
+    }
+    Ok(())
+}
+
+/// Prompt for the identifier, branch, and name of a source hosted on a forge.
+fn prompt_forge_args(
+    host: &str,
+    identifier_prompt: &str,
+    identifier: &mut String,
+    branch: &mut Option<String>,
+    name: &mut Option<String>,
+    config: &Config,
+) -> Result<()> {
+    if identifier.is_empty() {
+        *identifier = prompt(identifier_prompt, None)?;
+    }
+    let (owner, repo) = parse_identifier(identifier, host)?;
+    if branch.is_none() {
+        let default = prompt_default_branch(&format!("https://{host}/{owner}/{repo}.git"), config);
+        *branch = Some(prompt("Branch to track", default)?);
+    }
+    if name.is_none() {
+        *name = Some(prompt("Name of the source", Some(repo.into()))?);
+    }
+    Ok(())
+}
+
+/// Return the branch to suggest: default-branch from lon.toml or the default branch of the
+/// repository.
+fn prompt_default_branch(url: &str, config: &Config) -> Option<String> {
+    if let Some(branch) = &config.default_branch {
+        return Some(branch.clone());
+    }
+    git::find_default_branch(url)
+        .inspect_err(|err| log::warn!("{err:#}"))
+        .ok()
+}
+
+/// Prompt for a non-empty value, suggesting a default if there is one.
+fn prompt(prompt: &str, default: Option<String>) -> Result<String> {
+    let mut input = Input::<String>::new().with_prompt(prompt);
+    if let Some(default) = default {
+        input = input.default(default);
+    }
+    Ok(input.interact_text()?.trim().to_string())
+}
+
+/// Prompt for a yes or no answer, defaulting to no.
+fn confirm(prompt: &str) -> Result<bool> {
+    Ok(Confirm::new()
+        .with_prompt(prompt)
+        .default(false)
+        .interact()?)
+}
+
+/// Return the supplied branch or fall back to the default branch from lon.toml.
+fn branch_or_default<'a>(branch: Option<&'a String>, config: &'a Config) -> Result<&'a String> {
+    branch
+        .or(config.default_branch.as_ref())
+        .context("No branch supplied and no default-branch set in lon.toml")
+}
+
+/// Return the supplied branch, default-branch from lon.toml or the default branch of the
+/// repository at `url`.
+fn branch_or_detect(branch: Option<&String>, config: &Config, url: &str) -> Result<String> {
+    if let Some(branch) = branch.or(config.default_branch.as_ref()) {
+        return Ok(branch.clone());
+    }
+    let branch = git::find_default_branch(url)?;
+    log::info!("Detected default branch {branch}");
+    Ok(branch)
+}
+
+/// Parse an identifier of a repository on a forge into owner and repo.
+///
+/// Besides `{owner}/{repo}`, this also accepts URLs to the repository on the host and a trailing
+/// `.git`.
+fn parse_identifier<'a>(identifier: &'a str, host: &str) -> Result<(&'a str, &'a str)> {
+    let stripped = [
+        format!("https://{host}/"),
+        format!("http://{host}/"),
+        format!("{host}/"),
+    ]
+    .iter()
+    .find_map(|prefix| identifier.strip_prefix(prefix.as_str()))
+    .unwrap_or(identifier);
+    let stripped = stripped.trim_end_matches('/');
+    let stripped = stripped.strip_suffix(".git").unwrap_or(stripped);
+
+    match stripped.split_once('/') {
+        Some((owner, repo)) if !owner.is_empty() && !repo.is_empty() && !repo.contains('/') => {
+            Ok((owner, repo))
+        }
+        _ => bail!("Failed to parse identifier {identifier}. Expected {{owner}}/{{repo}}"),
+    }
+}
+
+fn update(
+    directory: impl AsRef<Path>,
+    args: &UpdateArgs,
+    config: &Config,
+    quiet: bool,
+) -> Result<Outcome> {
+    let mut sources = Sources::read(config.lock_path(&directory))?;
+
+    let mut names = Vec::new();
+
+    if let Some(ref name) = args.name {
+        names.push(name.to_string());
+    } else {
+        names.extend(sources.names().into_iter().map(ToString::to_string));
+    }
+
+    if names.is_empty() {
+        bail!("Lock file doesn't contain any sources")
+    }
+
+    if args.only_frozen_check {
+        return check_frozen(&sources, &names);
+    }
+
+    let mut commit_message = CommitMessage::new();
+    let mut totals = UpdateTotals::default();
+
+    // A source that fails to update doesn't abort the others so that all failures are reported.
+    for name in &names {
+        let Some(source) = sources.get_mut(name) else {
+            bail!(LonError::SourceNotFound(name.clone()))
+        };
+
+        if source.meta().frozen {
+            log::info!("Source {name} is frozen. Skipping...");
+            totals.frozen += 1;
+            continue;
+        }
+
+        if source.meta().manual && args.name.is_none() {
+            log::info!("Source {name} is only updated manually. Skipping...");
+            totals.skipped += 1;
+            continue;
+        }
+
+        log::info!("Updating {name}...");
+
+        match source
+            .update()
+            .with_context(|| format!("Failed to update {name}"))
+        {
+            Ok(Some(mut summary)) => {
+                if args.git.list_commits > 0 {
+                    add_rev_list(source, &mut summary, args.git.list_commits, &args.filter);
+                }
+                commit_message.add_summary(name, summary);
+                totals.updated += 1;
+            }
+            Ok(None) => totals.unchanged += 1,
+            Err(err) if args.prune && (is_gone(&err) || confirm_prune(name, &err)?) => {
+                log::warn!("{err:#}\nRemoving {name}...");
+                commit_message.add_removed(name);
+                totals.pruned += 1;
+            }
+            Err(err) => {
+                log::error!("{err:#}");
+                totals.failed.push(name.clone());
+            }
+        }
+    }
+
+    if !totals.failed.is_empty() && !args.keep_going && !commit_message.is_empty() {
+        log::warn!(
+            "Not writing any updates because some sources failed to update. Supply --keep-going to write them anyway"
+        );
+    } else if !commit_message.is_empty() {
+        for name in commit_message.removed() {
+            sources.remove(name);
+        }
+
+        sources.write(config.lock_path(&directory))?;
+        LonNix::update(&directory, config, &sources)?;
+
+        for (name, summary) in commit_message.updates() {
+            report::updated(name, &summary.old_revision, &summary.new_revision);
+        }
+        for name in commit_message.removed() {
+            report::removed(name);
+        }
+
+        if args.git.commit {
+            commit_message.set_subject_only(
+                args.git.no_commit_body || config::flag_env("LON_NO_COMMIT_BODY"),
+            );
+            let signing = config.signing(args.git.sign)?;
+            commit(
+                &directory,
+                &commit_message.to_string(),
+                None,
+                signing.as_ref(),
+                config,
+            )?;
+        }
+    }
+
+    totals.report(quiet);
+    totals.ensure_success()?;
+
+    if commit_message.is_empty() {
+        log::info!("No updates available");
+        return Ok(Outcome::NothingToDo);
+    }
+
+    Ok(Outcome::Done)
+}
+
+/// List the commits of an update for the commit message.
+///
+/// The update is already performed at this point, so failing to list the commits only omits
+/// them from the commit message.
+fn add_rev_list(
+    source: &Source,
+    summary: &mut UpdateSummary,
+    commits: usize,
+    filter: &CommitFilterArgs,
+) {
+    let paths = source
+        .path()
+        .map(ToString::to_string)
+        .into_iter()
+        .collect::<Vec<_>>();
+    match source.rev_list(summary, commits, &paths, filter.filter()) {
+        Ok(rev_list) => summary.add_rev_list(rev_list),
+        Err(err) => log::warn!("Failed to list the commits of the update: {err:#}"),
+    }
+}
+
+/// Report frozen sources whose branch has moved on since they were frozen.
+///
+/// Nothing is written. Succeeds with [`Outcome::NothingToDo`] if no frozen source is behind.
+fn check_frozen(sources: &Sources, names: &[String]) -> Result<Outcome> {
+    let mut behind = 0;
+    let mut failed = Vec::new();
+
+    for name in names {
+        let Some(source) = sources.get(name) else {
+            bail!(LonError::SourceNotFound(name.clone()))
+        };
+
+        if !source.meta().frozen {
+            log::debug!("Source {name} isn't frozen. Skipping...");
+            continue;
+        }
+
+        log::info!("Checking {name}...");
+
+        let result = source
+            .pending_update()
+            .and_then(|summary| {
+                let Some(summary) = summary else {
+                    return Ok(None);
+                };
+                let commits = source.commits_behind(&summary)?;
+                Ok(Some((summary, commits)))
+            })
+            .with_context(|| format!("Failed to check {name}"));
+
+        match result {
+            Ok(Some((summary, commits))) => {
+                report::behind(name, &summary.old_revision, &summary.new_revision, commits);
+                behind += 1;
+            }
+            Ok(None) => log::info!("{name} is frozen at the newest revision"),
+            Err(err) => {
+                log::error!("{err:#}");
+                failed.push(name.clone());
+            }
+        }
+    }
+
+    if !failed.is_empty() {
+        for name in &failed {
+            report::failed(name);
+        }
+        bail!("Failed to check {}", failed.join(", "));
+    }
+
+    if behind == 0 {
+        log::info!("No frozen source is behind");
+        return Ok(Outcome::NothingToDo);
+    }
+
+    Ok(Outcome::Done)
+}
+
+fn diff(directory: impl AsRef<Path>, args: &DiffArgs, config: &Config) -> Result<Outcome> {
+    let Some(summary) = pending_update(
+        &directory,
+        &args.name,
+        (args.commits, args.filter.filter()),
+        &args.paths,
+        config,
+    )?
+    else {
+        return Ok(Outcome::NothingToDo);
+    };
+
+    let mut commit_message = CommitMessage::new();
+    commit_message.add_summary(&args.name, summary);
+    print!("{}", commit_message.body()?.trim_start_matches('\n'));
+
+    Ok(Outcome::Done)
+}
+
+fn commit_message(
+    directory: impl AsRef<Path>,
+    args: &CommitMessageArgs,
+    config: &Config,
+) -> Result<Outcome> {
+    let commits = args.commits.or(config.bot.list_commits).unwrap_or_default();
+    let commits = (commits, args.filter.filter());
+    let Some(summary) = pending_update(&directory, &args.name, commits, &[], config)? else {
+        return Ok(Outcome::NothingToDo);
+    };
+
+    let mut commit_message = CommitMessage::new();
+    commit_message.add_summary(&args.name, summary);
+    commit_message.set_subject_only(config::flag_env("LON_NO_COMMIT_BODY"));
+    print!("{commit_message}");
+
+    Ok(Outcome::Done)
+}
+
+/// Resolve the update of a source without performing it.
+///
+/// If the number of `commits` isn't zero, that many commits between the locked and the newest
+/// revision are listed. They are filtered by `paths`, which default to the path of the source.
+/// Returns `None` if the source is up to date.
+fn pending_update(
+    directory: impl AsRef<Path>,
+    name: &str,
+    (commits, filter): (usize, CommitFilter),
+    paths: &[String],
+    config: &Config,
+) -> Result<Option<UpdateSummary>> {
+    let sources = Sources::read(config.lock_path(&directory))?;
+
+    let Some(source) = sources.get(name) else {
+        bail!(LonError::SourceNotFound(name.into()))
+    };
+
+    if source.meta().frozen {
+        log::info!("Source {name} is frozen. lon update skips it");
+    }
+
+    let Some(mut summary) = source.pending_update()? else {
+        log::info!("{name} is up to date");
+        return Ok(None);
+    };
+
+    if commits > 0 {
+        let paths = if paths.is_empty() {
+            source.path().map(ToString::to_string).into_iter().collect()
+        } else {
+            paths.to_vec()
+        };
+        let rev_list = source.rev_list(&summary, commits, &paths, filter)?;
+        summary.add_rev_list(rev_list);
+    }
+
+    Ok(Some(summary))
+}
+
+fn modify(directory: impl AsRef<Path>, args: &ModifyArgs, config: &Config) -> Result<()> {
+    ensure_all_submodules(&args.submodules.submodule_paths)?;
+
+    let mut sources = Sources::read(config.lock_path(&directory))?;
+
+    let Some(source) = sources.get_mut(&args.name) else {
+        bail!(LonError::SourceNotFound(args.name.clone()))
+    };
+
+    log::info!("Modifying {}...", args.name);
+
+    source.modify(
+        args.branch.as_ref(),
+        args.revision.as_ref(),
+        args.reference.as_ref(),
+    )?;
+
+    if let Some(url) = &args.url {
+        source.set_url(url)?;
+    }
+
+    if args.unlock && source.unlock()?.is_none() {
+        log::info!("{} already locks the newest revision", args.name);
+    }
+
+    if args.submodules.submodules || args.submodules.no_submodules {
+        source.set_submodules(args.submodules.submodules)?;
+    }
+
+    if let Some(update_interval) = args.update_interval {
+        log::info!("Changed update interval: {update_interval}s");
+        source.meta_mut().update_interval = Some(update_interval);
+    }
+
+    if !args.labels.is_empty() || args.no_labels {
+        log::info!("Changed labels: [{}]", args.labels.join(", "));
+        source.meta_mut().labels.clone_from(&args.labels);
+    }
+
+    let revision = source.locked();
+
+    sources.write(config.lock_path(&directory))?;
+    LonNix::update(&directory, config, &sources)?;
+
+    report::modified(&args.name, &revision);
+
+    Ok(())
+}
+
+fn set(directory: impl AsRef<Path>, args: &SetArgs, config: &Config) -> Result<()> {
+    let hash = NixHash::from_str(&args.hash, None)
+        .with_context(|| format!("Invalid hash {:?}", args.hash))?;
+
+    let mut sources = Sources::read(config.lock_path(&directory))?;
+
+    let Some(source) = sources.get_mut(&args.name) else {
+        bail!(LonError::SourceNotFound(args.name.clone()))
+    };
+
+    log::info!("Setting {}...", args.name);
+
+    source.set_locked(&args.revision, hash)?;
+
+    let revision = source.locked();
+
+    sources.write(config.lock_path(&directory))?;
+    LonNix::update(&directory, config, &sources)?;
+
+    report::modified(&args.name, &revision);
+
+    Ok(())
+}
+
+fn remove(directory: impl AsRef<Path>, args: &RemoveArgs, config: &Config) -> Result<()> {
+    let Some(name) = &args.name else {
+        return remove_all(directory, args.yes, config);
+    };
+
+    let mut sources = Sources::read(config.lock_path(&directory))?;
+
+    if !sources.contains(name) {
+        bail!(LonError::SourceNotFound(name.clone()))
     }
+
+    log::info!("Removing {name}...");
+
+    sources.remove(name);
+
+    sources.write(config.lock_path(&directory))?;
+    LonNix::update(&directory, config, &sources)?;
+
+    report::removed(name);
+
+    Ok(())
 }
 
-fn init(directory: impl AsRef<Path>, args: &InitArgs) -> Result<()> {
-    if LonNix::path(&directory).exists() {
-        log::info!("lon.nix already exists");
-    } else {
-        log::info!("Writing lon.nix...");
-        LonNix::write(&directory)?;
+/// Remove all sources after asking for confirmation unless `yes` is set.
+fn remove_all(directory: impl AsRef<Path>, yes: bool, config: &Config) -> Result<()> {
+    let sources = Sources::read(config.lock_path(&directory))?;
+
+    let names = sources.names();
+    if names.is_empty() {
+        bail!("There are no sources to remove");
     }
 
-    if Lock::path(&directory).exists() {
-        log::info!("lon.lock already exists");
-        return Ok(());
+    if !yes {
+        if !Term::stderr().is_term() {
+            bail!("Supply --yes to remove all sources without a terminal to confirm it");
+        }
+        if !confirm(&format!("Remove all {} sources?", names.len()))? {
+            log::info!("Keeping all sources");
+            return Ok(());
+        }
     }
 
-    if args.from.is_none() && args.source.is_none() {
-        log::info!("Writing empty lon.lock...");
-        let sources = Sources::default();
-        sources.write(directory)?;
-        return Ok(());
+    log::info!("Removing all sources...");
+
+    let sources = Sources::default();
+    sources.write(config.lock_path(&directory))?;
+    LonNix::update(&directory, config, &sources)?;
+
+    for name in names {
+        report::removed(name);
     }
 
-    let Some(path) = &args.source else {
-        bail!("No path to initialize from is provided");
-    };
+    Ok(())
+}
 
-    let Some(lock_file_type) = &args.from else {
-        bail!("No lock file type is provided");
-    };
+fn freeze(directory: impl AsRef<Path>, args: &SourceArgs, config: &Config) -> Result<()> {
+    let mut sources = Sources::read(config.lock_path(&directory))?;
 
-    let lock_file = match lock_file_type {
-        LockFileType::Niv => niv::LockFile::from_file(path)?,
+    let Some(source) = sources.get_mut(&args.name) else {
+        bail!(LonError::SourceNotFound(args.name.clone()))
     };
 
-    log::info!("Initializing lon.lock from {path:?}");
+    log::info!("Freezing {}...", args.name);
 
-    let sources = lock_file.convert()?;
-    sources.write(&directory)?;
+    source.meta_mut().frozen = true;
+
+    sources.write(config.lock_path(&directory))?;
+    LonNix::update(&directory, config, &sources)?;
+
+    report::frozen(&args.name, true);
 
     Ok(())
 }
 
-fn add_git(directory: impl AsRef<Path>, args: &AddGitArgs) -> Result<()> {
-    let mut sources = Sources::read(&directory)?;
-    if sources.contains(&args.name) {
-        bail!("Source {} already exists", args.name);
-    }
+fn unfreeze(directory: impl AsRef<Path>, args: &SourceArgs, config: &Config) -> Result<()> {
+    let mut sources = Sources::read(config.lock_path(&directory))?;
 
-    log::info!("Adding {}...", args.name);
+    let Some(source) = sources.get_mut(&args.name) else {
+        bail!(LonError::SourceNotFound(args.name.clone()))
+    };
 
-    let source = GitSource::new(
-        &args.url,
-        &args.branch,
-        args.revision.as_ref(),
-        args.submodules,
-        args.frozen,
-    )?;
+    log::info!("Unfreezing {}...", args.name);
 
-    sources.add(&args.name, Source::Git(source));
+    source.meta_mut().frozen = false;
 
-    sources.write(&directory)?;
-    LonNix::update(&directory)?;
+    sources.write(config.lock_path(&directory))?;
+    LonNix::update(&directory, config, &sources)?;
+
+    report::frozen(&args.name, false);
 
     Ok(())
 }
 
-fn add_github(directory: impl AsRef<Path>, args: &AddGitHubArgs) -> Result<()> {
-    let Some((owner, repo)) = args.identifier.split_once('/') else {
-        bail!("Failed to parse identifier {}", args.identifier)
-    };
+/// Set whether a source is only updated when it is named explicitly.
+fn set_manual(
+    directory: impl AsRef<Path>,
+    args: &SourceArgs,
+    manual: bool,
+    config: &Config,
+) -> Result<()> {
+    let mut sources = Sources::read(config.lock_path(&directory))?;
 
-    let name = args.name.clone().unwrap_or(repo.to_string());
+    let Some(source) = sources.get_mut(&args.name) else {
+        bail!(LonError::SourceNotFound(args.name.clone()))
+    };
 
-    let mut sources = Sources::read(&directory)?;
-    if sources.contains(&name) {
-        bail!("Source {name} already exists");
+    if manual {
+        log::info!("Only updating {} manually...", args.name);
+    } else {
+        log::info!("Updating {} together with all sources...", args.name);
     }
 
-    log::info!("Adding {name}...");
-
-    let source = GitHubSource::new(
-        owner,
-        repo,
-        &args.branch,
-        args.revision.as_ref(),
-        args.frozen,
-    )?;
+    source.meta_mut().manual = manual;
 
-    sources.add(&name, Source::GitHub(source));
+    sources.write(config.lock_path(&directory))?;
+    LonNix::update(&directory, config, &sources)?;
 
-    sources.write(&directory)?;
-    LonNix::update(&directory)?;
+    report::manual(&args.name, manual);
 
     Ok(())
 }
 
-fn update(directory: impl AsRef<Path>, args: &UpdateArgs) -> Result<()> {
-    let mut sources = Sources::read(&directory)?;
+fn dedup(directory: impl AsRef<Path>, args: &DedupArgs, config: &Config) -> Result<()> {
+    let mut sources = Sources::read(config.lock_path(&directory))?;
 
-    let mut names = Vec::new();
+    let duplicates = sources
+        .duplicates()
+        .into_iter()
+        .map(|names| names.into_iter().cloned().collect::<Vec<String>>())
+        .collect::<Vec<_>>();
 
-    if let Some(ref name) = args.name {
-        names.push(name.to_string());
-    } else {
-        names.extend(sources.names().into_iter().map(ToString::to_string));
+    if duplicates.is_empty() {
+        log::info!("No duplicate sources found");
+        return Ok(());
     }
 
-    if names.is_empty() {
-        bail!("Lock file doesn't contain any sources")
+    for names in &duplicates {
+        println!("{}", names.join(" "));
     }
 
-    let mut commit_message = CommitMessage::new();
+    if !args.remove {
+        return Ok(());
+    }
 
-    for name in &names {
-        let Some(source) = sources.get_mut(name) else {
-            bail!("Source {name} doesn't exist")
-        };
+    for names in &duplicates {
+        for name in names.iter().skip(1) {
+            log::info!("Removing {name}...");
+            sources.remove(name);
+        }
+    }
 
-        log::info!("Updating {name}...");
+    sources.write(config.lock_path(&directory))?;
+    LonNix::update(&directory, config, &sources)?;
 
-        let summary = source
-            .update()
-            .with_context(|| format!("Failed to update {name}"))?;
+    Ok(())
+}
 
-        if let Some(summary) = summary {
-            commit_message.add_summary(name, summary);
+fn version(args: &VersionArgs) -> Result<()> {
+    let build_info = BuildInfo::current();
+    if args.json {
+        println!("{}", serde_json::to_string(&build_info)?);
+    } else {
+        println!("{build_info}");
+        if let Some(rustc) = build_info.rustc {
+            println!("Built with {rustc}");
         }
     }
+    Ok(())
+}
 
-    if commit_message.is_empty() {
-        bail!("No updates available")
-    }
+fn list(directory: impl AsRef<Path>, args: &ListArgs, config: &Config) -> Result<()> {
+    let sources = Sources::read(config.lock_path(&directory))?;
+
+    let now = time::now();
+    let mut listed = 0;
+
+    for name in sources.names() {
+        let Some(source) = sources.get(name) else {
+            continue;
+        };
+
+        if args.kind.is_some_and(|kind| source.kind() != kind) {
+            continue;
+        }
+
+        let age = source
+            .last_updated()
+            .map(|last_updated| now.saturating_sub(last_updated));
+
+        // Sources without a known age are always listed.
+        if args
+            .stale
+            .is_some_and(|stale| age.is_some_and(|age| age < stale))
+        {
+            continue;
+        }
 
-    sources.write(&directory)?;
-    LonNix::update(&directory)?;
+        match age {
+            Some(age) => println!("{name} {} ago", time::format_duration(age)),
+            None => println!("{name} unknown"),
+        }
+        listed += 1;
+    }
 
-    if args.commit {
-        commit(&directory, &commit_message.to_string(), None)?;
+    if listed == 0 {
+        if args.kind.is_some() {
+            log::info!("No matching sources found");
+        } else if args.stale.is_some() {
+            log::info!("No stale sources found");
+        } else {
+            log::info!("No sources found");
+        }
     }
 
     Ok(())
 }
 
-fn modify(directory: impl AsRef<Path>, args: &ModifyArgs) -> Result<()> {
-    let mut sources = Sources::read(&directory)?;
+/// Print the type, the tracked branch, the locked revision and the hashes of a source.
+fn show(directory: impl AsRef<Path>, args: &SourceArgs, config: &Config) -> Result<()> {
+    let sources = Sources::read(config.lock_path(&directory))?;
 
-    let Some(source) = sources.get_mut(&args.name) else {
-        bail!("Source {} doesn't exist", args.name)
+    let Some(source) = sources.get(&args.name) else {
+        bail!(LonError::SourceNotFound(args.name.clone()))
     };
 
-    log::info!("Modifying {}...", args.name);
+    if let Some(kind) = source.kind().to_possible_value() {
+        println!("type: {}", kind.get_name());
+    }
+    if let Some(branch) = source.branch() {
+        println!("branch: {branch}");
+    }
+    println!("locked: {}", source.locked());
+    if let Some(hash) = source.hash() {
+        println!("hash: {hash}");
+    }
+    if let Some(nar_hash) = source.nar_hash() {
+        println!("narHash: {nar_hash}");
+    }
+
+    Ok(())
+}
 
-    source.modify(args.branch.as_ref(), args.revision.as_ref())?;
+fn audit(directory: impl AsRef<Path>, args: &AuditArgs, config: &Config) -> Result<()> {
+    let sources = Sources::read(config.lock_path(&directory))?;
+
+    let findings = audit::audit(&sources, time::now(), args.max_age);
+    for Finding {
+        severity,
+        name,
+        problem,
+        hint,
+    } in &findings
+    {
+        report::finding(&severity.to_string(), name, problem, hint);
+    }
 
-    sources.write(&directory)?;
-    LonNix::update(&directory)?;
+    if findings.is_empty() {
+        log::info!("No problems found");
+    }
 
     Ok(())
 }
 
-fn remove(directory: impl AsRef<Path>, args: &SourceArgs) -> Result<()> {
-    let mut sources = Sources::read(&directory)?;
+fn export(directory: impl AsRef<Path>, args: &ExportArgs, config: &Config) -> Result<()> {
+    let sources = Sources::read(config.lock_path(&directory))?;
+
+    let lock_file = match args.format {
+        LockFileType::Niv => export::niv::LockFile::export(&sources)?,
+    };
 
-    if !sources.contains(&args.name) {
-        bail!("Source {} doesn't exist", args.name)
+    if let Some(path) = &args.output {
+        log::info!("Writing {path:?}...");
+        let file = File::create(path).with_context(|| format!("Failed to open {path:?}"))?;
+        lock_file.to_writer(file)?;
+    } else {
+        lock_file.to_writer(io::stdout().lock())?;
     }
 
-    log::info!("Removing {}...", args.name);
+    Ok(())
+}
+
+fn check(directory: impl AsRef<Path>, config: &Config) -> Result<()> {
+    Sources::read(config.lock_path(&directory))?;
+
+    if !config.nix_path(&directory).exists() {
+        log::info!(
+            "{} is valid and {} doesn't exist",
+            config.lock_file().display(),
+            config.nix_file().display()
+        );
+        return Ok(());
+    }
 
-    sources.remove(&args.name);
+    if let Some(summary) = LonNix::diff(&directory, config)? {
+        bail!(
+            "{} is out of date: {summary}.\nRemove it and run lon init to regenerate it",
+            config.nix_file().display()
+        )
+    }
 
-    sources.write(&directory)?;
-    LonNix::update(&directory)?;
+    log::info!(
+        "{} and {} are up to date",
+        config.lock_file().display(),
+        config.nix_file().display()
+    );
 
     Ok(())
 }
 
-fn freeze(directory: impl AsRef<Path>, args: &SourceArgs) -> Result<()> {
-    let mut sources = Sources::read(&directory)?;
-
-    let Some(source) = sources.get_mut(&args.name) else {
-        bail!("Source {} doesn't exist", args.name)
+fn bot(
+    directory: impl AsRef<Path>,
+    forge: &(impl Forge + Sync),
+    args: &BotArgs,
+    config: &Config,
+    quiet: bool,
+) -> Result<Outcome> {
+    let start = Instant::now();
+    let mut report = RunReport {
+        started_at: time::now(),
+        ..RunReport::default()
     };
 
-    log::info!("Freezing {}...", args.name);
-
-    source.freeze();
+    let result = run_bot(directory, forge, args, config, quiet, &mut report);
 
-    sources.write(&directory)?;
-    LonNix::update(&directory)?;
+    // The report is written even if the run failed so that dashboards see the failure.
+    if let Some(path) = &args.report_file {
+        report.duration_seconds = start.elapsed().as_secs_f64();
+        report.sources.sort_by(|a, b| a.name.cmp(&b.name));
+        if let Err(err) = &result {
+            report.error = Some(format!("{err:#}"));
+        }
+        match report.to_file(path) {
+            Ok(()) => {}
+            // Don't hide why the run failed behind the failure to write the report.
+            Err(err) if result.is_err() => log::error!("{err:#}"),
+            Err(err) => return Err(err),
+        }
+    }
 
-    Ok(())
+    result
 }
 
-fn unfreeze(directory: impl AsRef<Path>, args: &SourceArgs) -> Result<()> {
-    let mut sources = Sources::read(&directory)?;
+/// Check all due sources for updates and record the outcome of each in `report`.
+fn run_bot(
+    directory: impl AsRef<Path>,
+    forge: &(impl Forge + Sync),
+    args: &BotArgs,
+    config: &Config,
+    quiet: bool,
+    report: &mut RunReport,
+) -> Result<Outcome> {
+    let bot_config = &config.bot;
+
+    // Nothing is committed when only reporting, so uncommitted changes don't matter.
+    let report_only = args.report || config::flag_env("LON_REPORT_ONLY");
+    if !report_only && !args.dry_run && !args.branches.allow_dirty {
+        ensure_clean(&directory)?;
+    }
 
-    let Some(source) = sources.get_mut(&args.name) else {
-        bail!("Source {} doesn't exist", args.name)
+    let mut sources = Sources::read(config.lock_path(&directory))?;
+
+    let toplevel = git::toplevel(&directory)?;
+    let relative_directory = directory
+        .as_ref()
+        .canonicalize()
+        .context("Failed to canonicalize directory")?
+        .strip_prefix(&toplevel)
+        .context("Directory is not inside the git repository")?
+        .to_path_buf();
+
+    let state_file = match args
+        .state_file
+        .clone()
+        .or_else(|| env::var_os("LON_STATE_FILE").map(PathBuf::from))
+    {
+        Some(state_file) => state_file,
+        None => git::common_dir(&directory)?
+            .join("lon")
+            .join(&relative_directory)
+            .join("state.json"),
     };
+    let mut state = BotState::from_file(&state_file)?;
+    state.apply(&mut sources);
+
+    let mut totals = UpdateTotals::default();
+
+    let names = due_sources(&sources, args.ignore_schedule, &mut totals);
+
+    // Sources that aren't checked are either frozen, manual or not due.
+    for name in sources.names() {
+        if !names.contains(name) {
+            let outcome = if sources.get(name).is_some_and(|source| source.meta().frozen) {
+                SourceOutcome::Frozen
+            } else {
+                SourceOutcome::Skipped
+            };
+            report
+                .sources
+                .push(SourceReport::new(name, outcome, Duration::ZERO));
+        }
+    }
 
-    log::info!("Unfreezing {}...", args.name);
+    let run = BotRun {
+        forge,
+        directory: directory.as_ref(),
+        relative_directory,
+        base_ref: git::current_rev(&directory)?,
+        base_branch: args
+            .branches
+            .base
+            .clone()
+            .or_else(|| env::var("LON_BASE_BRANCH").ok()),
+        list_commits: match env::var("LON_LIST_COMMITS") {
+            Ok(s) => s.parse::<usize>().unwrap_or(50),
+            Err(_) => bot_config.list_commits.unwrap_or(0),
+        },
+        user: git::User::new(
+            &env::var("LON_USER_NAME")
+                .ok()
+                .or(bot_config.user_name.clone())
+                .unwrap_or("LonBot".into()),
+            &env::var("LON_USER_EMAIL")
+                .ok()
+                .or(bot_config.user_email.clone())
+                .unwrap_or("lonbot@lonbot".into()),
+        ),
+        push_url: env::var("LON_PUSH_URL").ok(),
+        force_push: args.branches.force,
+        signing: config.signing(args.branches.sign)?,
+        config,
+        report: report_only.then(Mutex::default),
+        dry_run: args.dry_run,
+        commit_filter: args.filter.bot_filter(),
+    };
 
-    source.unfreeze();
+    let jobs = args.jobs.or(bot_config.jobs).unwrap_or(1);
+    let checked_at = time::now();
+    let (worker_totals, source_reports) = run.check_sources(&sources, &names, jobs)?;
+    totals.merge(worker_totals);
 
-    sources.write(&directory)?;
-    LonNix::update(&directory)?;
+    // A dry run doesn't change anything, not even what the bot remembers.
+    if !args.dry_run {
+        for source_report in &source_reports {
+            if source_report.outcome != SourceOutcome::Failed {
+                state.record_check(&sources, &source_report.name, checked_at);
+            }
+        }
+        state.retain(&sources);
+        state.to_file(&state_file)?;
+    }
+    report.sources.extend(source_reports);
 
-    Ok(())
+    if let Some(updates) = &run.report {
+        let updates = updates
+            .lock()
+            .map_err(|_| anyhow!("A bot worker panicked"))?;
+        report.issue = run.open_report(&updates)?;
+    }
+
+    totals.report(quiet);
+    totals.ensure_success()?;
+
+    Ok(Outcome::Done)
 }
 
-fn bot(directory: impl AsRef<Path>, forge: &impl Forge) -> Result<()> {
-    let base_ref = git::current_rev(&directory)?;
+/// Return the names of the sources the bot checks for updates.
+///
+/// Frozen sources, manual sources and sources that aren't due (unless the schedule is ignored)
+/// are counted in the totals instead.
+fn due_sources(sources: &Sources, ignore_schedule: bool, totals: &mut UpdateTotals) -> Vec<String> {
+    let now = time::now();
+    sources
+        .names()
+        .into_iter()
+        .filter(|name| {
+            let Some(source) = sources.get(name) else {
+                return false;
+            };
+            if source.meta().frozen {
+                log::info!("Source {name} is frozen. Skipping...");
+                totals.frozen += 1;
+                return false;
+            }
+            if source.meta().manual {
+                log::info!("Source {name} is only updated manually. Skipping...");
+                totals.skipped += 1;
+                return false;
+            }
+            if !ignore_schedule && !source.is_due(now) {
+                log::info!("Source {name} is not due for an update. Skipping...");
+                totals.skipped += 1;
+                return false;
+            }
+            true
+        })
+        .cloned()
+        .collect()
+}
 
-    let result = bot_fallible(&directory, forge, &base_ref);
+/// Return whether an update failed because the source no longer exists upstream.
+fn is_gone(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|err| err.downcast_ref::<LonError>())
+        .any(LonError::is_gone)
+}
 
-    // Always return to the base commit.
-    git::checkout(&directory, &base_ref, false)?;
+/// Ask whether to remove a source whose remote repository isn't accessible.
+///
+/// The repository may only be private with missing or expired credentials, so such a source is
+/// never removed without confirmation. Without a terminal, it's kept.
+fn confirm_prune(name: &str, err: &anyhow::Error) -> Result<bool> {
+    let inaccessible = err
+        .chain()
+        .filter_map(|err| err.downcast_ref::<LonError>())
+        .any(|err| matches!(err, LonError::RepositoryInaccessible { .. }));
+    if !inaccessible || !Term::stderr().is_term() {
+        return Ok(false);
+    }
+    confirm(&format!("{err:#}\nRemove {name}?"))
+}
 
-    result
+/// Ensure that the working tree has no uncommitted changes that could end up in update branches.
+fn ensure_clean(directory: impl AsRef<Path>) -> Result<()> {
+    if !git::is_clean(&directory)? {
+        bail!(
+            "The working tree has uncommitted changes that could end up in the update branches.\nCommit or stash them first or pass --allow-dirty"
+        );
+    }
+    Ok(())
 }
 
-fn bot_fallible(directory: impl AsRef<Path>, forge: &impl Forge, base_ref: &str) -> Result<()> {
-    let sources = Sources::read(&directory)?;
+/// An update of a source found by the bot.
+struct BotUpdate {
+    summary: UpdateSummary,
+    /// The URL of the PR opened for the update
+    pull_request: Option<String>,
+}
 
-    let names = sources
-        .names()
-        .into_iter()
-        .cloned()
-        .collect::<Vec<String>>();
+/// Shared state of a single bot run.
+struct BotRun<'a, F> {
+    forge: &'a F,
+    /// The directory containing lon.{nix,lock}
+    directory: &'a Path,
+    /// The directory containing lon.{nix,lock} relative to the root of the repository
+    relative_directory: PathBuf,
+    /// The ref all update branches are based on
+    base_ref: String,
+    /// The branch PRs are opened against instead of the default branch
+    base_branch: Option<String>,
+    list_commits: usize,
+    user: git::User,
+    push_url: Option<String>,
+    /// Whether to overwrite update branches regardless of what they point to on the remote
+    force_push: bool,
+    signing: Option<git::Signing>,
+    config: &'a Config,
+    /// The available updates if they are only reported instead of opening a PR for each
+    report: Option<Mutex<Vec<(String, UpdateSummary)>>>,
+    /// Whether to only print the PRs or the issue instead of changing anything
+    dry_run: bool,
+    /// Which of the commits of an update are listed
+    commit_filter: CommitFilter,
+}
 
-    let list_commits = match env::var("LON_LIST_COMMITS") {
-        Ok(s) => s.parse::<usize>().unwrap_or(50),
-        Err(_) => 0,
-    };
+impl<F: Forge + Sync> BotRun<'_, F> {
+    /// Check the sources with the given names for updates using `jobs` workers.
+    ///
+    /// A source that fails to update doesn't abort the others.
+    fn check_sources(
+        &self,
+        sources: &Sources,
+        names: &[String],
+        jobs: usize,
+    ) -> Result<(UpdateTotals, Vec<SourceReport>)> {
+        // Each worker takes the next source from the queue until it is empty.
+        let queue = Mutex::new(names.iter());
+        let next_name = || queue.lock().ok().and_then(|mut names| names.next());
+
+        let updated = if self.report.is_some() || self.dry_run {
+            SourceOutcome::Available
+        } else {
+            SourceOutcome::Updated
+        };
 
-    for name in &names {
-        // Clone the original sources to reset the state between updates
-        let mut m_sources = sources.clone();
+        let results = thread::scope(|scope| {
+            let workers = (0..jobs.max(1))
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut totals = UpdateTotals::default();
+                        let mut reports = Vec::new();
+                        while let Some(name) = next_name() {
+                            let start = Instant::now();
+                            let result = self
+                                .check_source(sources, name)
+                                .with_context(|| format!("Failed to update {name}"));
+                            let report =
+                                |outcome| SourceReport::new(name, outcome, start.elapsed());
+                            reports.push(match result {
+                                Ok(Some(update)) => {
+                                    totals.updated += 1;
+                                    report(updated)
+                                        .with_update(&update.summary)
+                                        .with_pull_request(update.pull_request)
+                                }
+                                Ok(None) => {
+                                    totals.unchanged += 1;
+                                    report(SourceOutcome::Unchanged)
+                                }
+                                Err(err) => {
+                                    log::error!("{err:#}");
+                                    totals.failed.push(name.clone());
+                                    report(SourceOutcome::Failed).with_error(&err)
+                                }
+                            });
+                        }
+                        (totals, reports)
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            workers
+                .into_iter()
+                .map(|worker| worker.join().map_err(|_| anyhow!("A bot worker panicked")))
+                .collect::<Result<Vec<_>>>()
+        })?;
+
+        let mut totals = UpdateTotals::default();
+        let mut reports = Vec::new();
+        for (worker_totals, worker_reports) in results {
+            totals.merge(worker_totals);
+            reports.extend(worker_reports);
+        }
+        Ok((totals, reports))
+    }
 
-        let Some(source) = m_sources.get_mut(name) else {
-            log::warn!("Source {name} doesn't exist");
-            continue;
+    /// Check a single source for an update and either open a PR for it, add it to the report or
+    /// print the PR in a dry run.
+    ///
+    /// Returns the update if one is available.
+    fn check_source(&self, sources: &Sources, name: &str) -> Result<Option<BotUpdate>> {
+        if self.report.is_none() && !self.dry_run {
+            return self.update_source(sources, name);
+        }
+
+        let Some(mut source) = sources.get(name).cloned() else {
+            bail!(LonError::SourceNotFound(name.into()));
         };
 
-        if source.frozen() {
-            log::info!("Source {name} is frozen. Skipping...");
-            continue;
+        log::info!("Checking {name} for updates...");
+
+        let Some(mut summary) = source.update()? else {
+            log::info!("No updates available for {name}");
+            return Ok(None);
+        };
+
+        if self.list_commits > 0 {
+            let rev_list = self.rev_list(&source, &summary)?;
+            summary.add_rev_list(rev_list);
+        }
+
+        if let Some(report) = &self.report {
+            report
+                .lock()
+                .map_err(|_| anyhow!("A bot worker panicked"))?
+                .push((name.into(), summary.clone()));
+        } else {
+            let mut commit_message = CommitMessage::new();
+            commit_message.add_summary(name, summary.clone());
+            report::planned_pull_request(
+                name,
+                &update_branch(name),
+                &self.forge.pull_request_title(name),
+                &self.forge.pull_request_labels(&source.meta().labels),
+                &commit_message.body()?,
+            );
+        }
+
+        Ok(Some(BotUpdate {
+            summary,
+            pull_request: None,
+        }))
+    }
+
+    /// Open or update the issue that lists all available updates and return its URL.
+    ///
+    /// Nothing is reported if no updates are available.
+    fn open_report(&self, updates: &[(String, UpdateSummary)]) -> Result<Option<String>> {
+        if updates.is_empty() {
+            return Ok(None);
+        }
+
+        // The workers finish in any order, so sort to keep the issue stable between runs.
+        let mut updates = updates.to_vec();
+        updates.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let names = updates
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let mut commit_message = CommitMessage::new();
+        for (name, summary) in updates.iter().cloned() {
+            commit_message.add_summary(&name, summary);
+        }
+
+        let body = format!(
+            "Updates are available for {names}.\n{}",
+            commit_message.body()?
+        );
+        if self.dry_run {
+            report::planned_issue(REPORT_TITLE, &body);
+            return Ok(None);
+        }
+        let issue_url = self.forge.open_issue(REPORT_TITLE, &body)?;
+        report::issue(&issue_url);
+
+        Ok(Some(issue_url))
+    }
+
+    /// Update a single source in its own worktree and open a PR for it.
+    ///
+    /// Returns the update if the source was updated.
+    fn update_source(&self, sources: &Sources, name: &str) -> Result<Option<BotUpdate>> {
+        let branch = update_branch(name);
+
+        let tmp_dir = TempDir::new()?;
+        let worktree = tmp_dir.path().join("worktree");
+
+        log::debug!("Adding worktree for new branch {branch}...");
+        git::worktree_add(self.directory, &worktree, &branch, &self.base_ref)?;
+
+        let result = self.update_source_in_worktree(
+            sources,
+            name,
+            &branch,
+            &worktree.join(&self.relative_directory),
+        );
+
+        log::debug!("Removing worktree for branch {branch}...");
+        let removed = git::worktree_remove(self.directory, &worktree);
+
+        // Report why the update failed rather than a failure to clean up after it.
+        let update = result?;
+        removed?;
+        Ok(update)
+    }
+
+    /// List the commits of an update.
+    ///
+    /// If the source is hosted on the forge, its API is used. Otherwise, or if that fails, the
+    /// commits are listed via the source.
+    ///
+    /// If the source only exposes a subdirectory, only commits touching it are listed. The
+    /// forge APIs can't filter by path, so they are skipped in this case.
+    ///
+    /// Sources without a revision (e.g. flakes fetched from a tarball) have no commits to list.
+    fn rev_list(&self, source: &Source, summary: &UpdateSummary) -> Result<RevList> {
+        // The summary of such a source holds hashes instead of revisions.
+        if source.revision().is_none() {
+            return Ok(RevList::from_commits([]));
+        }
+
+        let paths = source
+            .path()
+            .map(ToString::to_string)
+            .into_iter()
+            .collect::<Vec<_>>();
+        if !paths.is_empty() {
+            return source.rev_list(summary, self.list_commits, &paths, self.commit_filter);
+        }
+
+        // Flakes aren't git repositories, but may still be hosted on GitHub.
+        if let Ok(git_url) = source.git_url() {
+            match self.forge.compare_commits(
+                &git_url,
+                summary.old_revision.as_str(),
+                summary.new_revision.as_str(),
+                self.list_commits,
+                self.commit_filter,
+            ) {
+                Ok(Some(rev_list)) => return Ok(rev_list),
+                Ok(None) => {}
+                Err(err) => log::warn!("{err:#}"),
+            }
         }
+        source.rev_list(summary, self.list_commits, &paths, self.commit_filter)
+    }
 
-        log::debug!("Checking out base ref {base_ref}...");
-        git::checkout(&directory, base_ref, false)?;
+    fn update_source_in_worktree(
+        &self,
+        sources: &Sources,
+        name: &str,
+        branch: &str,
+        directory: &Path,
+    ) -> Result<Option<BotUpdate>> {
+        // Clone the original sources to reset the state between updates
+        let mut m_sources = sources.clone();
 
-        let branch = format!("lon/{name}");
-        log::debug!("Checking out new branch {branch}...");
-        git::checkout(&directory, &branch, true)?;
+        let Some(source) = m_sources.get_mut(name) else {
+            bail!(LonError::SourceNotFound(name.into()));
+        };
 
         log::info!("Updating {name}...");
 
-        let summary = source
-            .update()
-            .with_context(|| format!("Failed to update {name}"))?;
+        let Some(mut summary) = source.update()? else {
+            log::info!("No updates available for {name}");
+            return Ok(None);
+        };
 
-        let Some(mut summary) = summary else {
-            log::info!("No updates available");
-            continue;
+        // Remember where the branch points to now so that changes by someone else (e.g. a
+        // concurrent run) until it is pushed aren't overwritten.
+        let push = if self.force_push {
+            git::Push::Force
+        } else {
+            git::Push::Lease(git::find_pushed_revision(
+                directory,
+                self.push_url.as_deref(),
+                branch,
+            )?)
         };
 
-        if list_commits > 0 {
-            let rev_list = source.rev_list(&summary, list_commits)?;
+        if self.list_commits > 0 {
+            let rev_list = self.rev_list(source, &summary)?;
             summary.add_rev_list(rev_list);
         }
+        let labels = source.meta().labels.clone();
 
         let mut commit_message = CommitMessage::new();
 
         commit_message.add_summary(name, summary.clone());
+        // The PR still describes the update in full.
+        commit_message.set_subject_only(config::flag_env("LON_NO_COMMIT_BODY"));
 
-        m_sources.write(&directory)?;
-        LonNix::update(&directory)?;
-
-        let user_name = env::var("LON_USER_NAME").unwrap_or("LonBot".into());
-        let user_email = env::var("LON_USER_EMAIL").unwrap_or("lonbot@lonbot".into());
+        m_sources.write(self.config.lock_path(directory))?;
+        LonNix::update(directory, self.config, &m_sources)?;
 
         log::debug!("Committing changes...");
         commit(
-            &directory,
+            directory,
             &commit_message.to_string(),
-            Some(git::User::new(&user_name, &user_email)),
+            Some(self.user.clone()),
+            self.signing.as_ref(),
+            self.config,
         )?;
 
-        let push_url = env::var("LON_PUSH_URL").ok();
-
         // Never log the URL as it might contain a secret token.
-        log::debug!("Force pushing repository...");
-        git::force_push(&directory, push_url.as_deref(), &branch)?;
+        log::debug!("Pushing repository...");
+        git::push(directory, self.push_url.as_deref(), branch, &push)?;
+
+        let pull_request = match self.forge.open_pull_request(
+            branch,
+            self.base_branch.as_deref(),
+            name,
+            &labels,
+            Some(commit_message.body()?),
+        ) {
+            Ok(pull_request_url) => {
+                report::pull_request(name, &pull_request_url);
+                Some(pull_request_url)
+            }
+            Err(err) => {
+                log::warn!("{err}");
+                None
+            }
+        };
 
-        match forge.open_pull_request(&branch, name, Some(commit_message.body()?)) {
-            Ok(pull_request_url) => log::info!("Opened Pull Request: {pull_request_url}"),
-            Err(err) => log::warn!("{err}"),
-        }
+        Ok(Some(BotUpdate {
+            summary,
+            pull_request,
+        }))
     }
+}
 
-    Ok(())
+/// The title of the issue opened by `lon bot --report`.
+///
+/// It is used to find the issue of a previous run, so it must never change.
+const REPORT_TITLE: &str = "lon: available updates";
+
+/// Return the branch the bot pushes the update of a source to.
+fn update_branch(name: &str) -> String {
+    format!("lon/{name}")
 }
 
 fn commit(
     directory: impl AsRef<Path>,
     commit_message: &str,
     user: Option<git::User>,
+    signing: Option<&git::Signing>,
+    config: &Config,
 ) -> Result<()> {
     // Don't provide the directory twice. The `git add` command is already executed in the
-    // directory, so the Lock and LonNix paths don't need to include it as well.
-    git::add(&directory, &[&Lock::path(""), &LonNix::path("")])?;
-    git::commit(&directory, commit_message, user)?;
+    // directory, so the paths to the files don't need to include it as well.
+    let mut files = vec![config.lock_file()];
+    if config.nix_path(&directory).exists() {
+        files.push(config.nix_file());
+    }
+    git::add(&directory, &files)?;
+    git::commit(&directory, commit_message, user, signing)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::lock::Lock;
+
+    #[test]
+    fn detect_lock_file_type() -> Result<()> {
+        assert_eq!(
+            LockFileType::detect(include_str!("../tests/niv.json"))?,
+            LockFileType::Niv
+        );
+        assert_eq!(LockFileType::detect("{}")?, LockFileType::Niv);
+
+        let error = |contents| {
+            LockFileType::detect(contents)
+                .err()
+                .map(|err| err.to_string())
+                .unwrap_or_default()
+        };
+        assert_eq!(
+            error(r#"{ "nodes": {}, "root": "root", "version": 7 }"#),
+            "Initializing from a flake.lock is not supported. Supported types are: niv"
+        );
+        assert_eq!(
+            error(r#"{ "pins": {}, "version": 5 }"#),
+            "Initializing from npins is not supported. Supported types are: niv"
+        );
+        assert_eq!(
+            error("sources: []"),
+            "Failed to detect the lock file type. Supported types are: niv"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn choose_color() {
+        let choice = |color, vars: &[(&str, &str)]| {
+            Color::choice(color, |key| {
+                vars.iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, v)| (*v).to_string())
+            })
+        };
+
+        assert_eq!(choice(None, &[]), stderrlog::ColorChoice::Auto);
+        assert_eq!(
+            choice(None, &[("NO_COLOR", "1")]),
+            stderrlog::ColorChoice::Never
+        );
+        // An empty NO_COLOR is ignored.
+        assert_eq!(
+            choice(None, &[("NO_COLOR", "")]),
+            stderrlog::ColorChoice::Auto
+        );
+        assert_eq!(
+            choice(None, &[("CLICOLOR", "0")]),
+            stderrlog::ColorChoice::Never
+        );
+        assert_eq!(
+            choice(None, &[("CLICOLOR_FORCE", "1")]),
+            stderrlog::ColorChoice::Always
+        );
+        assert_eq!(
+            choice(Some(Color::Always), &[("NO_COLOR", "1")]),
+            stderrlog::ColorChoice::Always
+        );
+        assert_eq!(
+            choice(Some(Color::Never), &[("CLICOLOR_FORCE", "1")]),
+            stderrlog::ColorChoice::Never
+        );
+    }
+
+    #[test]
+    fn parse_identifiers() -> Result<()> {
+        assert_eq!(
+            parse_identifier("nixos/nixpkgs", "github.com")?,
+            ("nixos", "nixpkgs")
+        );
+        assert_eq!(
+            parse_identifier("https://github.com/nixos/nixpkgs.git", "github.com")?,
+            ("nixos", "nixpkgs")
+        );
+        assert_eq!(
+            parse_identifier("github.com/nixos/nixpkgs/", "github.com")?,
+            ("nixos", "nixpkgs")
+        );
+        assert!(parse_identifier("nixpkgs", "github.com").is_err());
+        assert!(parse_identifier("nixos/", "github.com").is_err());
+        assert!(parse_identifier("nixos/nixpkgs/tree/master", "github.com").is_err());
+        assert!(parse_identifier("https://gitlab.com/nixos/nixpkgs", "github.com").is_err());
+        assert_eq!(
+            parse_identifier(
+                "https://bitbucket.org/atlassian/python-bitbucket",
+                "bitbucket.org"
+            )?,
+            ("atlassian", "python-bitbucket")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn suggest_name_on_collision() -> Result<()> {
+        let lock = serde_json::from_str::<Lock>(include_str!("../tests/lon.lock"))?;
+        let mut sources = Sources::from(lock);
+
+        let args = AddGitHubArgs {
+            identifier: "someone/nixpkgs".into(),
+            branch: Some("master".into()),
+            name: None,
+            revision: None,
+            hash_algo: HashAlgoArg::Sha256,
+            frozen: false,
+            path: None,
+            bot: AddBotArgs::default(),
+        };
+        let err = add_github_source(&mut sources, &args, &Config::default())
+            .err()
+            .context("Expected an error")?;
+        assert!(err.to_string().contains("Supply --name someone-nixpkgs"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn accept_both_git_argument_orders() -> Result<()> {
+        let args = AddGitArgs {
+            url: "https://git.lix.systems/lix-project/lix.git".into(),
+            branch: Some("main".into()),
+            ..AddGitArgs::default()
+        }
+        .normalize()?;
+        assert_eq!(args.url, "https://git.lix.systems/lix-project/lix.git");
+        assert_eq!(args.branch.as_deref(), Some("main"));
+        assert_eq!(args.name()?, "lix");
+
+        let args = AddGitArgs {
+            url: "lix".into(),
+            branch: Some("git@github.com:lix-project/lix".into()),
+            legacy_branch: Some("main".into()),
+            ..AddGitArgs::default()
+        }
+        .normalize()?;
+        assert_eq!(args.url, "git@github.com:lix-project/lix");
+        assert_eq!(args.branch.as_deref(), Some("main"));
+        assert_eq!(args.name()?, "lix");
+
+        let args = AddGitArgs {
+            url: "lix".into(),
+            name: Some("lix".into()),
+            branch: Some("https://git.lix.systems/lix-project/lix.git".into()),
+            ..AddGitArgs::default()
+        };
+        assert!(args.normalize().is_err());
+
+        Ok(())
+    }
+}
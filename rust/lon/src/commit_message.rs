@@ -4,26 +4,55 @@ use std::fmt::{self, Write};
 
 pub struct CommitMessage {
     updates: Vec<(String, UpdateSummary)>,
+    /// Names of the sources that were removed because they no longer exist upstream
+    removed: Vec<String>,
+    /// Whether the message consists only of the subject line
+    subject_only: bool,
 }
 
 impl CommitMessage {
     pub fn new() -> Self {
-        Self { updates: vec![] }
+        Self {
+            updates: vec![],
+            removed: vec![],
+            subject_only: false,
+        }
+    }
+
+    /// Leave out the body for repositories that only allow short commit messages.
+    ///
+    /// The body is still available via [`Self::body`], e.g. for the description of a PR.
+    pub fn set_subject_only(&mut self, subject_only: bool) {
+        self.subject_only = subject_only;
     }
 
     pub fn add_summary(&mut self, name: &str, summary: UpdateSummary) {
         self.updates.push((name.into(), summary));
     }
 
+    pub fn add_removed(&mut self, name: &str) {
+        self.removed.push(name.into());
+    }
+
     pub fn is_empty(&self) -> bool {
-        self.updates.is_empty()
+        self.updates.is_empty() && self.removed.is_empty()
+    }
+
+    /// Return the names and summaries of all updates.
+    pub fn updates(&self) -> &[(String, UpdateSummary)] {
+        &self.updates
+    }
+
+    /// Return the names of all removed sources.
+    pub fn removed(&self) -> &[String] {
+        &self.removed
     }
 
     /// Construct the body of the commit message.
     pub fn body(&self) -> std::result::Result<String, fmt::Error> {
         let mut commit_message = String::new();
 
-        if self.updates.len() == 1 {
+        if self.updates.len() == 1 && self.removed.is_empty() {
             let summary = &self.updates[0].1;
 
             writeln!(&mut commit_message)?;
@@ -47,21 +76,53 @@ impl CommitMessage {
                 }
             }
         }
+
+        if !self.removed.is_empty() {
+            writeln!(&mut commit_message)?;
+            writeln!(
+                &mut commit_message,
+                "Removed sources that no longer exist upstream:"
+            )?;
+            for name in &self.removed {
+                writeln!(&mut commit_message, "• {name}")?;
+            }
+        }
         Ok(commit_message)
     }
 
     /// Construct the overview of the rev list from a summary.
     ///
     /// Adds whitespace according to the ident argument.
+    ///
+    /// If the rev list was requested but contains no commits (e.g. because only a tag moved), a
+    /// short note is returned instead of an empty list.
+    ///
+    /// If the rev list doesn't contain all commits of the update, the heading says so.
     fn rev_list_overview(summary: &UpdateSummary, indent: usize) -> Option<String> {
-        summary.rev_list.as_ref().map(|revs| {
+        summary.rev_list.as_ref().map(|rev_list| {
             let prefix = " ".repeat(indent);
-            let revs = revs.revs();
+            let revs = rev_list.revs();
+
+            if revs.is_empty() {
+                return format!("{prefix}No individual commits available");
+            }
+
+            let heading = match (rev_list.is_truncated(), rev_list.total()) {
+                (false, _) => format!("Last {} commits:", revs.len()),
+                (true, Some(total)) => format!("Last {} of {total} commits:", revs.len()),
+                (true, None) => format!("Last {0} of more than {0} commits:", revs.len()),
+            };
 
-            std::iter::once(format!("{prefix}Last {} commits:", revs.len()))
+            std::iter::once(format!("{prefix}{heading}"))
                 .chain(revs.iter().map(|commit| {
+                    let attribution = match (&commit.author, &commit.date) {
+                        (Some(author), Some(date)) => format!(" ({author}, {date})"),
+                        (Some(author), None) => format!(" ({author})"),
+                        (None, Some(date)) => format!(" ({date})"),
+                        (None, None) => String::new(),
+                    };
                     format!(
-                        "\n{prefix}  {} {}",
+                        "\n{prefix}  {}{attribution} {}",
                         commit.revision.short(),
                         commit.message_summary(),
                     )
@@ -76,13 +137,16 @@ impl fmt::Display for CommitMessage {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut commit_message = String::new();
 
-        if self.updates.len() == 1 {
-            let name = &self.updates[0].0;
+        if let ([(name, _)], []) = (self.updates.as_slice(), self.removed.as_slice()) {
             writeln!(&mut commit_message, "lon: update {name}")?;
+        } else if let ([], [name]) = (self.updates.as_slice(), self.removed.as_slice()) {
+            writeln!(&mut commit_message, "lon: remove {name}")?;
         } else {
             writeln!(&mut commit_message, "lon: update")?;
         }
-        write!(&mut commit_message, "{}", self.body()?)?;
+        if !self.subject_only {
+            write!(&mut commit_message, "{}", self.body()?)?;
+        }
         write!(f, "{commit_message}")
     }
 }
@@ -117,10 +181,10 @@ mod tests {
             Revision::new("21386f9d14831b594048e1e4340ac7a300e312d6"),
         );
         let rev_list_git_output = indoc! {"
-            1ba800e readme: reorganize
-            26244f0 readme: add section about bot
-            c67d352 changelog: add entry about bot
-            5de6d54 bot: init
+            1ba800e\tAlice\t2024-05-01\treadme: reorganize
+            26244f0\tAlice\t2024-05-01\treadme: add section about bot
+            c67d352\tBob\t2024-04-30\tchangelog: add entry about bot
+            5de6d54\tBob\t2024-04-29\tbot: init
         "};
         let rev_list = RevList::from_git_output(rev_list_git_output);
         summary.add_rev_list(rev_list);
@@ -151,6 +215,13 @@ mod tests {
         summary
     }
 
+    /// Summary with a rev list that contains no commits
+    fn summary_empty_rev_list() -> UpdateSummary {
+        let mut summary = summary_2();
+        summary.add_rev_list(RevList::from_commits(vec![]));
+        summary
+    }
+
     #[test]
     fn commit_message_single_update() {
         let mut commit_message = CommitMessage::new();
@@ -185,6 +256,48 @@ mod tests {
         expected.assert_eq(&commit_message.to_string());
     }
 
+    #[test]
+    fn commit_message_subject_only() {
+        let mut commit_message = CommitMessage::new();
+        commit_message.add_summary("fake_1", summary_rev_list_1());
+        commit_message.set_subject_only(true);
+
+        assert_eq!(commit_message.to_string(), "lon: update fake_1\n");
+        assert!(
+            commit_message
+                .body()
+                .is_ok_and(|body| body.contains("Last 4 commits:"))
+        );
+    }
+
+    #[test]
+    fn commit_message_removed_sources() {
+        let mut commit_message = CommitMessage::new();
+        commit_message.add_removed("gone");
+
+        let expected = expect![[r#"
+            lon: remove gone
+
+            Removed sources that no longer exist upstream:
+            • gone
+        "#]];
+        expected.assert_eq(&commit_message.to_string());
+
+        commit_message.add_summary("fake_1", summary_1());
+
+        let expected = expect![[r#"
+            lon: update
+
+            • fake_1:
+                043344a1c19619435e2b79cd42de6592308af0aa
+              → 21386f9d14831b594048e1e4340ac7a300e312d6
+
+            Removed sources that no longer exist upstream:
+            • gone
+        "#]];
+        expected.assert_eq(&commit_message.to_string());
+    }
+
     #[test]
     fn commit_message_rev_list_single_update() {
         let mut commit_message = CommitMessage::new();
@@ -197,10 +310,10 @@ mod tests {
             → 21386f9d14831b594048e1e4340ac7a300e312d6
 
             Last 4 commits:
-              1ba800e readme: reorganize
-              26244f0 readme: add section about bot
-              c67d352 changelog: add entry about bot
-              5de6d54 bot: init
+              1ba800e (Alice, 2024-05-01) readme: reorganize
+              26244f0 (Alice, 2024-05-01) readme: add section about bot
+              c67d352 (Bob, 2024-04-30) changelog: add entry about bot
+              5de6d54 (Bob, 2024-04-29) bot: init
         "#]];
         expected.assert_eq(&commit_message.to_string());
     }
@@ -219,10 +332,10 @@ mod tests {
               → 21386f9d14831b594048e1e4340ac7a300e312d6
 
               Last 4 commits:
-                1ba800e readme: reorganize
-                26244f0 readme: add section about bot
-                c67d352 changelog: add entry about bot
-                5de6d54 bot: init
+                1ba800e (Alice, 2024-05-01) readme: reorganize
+                26244f0 (Alice, 2024-05-01) readme: add section about bot
+                c67d352 (Bob, 2024-04-30) changelog: add entry about bot
+                5de6d54 (Bob, 2024-04-29) bot: init
 
             • fake_2:
                 6c1da4c913f0edf2835c3cc47c3889c36c05e6ca
@@ -235,4 +348,73 @@ mod tests {
         "#]];
         expected.assert_eq(&commit_message.to_string());
     }
+
+    #[test]
+    fn commit_message_truncated_rev_list() {
+        let mut summary_1 = summary_1();
+        let rev_list_git_output = indoc! {"
+            1ba800e\tAlice\t2024-05-01\treadme: reorganize
+            26244f0\tAlice\t2024-05-01\treadme: add section about bot
+            c67d352\tBob\t2024-04-30\tchangelog: add entry about bot
+        "};
+        summary_1.add_rev_list(RevList::from_git_output(rev_list_git_output).limit(2));
+
+        let mut summary_2 = summary_2();
+        summary_2.add_rev_list(
+            RevList::from_commits(vec![Commit::from_str("6232894", ".gitignore: ignore .env")])
+                .with_total(312),
+        );
+
+        let mut commit_message = CommitMessage::new();
+        commit_message.add_summary("fake_1", summary_1);
+        commit_message.add_summary("fake_2", summary_2);
+
+        let expected = expect![[r#"
+            lon: update
+
+            • fake_1:
+                043344a1c19619435e2b79cd42de6592308af0aa
+              → 21386f9d14831b594048e1e4340ac7a300e312d6
+
+              Last 2 of more than 2 commits:
+                1ba800e (Alice, 2024-05-01) readme: reorganize
+                26244f0 (Alice, 2024-05-01) readme: add section about bot
+
+            • fake_2:
+                ad3bc97747c651e23fbc12c70a5849d3d8e9fdf4
+              → 75962bcd89dcccc9fe125c9ab46377d6cd1ddb00
+
+              Last 1 of 312 commits:
+                6232894 .gitignore: ignore .env
+        "#]];
+        expected.assert_eq(&commit_message.to_string());
+    }
+
+    #[test]
+    fn commit_message_empty_rev_list() {
+        let mut commit_message = CommitMessage::new();
+        commit_message.add_summary("fake_1", summary_rev_list_1());
+        commit_message.add_summary("fake_2", summary_empty_rev_list());
+
+        let expected = expect![[r#"
+            lon: update
+
+            • fake_1:
+                043344a1c19619435e2b79cd42de6592308af0aa
+              → 21386f9d14831b594048e1e4340ac7a300e312d6
+
+              Last 4 commits:
+                1ba800e (Alice, 2024-05-01) readme: reorganize
+                26244f0 (Alice, 2024-05-01) readme: add section about bot
+                c67d352 (Bob, 2024-04-30) changelog: add entry about bot
+                5de6d54 (Bob, 2024-04-29) bot: init
+
+            • fake_2:
+                ad3bc97747c651e23fbc12c70a5849d3d8e9fdf4
+              → 75962bcd89dcccc9fe125c9ab46377d6cd1ddb00
+
+              No individual commits available
+        "#]];
+        expected.assert_eq(&commit_message.to_string());
+    }
 }
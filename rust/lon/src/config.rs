@@ -1,6 +1,39 @@
-use std::env;
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::ErrorKind,
+    path::{Component, Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+use crate::{
+    git::{Signing, SigningFormat, Transport},
+    lock::Lock,
+    lon_nix::{Fetchers, LonNix, NixStyle},
+    time,
+};
+
+/// Whether Lon is forbidden from accessing the network.
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Whether git sources are hashed by Lon itself instead of nix-prefetch-git.
+static BUILTIN_HASH: AtomicBool = AtomicBool::new(false);
+
+/// Whether the narHash of flakes is locked for every source as well.
+static NAR_HASH: AtomicBool = AtomicBool::new(false);
+
+/// The transport git repositories are fetched over if it is forced.
+static GIT_TRANSPORT: Mutex<Option<Transport>> = Mutex::new(None);
+
+/// Local mirrors of git repositories, keyed by the URL of the repository.
+static MIRRORS: Mutex<BTreeMap<String, PathBuf>> = Mutex::new(BTreeMap::new());
 
 /// Read a required environment variable.
 ///
@@ -8,3 +41,539 @@ use anyhow::{Context, Result};
 pub fn required_env(key: &str) -> Result<String> {
     env::var(key).with_context(|| format!("Failed to read {key} from environment"))
 }
+
+/// Read a boolean flag from the environment.
+///
+/// The flag is enabled if the variable is set to `1` or `true`.
+pub fn flag_env(key: &str) -> bool {
+    env::var(key).is_ok_and(|value| matches!(value.as_str(), "1" | "true"))
+}
+
+/// Read a comma separated list from the environment.
+///
+/// Returns `None` if the variable is not set. Empty items are skipped.
+pub fn list_env(key: &str) -> Option<Vec<String>> {
+    let value = env::var(key).ok()?;
+    Some(
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(ToString::to_string)
+            .collect(),
+    )
+}
+
+/// The default timeout for commands (e.g. git and nix) in seconds.
+const DEFAULT_COMMAND_TIMEOUT: u64 = 5 * 60;
+
+/// Read the timeout for commands from `LON_COMMAND_TIMEOUT`.
+///
+/// The value is either a number of seconds or a duration (e.g. `10m`). Zero disables the timeout.
+pub fn command_timeout() -> Result<Duration> {
+    timeout("LON_COMMAND_TIMEOUT", DEFAULT_COMMAND_TIMEOUT)
+}
+
+/// Read the timeout for commands that download a source from `LON_DOWNLOAD_TIMEOUT`.
+///
+/// The value has the same format as for [`command_timeout`]. There is no timeout by default.
+pub fn download_timeout() -> Result<Duration> {
+    timeout("LON_DOWNLOAD_TIMEOUT", 0)
+}
+
+/// Read a timeout in seconds or as a duration from an environment variable.
+fn timeout(variable: &str, default: u64) -> Result<Duration> {
+    let seconds = match env::var(variable) {
+        Ok(value) => match value.parse::<u64>() {
+            Ok(seconds) => seconds,
+            Err(_) => time::parse_duration(&value)
+                .with_context(|| format!("Failed to parse {variable}"))?,
+        },
+        Err(_) => default,
+    };
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Forbid or allow network access for the rest of the invocation.
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+/// Hash git sources without nix-prefetch-git for the rest of the invocation.
+pub fn set_builtin_hash(builtin_hash: bool) {
+    BUILTIN_HASH.store(builtin_hash, Ordering::Relaxed);
+}
+
+/// Return whether git sources are hashed without nix-prefetch-git.
+pub fn builtin_hash() -> bool {
+    BUILTIN_HASH.load(Ordering::Relaxed)
+}
+
+/// Lock the narHash of flakes alongside the hash for the rest of the invocation.
+pub fn set_nar_hash(nar_hash: bool) {
+    NAR_HASH.store(nar_hash, Ordering::Relaxed);
+}
+
+/// Return whether the narHash of flakes is locked alongside the hash.
+pub fn nar_hash() -> bool {
+    NAR_HASH.load(Ordering::Relaxed)
+}
+
+/// Force the transport git repositories are fetched over for the rest of the invocation.
+pub fn set_git_transport(transport: Option<Transport>) {
+    if let Ok(mut git_transport) = GIT_TRANSPORT.lock() {
+        *git_transport = transport;
+    }
+}
+
+/// Return the transport git repositories are fetched over if it is forced.
+pub fn git_transport() -> Option<Transport> {
+    GIT_TRANSPORT.lock().ok().and_then(|transport| *transport)
+}
+
+/// Read the history of git repositories from local mirrors for the rest of the invocation.
+pub fn set_mirrors(mirrors: BTreeMap<String, PathBuf>) {
+    if let Ok(mut current) = MIRRORS.lock() {
+        *current = mirrors
+            .into_iter()
+            .map(|(url, path)| (mirror_key(&url).into(), path))
+            .collect();
+    }
+}
+
+/// Return the path to the local mirror of a git repository if one is configured.
+pub fn mirror(url: &str) -> Option<PathBuf> {
+    MIRRORS
+        .lock()
+        .ok()
+        .and_then(|mirrors| mirrors.get(mirror_key(url)).cloned())
+}
+
+/// Normalize the URL of a repository so that `.git` and trailing slashes don't matter.
+fn mirror_key(url: &str) -> &str {
+    let url = url.trim_end_matches('/');
+    url.strip_suffix(".git").unwrap_or(url)
+}
+
+/// Fail if Lon is in offline mode.
+///
+/// Call this before every operation that accesses the network so that it fails fast instead of
+/// running into a timeout.
+pub fn ensure_online(action: &str) -> Result<()> {
+    if OFFLINE.load(Ordering::Relaxed) {
+        bail!("Cannot {action} in offline mode");
+    }
+    Ok(())
+}
+
+/// Defaults read from lon.toml.
+///
+/// Every key is optional. Command line arguments and environment variables take precedence over
+/// the values in this file.
+#[derive(Default, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Config {
+    /// The branch to track when adding a source without supplying one
+    pub default_branch: Option<String>,
+    /// The fetchers lon.nix uses
+    #[serde(default)]
+    pub fetchers: Fetchers,
+    /// The form of the expression lon.nix evaluates to
+    #[serde(default)]
+    pub nix_style: NixStyle,
+    /// Whether to lock the narHash of flakes alongside the hash of every source
+    #[serde(default)]
+    pub nar_hash: bool,
+    /// Path to the lock file relative to the directory
+    lock_file: Option<PathBuf>,
+    /// Path to the generated Nix file relative to the directory
+    nix_file: Option<PathBuf>,
+    /// Local mirrors of git repositories, keyed by the URL of the repository
+    #[serde(default)]
+    mirrors: BTreeMap<String, PathBuf>,
+    #[serde(default)]
+    pub signing: SigningConfig,
+    #[serde(default)]
+    pub bot: BotConfig,
+}
+
+/// Defaults for signing commits made by Lon.
+#[derive(Default, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SigningConfig {
+    pub enable: Option<bool>,
+    pub format: Option<SigningFormat>,
+    pub key: Option<String>,
+}
+
+/// Defaults for the bot.
+#[derive(Default, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BotConfig {
+    pub labels: Option<Vec<String>>,
+    pub reviewers: Option<Vec<String>>,
+    pub assignees: Option<Vec<String>>,
+    pub draft: Option<bool>,
+    pub list_commits: Option<usize>,
+    pub user_name: Option<String>,
+    pub user_email: Option<String>,
+    pub jobs: Option<usize>,
+}
+
+impl Config {
+    /// The keys that are understood, including those of nested tables.
+    const KNOWN_KEYS: &[&str] = &[
+        "default-branch",
+        "fetchers",
+        "nix-style",
+        "nar-hash",
+        "lock-file",
+        "nix-file",
+        "mirrors",
+        "signing",
+        "signing.enable",
+        "signing.format",
+        "signing.key",
+        "bot",
+        "bot.labels",
+        "bot.reviewers",
+        "bot.assignees",
+        "bot.draft",
+        "bot.list-commits",
+        "bot.user-name",
+        "bot.user-email",
+        "bot.jobs",
+    ];
+
+    pub fn path(directory: impl AsRef<Path>) -> PathBuf {
+        directory.as_ref().join("lon.toml")
+    }
+
+    /// Find the directory containing lon.toml or lon.lock.
+    ///
+    /// Walks upward from `start` like git does to find `.git` so that Lon can be run from a
+    /// subdirectory. The nearest directory wins. If none is found, `start` is returned.
+    ///
+    /// A lock file that lon.toml of a directory further up configures via `lock-file` (e.g.
+    /// `nix/lon.lock`) belongs to that directory and doesn't make its own directory a Lon
+    /// directory.
+    pub fn find_directory(start: &Path) -> PathBuf {
+        let configured_lock_paths = start
+            .ancestors()
+            .filter(|dir| Self::path(dir).is_file())
+            .filter_map(|dir| Some(Self::read(dir).ok()?.lock_path(dir)))
+            .collect::<Vec<_>>();
+        let found = start
+            .ancestors()
+            .filter(|dir| {
+                let lock_path = Self::default().lock_path(dir);
+                Self::path(dir).is_file()
+                    || (lock_path.is_file() && !configured_lock_paths.contains(&lock_path))
+            })
+            .collect::<Vec<_>>();
+
+        let Some(nearest) = found.first() else {
+            return start.into();
+        };
+        if found.len() > 1 {
+            log::warn!(
+                "Found multiple Lon directories: {}. Using {}",
+                found
+                    .iter()
+                    .map(|dir| dir.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                nearest.display()
+            );
+        }
+        if *nearest != start {
+            log::info!("Using {}", nearest.display());
+        }
+        nearest.to_path_buf()
+    }
+
+    /// Read the config from lon.toml in the directory.
+    ///
+    /// If the file doesn't exist, the default config is returned.
+    pub fn read(directory: impl AsRef<Path>) -> Result<Self> {
+        let path = Self::path(directory);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content =
+            fs::read_to_string(&path).with_context(|| format!("Failed to read {path:?}"))?;
+        Self::from_toml(&content).with_context(|| format!("Failed to parse {path:?}"))
+    }
+
+    /// Set the style of lon.nix in lon.toml in the directory, creating the file if necessary.
+    ///
+    /// The key is prepended so that it doesn't end up in a table. Comments and formatting of the
+    /// file are kept. Fails if it already sets another style.
+    pub fn write_nix_style(directory: impl AsRef<Path>, style: NixStyle) -> Result<()> {
+        let path = Self::path(directory);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err).with_context(|| format!("Failed to read {path:?}")),
+        };
+        let table = toml::from_str::<toml::Table>(&content)
+            .with_context(|| format!("Failed to parse {path:?}"))?;
+
+        match table.get("nix-style") {
+            Some(toml::Value::String(existing)) if existing == style.name() => return Ok(()),
+            Some(existing) => {
+                bail!("{path:?} already sets nix-style = {existing}. Change it there")
+            }
+            None => {}
+        }
+
+        fs::write(
+            &path,
+            format!("nix-style = \"{}\"\n{content}", style.name()),
+        )
+        .with_context(|| format!("Failed to write {path:?}"))
+    }
+
+    fn from_toml(content: &str) -> Result<Self> {
+        let table = toml::from_str::<toml::Table>(content)?;
+        for key in unknown_keys(&table, "") {
+            log::warn!("Ignoring unknown key {key} in lon.toml");
+        }
+        let config = table.try_into::<Self>()?;
+        for (key, path) in [
+            ("lock-file", &config.lock_file),
+            ("nix-file", &config.nix_file),
+        ] {
+            if let Some(path) = path {
+                ensure_inside(key, path)?;
+            }
+        }
+        Ok(config)
+    }
+
+    /// Return how to sign commits or `None` if they shouldn't be signed.
+    ///
+    /// Signing is enabled by `sign` (i.e. the command line), `LON_SIGN_COMMITS`, or lon.toml. The
+    /// format and key are read from `LON_SIGNING_FORMAT` and `LON_SIGNING_KEY` or lon.toml.
+    pub fn signing(&self, sign: bool) -> Result<Option<Signing>> {
+        let enabled = sign
+            || if env::var("LON_SIGN_COMMITS").is_ok() {
+                flag_env("LON_SIGN_COMMITS")
+            } else {
+                self.signing.enable.unwrap_or(false)
+            };
+        if !enabled {
+            return Ok(None);
+        }
+
+        let format = match env::var("LON_SIGNING_FORMAT") {
+            Ok(format) => Some(SigningFormat::parse(&format)?),
+            Err(_) => self.signing.format,
+        };
+        let key = env::var("LON_SIGNING_KEY")
+            .ok()
+            .or(self.signing.key.clone());
+
+        Ok(Some(Signing { format, key }))
+    }
+
+    /// Return the local mirrors of git repositories.
+    ///
+    /// Relative paths are resolved against the directory.
+    pub fn mirrors(&self, directory: impl AsRef<Path>) -> BTreeMap<String, PathBuf> {
+        self.mirrors
+            .iter()
+            .map(|(url, path)| (url.clone(), directory.as_ref().join(path)))
+            .collect()
+    }
+
+    /// Return the path to the lock file relative to the directory.
+    pub fn lock_file(&self) -> &Path {
+        self.lock_file
+            .as_deref()
+            .unwrap_or(Path::new(Lock::FILENAME))
+    }
+
+    /// Return the path to the generated Nix file relative to the directory.
+    pub fn nix_file(&self) -> &Path {
+        self.nix_file
+            .as_deref()
+            .unwrap_or(Path::new(LonNix::FILENAME))
+    }
+
+    /// Return the path to the lock file inside the directory.
+    pub fn lock_path(&self, directory: impl AsRef<Path>) -> PathBuf {
+        directory.as_ref().join(self.lock_file())
+    }
+
+    /// Return the path to the generated Nix file inside the directory.
+    pub fn nix_path(&self, directory: impl AsRef<Path>) -> PathBuf {
+        directory.as_ref().join(self.nix_file())
+    }
+}
+
+/// Ensure that a configured path points to a file inside the directory.
+fn ensure_inside(key: &str, path: &Path) -> Result<()> {
+    if path.as_os_str().is_empty()
+        || !path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    {
+        bail!("{key} must be a relative path inside the directory, found {path:?}");
+    }
+    Ok(())
+}
+
+/// Find all keys in the table that Lon doesn't understand.
+fn unknown_keys(table: &toml::Table, prefix: &str) -> Vec<String> {
+    let mut unknown = Vec::new();
+    for (key, value) in table {
+        let key = format!("{prefix}{key}");
+        if !Config::KNOWN_KEYS.contains(&key.as_str()) {
+            unknown.push(key);
+        } else if let toml::Value::Table(table) = value
+            // The keys of mirrors are arbitrary URLs.
+            && key != "mirrors"
+        {
+            unknown.extend(unknown_keys(table, &format!("{key}.")));
+        }
+    }
+    unknown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+    use tempfile::tempdir;
+
+    #[test]
+    fn find_nearest_directory() -> Result<()> {
+        let tmpdir = tempdir()?;
+        let outer = tmpdir.path();
+        let inner = outer.join("inner");
+        let nested = inner.join("a/b");
+        fs::create_dir_all(&nested)?;
+
+        assert_eq!(Config::find_directory(&nested), nested);
+
+        fs::write(outer.join("lon.lock"), "")?;
+        assert_eq!(Config::find_directory(&nested), outer);
+
+        fs::write(inner.join("lon.toml"), "")?;
+        assert_eq!(Config::find_directory(&nested), inner);
+        assert_eq!(Config::find_directory(outer), outer);
+
+        Ok(())
+    }
+
+    #[test]
+    fn find_directory_with_custom_lock_file() -> Result<()> {
+        let tmpdir = tempdir()?;
+        let root = tmpdir.path();
+        let nix = root.join("nix");
+        fs::create_dir_all(&nix)?;
+        fs::write(root.join("lon.toml"), r#"lock-file = "nix/lon.lock""#)?;
+        fs::write(nix.join("lon.lock"), "")?;
+
+        assert_eq!(Config::find_directory(&nix), root);
+        assert_eq!(Config::find_directory(root), root);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_config() -> Result<()> {
+        let config = Config::from_toml(indoc! {r#"
+            default-branch = "main"
+            fetchers = "nixpkgs"
+            nar-hash = true
+            lock-file = "nix/lon.lock"
+
+            [mirrors]
+            "https://github.com/nixos/nixpkgs.git" = "/srv/mirrors/nixpkgs.git"
+
+            [signing]
+            enable = true
+            format = "ssh"
+
+            [bot]
+            labels = ["dependencies"]
+            list-commits = 20
+        "#})?;
+
+        assert_eq!(
+            config,
+            Config {
+                default_branch: Some("main".into()),
+                fetchers: Fetchers::Nixpkgs,
+                nix_style: NixStyle::Attrset,
+                nar_hash: true,
+                lock_file: Some("nix/lon.lock".into()),
+                nix_file: None,
+                mirrors: BTreeMap::from([(
+                    "https://github.com/nixos/nixpkgs.git".into(),
+                    "/srv/mirrors/nixpkgs.git".into(),
+                )]),
+                signing: SigningConfig {
+                    enable: Some(true),
+                    format: Some(SigningFormat::Ssh),
+                    key: None,
+                },
+                bot: BotConfig {
+                    labels: Some(vec!["dependencies".into()]),
+                    list_commits: Some(20),
+                    ..BotConfig::default()
+                },
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn find_unknown_keys() -> Result<()> {
+        let table = toml::from_str::<toml::Table>(indoc! {r#"
+            default-branch = "main"
+            prefetcher = "nix"
+
+            [mirrors]
+            "https://github.com/nixos/nixpkgs" = "/srv/mirrors/nixpkgs.git"
+
+            [bot]
+            labels = []
+            colour = "red"
+        "#})?;
+
+        assert_eq!(unknown_keys(&table, ""), vec!["bot.colour", "prefetcher"]);
+        Ok(())
+    }
+
+    #[test]
+    fn look_up_mirrors() {
+        set_mirrors(BTreeMap::from([(
+            "https://github.com/nixos/nixpkgs.git".into(),
+            "/srv/mirrors/nixpkgs.git".into(),
+        )]));
+
+        let expected = Some(PathBuf::from("/srv/mirrors/nixpkgs.git"));
+        assert_eq!(mirror("https://github.com/nixos/nixpkgs.git"), expected);
+        assert_eq!(mirror("https://github.com/nixos/nixpkgs/"), expected);
+        assert_eq!(
+            mirror("https://github.com/nix-community/lanzaboote.git"),
+            None
+        );
+    }
+
+    #[test]
+    fn configure_files() -> Result<()> {
+        let config = Config::from_toml(r#"nix-file = "nix/sources.nix""#)?;
+        assert_eq!(config.lock_file(), Path::new("lon.lock"));
+        assert_eq!(config.nix_path("/repo"), Path::new("/repo/nix/sources.nix"));
+
+        assert!(Config::from_toml(r#"lock-file = "/etc/lon.lock""#).is_err());
+        assert!(Config::from_toml(r#"lock-file = "../lon.lock""#).is_err());
+        assert!(Config::from_toml(r#"nix-file = "./lon.nix""#).is_err());
+        Ok(())
+    }
+}
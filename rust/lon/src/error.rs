@@ -0,0 +1,99 @@
+//! Errors of the core operations that callers may need to tell apart.
+//!
+//! They are returned wrapped in [`anyhow::Error`] like all other errors and can be recovered via
+//! [`anyhow::Error::downcast_ref`]. Their messages are the ones shown to the user.
+
+use nix_compat::nixhash::NixHash;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum LonError {
+    /// The lock file doesn't contain a source with this name.
+    #[error("Source {0} doesn't exist")]
+    SourceNotFound(String),
+    /// A remote repository couldn't be queried.
+    #[error("Failed to reach {url}")]
+    RemoteUnreachable {
+        url: String,
+        #[source]
+        source: anyhow::Error,
+    },
+    /// A local repository doesn't exist (anymore).
+    #[error("The repository {url} doesn't exist")]
+    RepositoryNotFound {
+        url: String,
+        #[source]
+        source: anyhow::Error,
+    },
+    /// A remote repository doesn't exist or isn't accessible.
+    ///
+    /// Forges answer for a private repository with missing or expired credentials exactly like
+    /// for one that doesn't exist, so this doesn't prove that the repository is gone.
+    #[error("The repository {url} doesn't exist or isn't accessible with the current credentials")]
+    RepositoryInaccessible {
+        url: String,
+        #[source]
+        source: anyhow::Error,
+    },
+    /// A remote repository doesn't contain a reference (anymore), e.g. a deleted branch.
+    #[error("The repository {url} doesn't contain the reference {reference}")]
+    RefNotFound { url: String, reference: String },
+    /// A reference matches more than one reference of a remote repository.
+    #[error("The reference {0} is ambiguous and points to multiple revisions")]
+    AmbiguousRef(String),
+    /// Nix failed to fetch a source to calculate its hash.
+    #[error("Failed to prefetch {url}\n{stderr}")]
+    PrefetchFailed { url: String, stderr: String },
+    /// A locked revision was fetched again and its content no longer matches the locked hash.
+    #[error("Hash mismatch for {url}: expected {expected}, got {actual}")]
+    HashMismatch {
+        url: String,
+        expected: NixHash,
+        actual: NixHash,
+    },
+}
+
+impl LonError {
+    /// Return whether the error shows that a source no longer exists upstream.
+    ///
+    /// Failures that may be transient (e.g. an unreachable server) never do. Neither does a remote
+    /// repository that isn't accessible because it may only be private.
+    pub fn is_gone(&self) -> bool {
+        matches!(
+            self,
+            Self::RepositoryNotFound { .. } | Self::RefNotFound { .. }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::{Context, Result, bail};
+
+    fn find(name: &str) -> Result<()> {
+        bail!(LonError::SourceNotFound(name.into()))
+    }
+
+    #[test]
+    fn recover_error_kind() -> Result<()> {
+        let err = find("nixpkgs").err().context("Expected an error")?;
+        assert_eq!(err.to_string(), "Source nixpkgs doesn't exist");
+        assert!(matches!(
+            err.downcast_ref::<LonError>(),
+            Some(LonError::SourceNotFound(name)) if name == "nixpkgs"
+        ));
+
+        let err = anyhow::Error::from(LonError::RemoteUnreachable {
+            url: "https://example.com/repo.git".into(),
+            source: anyhow::anyhow!("git ls-remote failed with exit code 128"),
+        });
+        assert_eq!(
+            format!("{err:#}"),
+            "Failed to reach https://example.com/repo.git: git ls-remote failed with exit code 128"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,12 @@
+pub mod niv;
+
+use anyhow::Result;
+
+use crate::sources::Sources;
+
+/// A trait for lock files that can be created from Lon `Sources`.
+///
+/// This is the inverse of `Convertible` and allows moving back to other tools.
+pub trait Exportable: Sized {
+    fn export(sources: &Sources) -> Result<Self>;
+}
@@ -0,0 +1,251 @@
+use std::{collections::BTreeMap, io::Write};
+
+use anyhow::{Context, Result, bail};
+use nix_compat::nixhash::{HashAlgo, NixHash};
+use serde::Serialize;
+
+use crate::{
+    export::Exportable,
+    lock::{self, v1},
+    sources::{RepositoryUrl, Sources},
+};
+
+#[derive(Debug, Serialize)]
+pub struct LockFile(BTreeMap<String, Package>);
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Package {
+    Git {
+        branch: String,
+        repo: String,
+        rev: String,
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        submodules: bool,
+    },
+    Tarball {
+        branch: String,
+        owner: String,
+        repo: String,
+        rev: String,
+        sha256: String,
+        url: String,
+        url_template: String,
+    },
+    File {
+        sha256: String,
+        url: String,
+    },
+    Local {
+        path: String,
+    },
+    /// A tarball that isn't derived from a repository.
+    #[serde(rename = "tarball")]
+    UrlTarball {
+        sha256: String,
+        url: String,
+    },
+}
+
+impl LockFile {
+    /// Write the lock file as JSON in the same format niv uses.
+    pub fn to_writer(&self, mut writer: impl Write) -> Result<()> {
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+        let mut serializer = serde_json::Serializer::with_formatter(&mut writer, formatter);
+        self.serialize(&mut serializer)
+            .context("Failed to serialize Niv lock file")?;
+        writer.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+impl Exportable for LockFile {
+    fn export(sources: &Sources) -> Result<Self> {
+        let lock::Lock::V1(lock) = sources.clone().into_latest_lock();
+
+        let mut packages = BTreeMap::new();
+
+        for (name, source) in lock.sources {
+            log::info!("Exporting {name}...");
+
+            let (path, package) = match source {
+                v1::Source::Git(source) => (
+                    source.path,
+                    Package::Git {
+                        branch: source.branch,
+                        repo: source.url,
+                        rev: source.revision,
+                        submodules: source.fetch_options.submodules,
+                    },
+                ),
+                v1::Source::GitHub(source) => (
+                    source.path,
+                    Package::Tarball {
+                        branch: source.branch,
+                        owner: source.owner,
+                        repo: source.repo,
+                        sha256: nixbase32_sha256(&name, &source.hash)?,
+                        url: source.url,
+                        url_template: "https://github.com/<owner>/<repo>/archive/<rev>.tar.gz"
+                            .into(),
+                        rev: source.revision,
+                    },
+                ),
+                v1::Source::Bitbucket(source) => (
+                    source.path,
+                    Package::Tarball {
+                        branch: source.branch,
+                        owner: source.workspace,
+                        repo: source.repo,
+                        sha256: nixbase32_sha256(&name, &source.hash)?,
+                        url: source.url,
+                        url_template: "https://bitbucket.org/<owner>/<repo>/get/<rev>.tar.gz"
+                            .into(),
+                        rev: source.revision,
+                    },
+                ),
+                v1::Source::Forgejo(source) => (
+                    source.path,
+                    Package::Tarball {
+                        branch: source.branch,
+                        owner: source.owner,
+                        repo: source.repo,
+                        sha256: nixbase32_sha256(&name, &source.hash)?,
+                        url: source.url,
+                        url_template: format!(
+                            "https://{}/<owner>/<repo>/archive/<rev>.tar.gz",
+                            source.host
+                        ),
+                        rev: source.revision,
+                    },
+                ),
+                v1::Source::Archive(source) => {
+                    (source.path.clone(), archive_package(&name, source)?)
+                }
+                v1::Source::GitHubRelease(source) => (None, release_package(&name, source)?),
+                v1::Source::Flake(_) => {
+                    log::warn!("Niv doesn't support flake references. Skipping {name}");
+                    continue;
+                }
+                // Niv can't resolve channels, so only the locked release is exported.
+                v1::Source::Channel(source) => (
+                    None,
+                    Package::UrlTarball {
+                        sha256: nixbase32_sha256(&name, &source.hash)?,
+                        url: source.url,
+                    },
+                ),
+                v1::Source::Url(source) => {
+                    let sha256 = nixbase32_sha256(&name, &source.hash)?;
+                    let package = if matches!(source.fetch_type, v1::FetchType::Tarball) {
+                        Package::UrlTarball {
+                            sha256,
+                            url: source.url,
+                        }
+                    } else {
+                        Package::File {
+                            sha256,
+                            url: source.url,
+                        }
+                    };
+                    (None, package)
+                }
+                v1::Source::Path(source) => (
+                    None,
+                    Package::Local {
+                        path: source.local_path,
+                    },
+                ),
+            };
+
+            if let Some(path) = path {
+                log::warn!("Niv doesn't support subdirectories. Ignoring path {path} of {name}");
+            }
+
+            packages.insert(name, package);
+        }
+
+        Ok(Self(packages))
+    }
+}
+
+/// Export an archive source as a tarball whose URL template uses niv's placeholders.
+fn archive_package(name: &str, source: v1::ArchiveSource) -> Result<Package> {
+    let repository = RepositoryUrl::parse(&source.repository)?;
+    // Niv only knows the owner, repo and revision as placeholders.
+    let url_template = source
+        .archive_url
+        .replace("{host}", repository.host)
+        .replace("{path}", repository.path)
+        .replace("{owner}", "<owner>")
+        .replace("{repo}", "<repo>")
+        .replace("{rev}", "<rev>");
+    Ok(Package::Tarball {
+        branch: source.branch,
+        owner: repository.owner.into(),
+        repo: repository.repo.into(),
+        sha256: nixbase32_sha256(name, &source.hash)?,
+        url: source.url,
+        url_template,
+        rev: source.revision,
+    })
+}
+
+/// Export a GitHub release asset as a tarball if it is unpacked and as a file otherwise.
+fn release_package(name: &str, source: v1::GitHubReleaseSource) -> Result<Package> {
+    let sha256 = nixbase32_sha256(name, &source.hash)?;
+    if !matches!(source.fetch_type, v1::FetchType::Tarball) {
+        return Ok(Package::File {
+            sha256,
+            url: source.url,
+        });
+    }
+    Ok(Package::Tarball {
+        sha256,
+        // The URL of an asset can't be derived from the revision.
+        url_template: source.url.clone(),
+        url: source.url,
+        branch: source.tag,
+        owner: source.owner,
+        repo: source.repo,
+        rev: source.revision,
+    })
+}
+
+/// Encode a SHA-256 hash in the Nix base32 format niv expects.
+fn nixbase32_sha256(name: &str, hash: &NixHash) -> Result<String> {
+    if hash.algo() != HashAlgo::Sha256 {
+        bail!("Niv only supports SHA-256 hashes but {name} is locked with {hash}");
+    }
+    Ok(nix_compat::nixbase32::encode(hash.digest_as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_niv_lock_file() -> Result<()> {
+        let sources = Sources::read("tests/lon.lock")?;
+        let lock_file = LockFile::export(&sources)?;
+
+        let mut json = Vec::new();
+        lock_file.to_writer(&mut json)?;
+        let value = serde_json::from_slice::<serde_json::Value>(&json)?;
+
+        assert_eq!(value["lanzaboote"]["type"], "git");
+        assert_eq!(
+            value["lanzaboote"]["repo"],
+            "git@github.com:nix-community/lanzaboote.git"
+        );
+        assert_eq!(value["nixpkgs"]["type"], "tarball");
+        assert_eq!(value["nixpkgs"]["owner"], "nixos");
+        assert_eq!(
+            value["nixpkgs"]["sha256"],
+            "15m79nynl05lrirfq6zfaqk05smxpdb83almh29q4qpf2mbd0p47"
+        );
+        assert_eq!(value["python-bitbucket"]["owner"], "atlassian");
+
+        Ok(())
+    }
+}
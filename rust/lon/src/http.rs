@@ -1,3 +1,77 @@
+mod channel;
 mod github;
 
-pub use github::GitHubRepoApi;
+use std::{env, fs, path::Path};
+
+use anyhow::{Context, Result, bail};
+use reqwest::{
+    Certificate,
+    blocking::{Client, ClientBuilder},
+};
+
+pub use channel::resolve_channel;
+pub use github::{GitHubRepoApi, find_token as find_github_token};
+
+/// The user agent of all HTTP requests unless overridden via `LON_USER_AGENT`.
+const DEFAULT_USER_AGENT: &str = concat!("lon/", env!("CARGO_PKG_VERSION"));
+
+/// Create a builder for an HTTP client that is configured from the environment.
+///
+/// All HTTP clients should be built from this so that they share the same configuration. Proxies
+/// are read from the standard environment variables (e.g. `HTTPS_PROXY` and `NO_PROXY`) by
+/// reqwest. Additional root certificates are read from the PEM bundle at `LON_CA_BUNDLE`.
+pub fn client_builder() -> Result<ClientBuilder> {
+    let mut builder = Client::builder().user_agent(user_agent(env::var("LON_USER_AGENT").ok()));
+
+    if let Some(path) = env::var_os("LON_CA_BUNDLE") {
+        for certificate in root_certificates(Path::new(&path))? {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    Ok(builder)
+}
+
+/// Return the user agent to send, preferring a non-empty override.
+fn user_agent(user_agent: Option<String>) -> String {
+    user_agent
+        .filter(|user_agent| !user_agent.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_USER_AGENT.into())
+}
+
+/// Read the root certificates from a PEM bundle.
+fn root_certificates(path: &Path) -> Result<Vec<Certificate>> {
+    let pem = fs::read(path).with_context(|| format!("Failed to read the CA bundle {path:?}"))?;
+    let certificates = Certificate::from_pem_bundle(&pem)
+        .with_context(|| format!("Failed to parse the CA bundle {path:?}"))?;
+    if certificates.is_empty() {
+        bail!("The CA bundle {path:?} doesn't contain any certificates");
+    }
+    Ok(certificates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn override_user_agent() {
+        assert_eq!(
+            user_agent(None),
+            format!("lon/{}", env!("CARGO_PKG_VERSION"))
+        );
+        assert_eq!(user_agent(Some(String::new())), DEFAULT_USER_AGENT);
+        assert_eq!(user_agent(Some("acme-ci/1.0".into())), "acme-ci/1.0");
+    }
+
+    #[test]
+    fn reject_empty_ca_bundle() -> Result<()> {
+        let tmpdir = tempfile::tempdir()?;
+        let path = tmpdir.path().join("ca.pem");
+        fs::write(&path, "not a certificate\n")?;
+
+        assert!(root_certificates(&path).is_err());
+        assert!(root_certificates(&tmpdir.path().join("missing.pem")).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use anyhow::{Context, Result, bail};
+use reqwest::StatusCode;
+
+use crate::{config, git::Revision};
+
+const CHANNELS_URL: &str = "https://channels.nixos.org";
+
+/// The release a Nix channel currently points to.
+pub struct ChannelRelease {
+    /// URL of the nixexprs tarball of the release
+    pub url: String,
+    /// Revision of nixpkgs the release was built from
+    pub revision: Revision,
+}
+
+/// Resolve the release a channel (e.g. nixos-24.05) currently points to.
+///
+/// The nixexprs tarball of a channel redirects to the one of its current release. Unlike the
+/// channel URL, the URL of the release never changes its content.
+pub fn resolve_channel(channel: &str) -> Result<ChannelRelease> {
+    config::ensure_online("resolve a channel")?;
+
+    let client = super::client_builder()?
+        .build()
+        .context("Failed to build the HTTP client")?;
+
+    let channel_url = format!("{CHANNELS_URL}/{channel}/nixexprs.tar.xz");
+    let res = client
+        .head(&channel_url)
+        .send()
+        .with_context(|| format!("Failed to send HEAD request to {channel_url}"))?;
+
+    let status = res.status();
+    if status == StatusCode::NOT_FOUND {
+        bail!("The channel {channel} doesn't exist: {channel_url}")
+    }
+    if !status.is_success() {
+        bail!("Failed to resolve the channel {channel} via {channel_url}: {status}")
+    }
+
+    let url = res.url().to_string();
+    let release_dir = release_dir(&url).with_context(|| {
+        format!("The channel {channel} redirected to {url} instead of the nixexprs of a release")
+    })?;
+
+    let revision_url = format!("{release_dir}/git-revision");
+    let res = client
+        .get(&revision_url)
+        .send()
+        .with_context(|| format!("Failed to send GET request to {revision_url}"))?;
+
+    let status = res.status();
+    if !status.is_success() {
+        bail!("Failed to get the revision of {release_dir}: {status}")
+    }
+
+    let revision = res.text()?.trim().to_string();
+    if !Revision::is_commit_hash(&revision) {
+        bail!("The release {release_dir} has the invalid revision {revision:?}")
+    }
+
+    Ok(ChannelRelease {
+        url,
+        revision: Revision::new(&revision),
+    })
+}
+
+/// Return the directory of a release from the URL of its nixexprs tarball.
+///
+/// The URL of the channel itself is rejected because it means the redirect wasn't followed.
+fn release_dir(url: &str) -> Option<&str> {
+    if url.starts_with(CHANNELS_URL) {
+        return None;
+    }
+    url.strip_suffix("/nixexprs.tar.xz")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_release_dir() {
+        assert_eq!(
+            release_dir(
+                "https://releases.nixos.org/nixos/24.05/nixos-24.05.7376.b134951a4c9f/nixexprs.tar.xz"
+            ),
+            Some("https://releases.nixos.org/nixos/24.05/nixos-24.05.7376.b134951a4c9f")
+        );
+        assert_eq!(
+            release_dir("https://channels.nixos.org/nixos-24.05/nixexprs.tar.xz"),
+            None
+        );
+        assert_eq!(
+            release_dir("https://releases.nixos.org/nixos/24.05/nixos-24.05.7376.b134951a4c9f"),
+            None
+        );
+    }
+}
@@ -1,14 +1,24 @@
+use std::{process::Command, sync::OnceLock};
+
 use anyhow::{Context, Result, bail};
 use reqwest::{
+    StatusCode,
     blocking::Client,
     header::{self, HeaderName, HeaderValue},
 };
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, de::IgnoredAny};
 
-use crate::git::{self, RevList};
+use crate::{
+    config::ensure_online,
+    git::{self, CommitFilter, RevList},
+    process::CommandExt,
+};
 
 const GITHUB_API: &str = "https://api.github.com";
 
+/// The OAuth scopes that allow opening Pull Requests. One of them is sufficient.
+const PULL_REQUEST_SCOPES: &[&str] = &["repo", "public_repo"];
+
 #[derive(Deserialize)]
 struct Repository {
     default_branch: String,
@@ -35,20 +45,115 @@ struct Labels {
     labels: Vec<String>,
 }
 
+#[derive(Serialize)]
+struct Issue<'a> {
+    title: &'a str,
+    body: &'a str,
+    labels: &'a [String],
+}
+
+#[derive(Serialize)]
+struct IssueUpdate<'a> {
+    body: &'a str,
+}
+
+#[derive(Deserialize)]
+struct IssueResponse {
+    html_url: String,
+    number: i64,
+    title: String,
+    /// Only set if the issue is a Pull Request
+    pull_request: Option<IgnoredAny>,
+}
+
+#[derive(Deserialize)]
+struct Branch {
+    commit: BranchCommit,
+}
+
+#[derive(Deserialize)]
+struct BranchCommit {
+    sha: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub assets: Vec<ReleaseAsset>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReleaseAsset {
+    pub name: String,
+    pub browser_download_url: String,
+}
+
+impl Release {
+    /// Find the single asset whose name matches a glob pattern (e.g. `foo-*.tar.gz`).
+    ///
+    /// Matching by pattern allows the name of the asset to contain the version.
+    pub fn find_asset(&self, pattern: &str) -> Result<&ReleaseAsset> {
+        let matching = self
+            .assets
+            .iter()
+            .filter(|asset| glob_match(pattern, &asset.name))
+            .collect::<Vec<_>>();
+
+        match matching.as_slice() {
+            [asset] => Ok(asset),
+            [] => bail!(
+                "The release {} has no asset matching {pattern}. Available assets: {}",
+                self.tag_name,
+                self.assets
+                    .iter()
+                    .map(|asset| asset.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            _ => bail!(
+                "The pattern {pattern} matches multiple assets of the release {}: {}",
+                self.tag_name,
+                matching
+                    .iter()
+                    .map(|asset| asset.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct CommitComparison {
-    pub commits: Vec<Commit>,
+    pub total_commits: usize,
+    pub commits: Vec<ComparedCommit>,
 }
 
 #[derive(Debug, Deserialize)]
-struct Commit {
+struct ComparedCommit {
     pub sha: String,
     pub commit: CommitDetails,
+    #[serde(default)]
+    pub parents: Vec<CommitParent>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommitParent {
+    pub sha: String,
 }
 
 #[derive(Debug, Deserialize)]
 struct CommitDetails {
     pub message: String,
+    pub author: Option<CommitSignature>,
+    pub committer: Option<CommitSignature>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommitSignature {
+    pub name: String,
+    /// ISO 8601 timestamp
+    pub date: String,
 }
 
 pub struct GitHubRepoApiBuilder {
@@ -70,6 +175,8 @@ impl GitHubRepoApiBuilder {
     }
 
     pub fn build(self) -> Result<GitHubRepoApi> {
+        ensure_online("access the GitHub API")?;
+
         let mut headers = header::HeaderMap::new();
         if let Some(token) = self.token {
             headers.insert(
@@ -88,8 +195,7 @@ impl GitHubRepoApiBuilder {
             HeaderValue::from_static("2022-11-28"),
         );
 
-        let client = Client::builder()
-            .user_agent("LonBot")
+        let client = super::client_builder()?
             .default_headers(headers)
             .build()
             .context("Failed to build the HTTP client")?;
@@ -97,6 +203,7 @@ impl GitHubRepoApiBuilder {
         Ok(GitHubRepoApi {
             client,
             repo_api_url: Self::repo_api_url(&self.repository),
+            repository: OnceLock::new(),
         })
     }
 
@@ -109,6 +216,8 @@ pub struct GitHubRepoApi {
     client: Client,
     /// The URL to the GitHub API of the specific repo
     repo_api_url: String,
+    /// The repository and the OAuth scopes of the token, once they were fetched
+    repository: OnceLock<(Repository, Option<String>)>,
 }
 
 impl GitHubRepoApi {
@@ -138,12 +247,20 @@ impl GitHubRepoApi {
         Ok(())
     }
 
+    /// List the commits between two revisions.
+    ///
+    /// The API lists at most 250 commits. If it doesn't list all of them and merge commits are
+    /// filtered, the total is unknown.
+    ///
+    /// Returns `None` if the first-parent history can't be followed because the newest commit
+    /// isn't listed. The commits have to be listed via git instead.
     pub fn compare_commits(
         &self,
         old_revision: &str,
         new_revision: &str,
         num_commits: usize,
-    ) -> Result<RevList> {
+        filter: CommitFilter,
+    ) -> Result<Option<RevList>> {
         let url = format!(
             "{}/compare/{old_revision}...{new_revision}",
             self.repo_api_url
@@ -164,27 +281,116 @@ impl GitHubRepoApi {
         }
 
         let comparison = res.json::<CommitComparison>()?;
+        let complete = comparison.commits.len() == comparison.total_commits;
+
+        let Some(commits) = filter.apply(
+            comparison.commits,
+            new_revision,
+            |c| &c.sha,
+            |c| c.parents.iter().map(|p| p.sha.as_str()).collect(),
+        ) else {
+            return Ok(None);
+        };
+        let total = match filter {
+            CommitFilter::All => Some(comparison.total_commits),
+            _ if complete => Some(commits.len()),
+            _ => None,
+        };
 
-        let commits = comparison
-            .commits
-            .iter()
-            .take(num_commits)
-            .map(|c| git::Commit::from_str(&c.sha, &c.commit.message));
+        // The API lists the oldest commits first. Sort them like the other forges and `git
+        // rev-list` do before the list is limited.
+        let listed = commits.len();
+        let commits = git::newest_commits(
+            commits,
+            |c| c.commit.committer.as_ref().map_or("", |c| &c.date),
+            listed,
+        );
+        let commits = commits.iter().map(|c| {
+            let commit = git::Commit::from_str(&c.sha, &c.commit.message);
+            match (&c.commit.author, &c.commit.committer) {
+                (Some(author), Some(committer)) => {
+                    // Only keep the date to match the short date format of git.
+                    let date = committer.date.get(..10).unwrap_or(&committer.date);
+                    commit.with_author(&author.name, date)
+                }
+                _ => commit,
+            }
+        });
+
+        let rev_list = RevList::from_commits(commits).limit(num_commits);
+        Ok(Some(match total {
+            Some(total) => rev_list.with_total(total),
+            None => rev_list,
+        }))
+    }
+
+    /// Get the revision a branch currently points to.
+    pub fn branch_revision(&self, branch: &str) -> Result<String> {
+        let url = format!("{}/branches/{branch}", self.repo_api_url);
+
+        let res = self
+            .client
+            .get(&url)
+            .send()
+            .with_context(|| format!("Failed to send GET request to {url}"))?;
+
+        let status = res.status();
+        if status == StatusCode::NOT_FOUND {
+            bail!("The repository doesn't contain the branch {branch} or isn't accessible: {url}")
+        }
+        if !status.is_success() {
+            bail!(
+                "Failed to get branch from {url}: {status}:\n{}",
+                res.text()?
+            )
+        }
+
+        Ok(res.json::<Branch>()?.commit.sha)
+    }
+
+    /// Get the newest release.
+    ///
+    /// Drafts and pre-releases are never returned.
+    pub fn latest_release(&self) -> Result<Release> {
+        self.get_release(&format!("{}/releases/latest", self.repo_api_url))
+    }
+
+    /// Get the release of a tag.
+    pub fn release(&self, tag: &str) -> Result<Release> {
+        self.get_release(&format!("{}/releases/tags/{tag}", self.repo_api_url))
+    }
+
+    fn get_release(&self, url: &str) -> Result<Release> {
+        let res = self
+            .client
+            .get(url)
+            .send()
+            .with_context(|| format!("Failed to send GET request to {url}"))?;
+
+        let status = res.status();
+        if !status.is_success() {
+            bail!(
+                "Failed to get release from {url}: {status}:\n{}",
+                res.text()?
+            )
+        }
 
-        Ok(RevList::from_commits(commits))
+        Ok(res.json::<Release>()?)
     }
 
+    /// Open a Pull Request against the base branch or the default branch of the repository.
     pub fn open_pull_request(
         &self,
         branch: &str,
+        base_branch: Option<&str>,
         title: &str,
         body: Option<String>,
     ) -> Result<PullRequestResponse> {
-        let repository = self.get_repository()?;
+        let (repository, scopes) = self.get_repository()?;
 
         let pull_request = PullRequest {
             head: branch.into(),
-            base: repository.default_branch.clone(),
+            base: base_branch.map_or_else(|| repository.default_branch.clone(), Into::into),
             title: title.into(),
             body,
             maintainer_can_modify: true,
@@ -202,8 +408,9 @@ impl GitHubRepoApi {
         let status = res.status();
         if !status.is_success() {
             bail!(
-                "Failed to open Pull Request at {url}: {status}:\n{}",
-                res.text()?
+                "Failed to open Pull Request at {url}: {status}:\n{}{}",
+                res.text()?,
+                missing_scopes_hint(status, scopes.as_deref())
             )
         }
 
@@ -212,7 +419,114 @@ impl GitHubRepoApi {
         Ok(pull_request_response)
     }
 
-    fn get_repository(&self) -> Result<Repository> {
+    /// Open an issue or update the body of an open issue with the same title.
+    ///
+    /// The API treats Pull Requests as issues as well, so they are skipped when looking for an
+    /// open issue. Returns the URL of the issue.
+    pub fn open_or_update_issue(
+        &self,
+        title: &str,
+        body: &str,
+        labels: &[String],
+    ) -> Result<String> {
+        let url = format!("{}/issues", self.repo_api_url);
+
+        if let Some(issue) = self.find_open_issue(&url, title)? {
+            let url = format!("{url}/{}", issue.number);
+
+            let res = self
+                .client
+                .patch(&url)
+                .json(&IssueUpdate { body })
+                .send()
+                .with_context(|| format!("Failed to send PATCH request to {url}"))?;
+
+            let status = res.status();
+            if !status.is_success() {
+                bail!(
+                    "Failed to update issue at {url}: {status}:\n{}",
+                    res.text()?
+                )
+            }
+
+            return Ok(issue.html_url);
+        }
+
+        let res = self
+            .client
+            .post(&url)
+            .json(&Issue {
+                title,
+                body,
+                labels,
+            })
+            .send()
+            .with_context(|| format!("Failed to send POST request to {url}"))?;
+
+        let status = res.status();
+        if !status.is_success() {
+            bail!("Failed to open issue at {url}: {status}:\n{}", res.text()?)
+        }
+
+        Ok(res.json::<IssueResponse>()?.html_url)
+    }
+
+    /// Find the open issue with a title.
+    ///
+    /// All pages are searched because newer issues and Pull Requests can push it off the first
+    /// page.
+    fn find_open_issue(&self, url: &str, title: &str) -> Result<Option<IssueResponse>> {
+        const PER_PAGE: usize = 100;
+
+        for page in 1.. {
+            let res = self
+                .client
+                .get(url)
+                .query(&[
+                    ("state", "open".to_string()),
+                    ("per_page", PER_PAGE.to_string()),
+                    ("page", page.to_string()),
+                ])
+                .send()
+                .with_context(|| format!("Failed to send GET request to {url}"))?;
+
+            let status = res.status();
+            if !status.is_success() {
+                bail!("Failed to list issues at {url}: {status}:\n{}", res.text()?)
+            }
+
+            let issues = res.json::<Vec<IssueResponse>>()?;
+            let last_page = issues.len() < PER_PAGE;
+
+            if let Some(issue) = issues
+                .into_iter()
+                .find(|issue| issue.pull_request.is_none() && issue.title == title)
+            {
+                return Ok(Some(issue));
+            }
+            if last_page {
+                break;
+            }
+        }
+        Ok(None)
+    }
+
+    /// Get information about the repository.
+    ///
+    /// Also returns the OAuth scopes of the token if GitHub reports them. This is only the case
+    /// for classic personal access tokens.
+    ///
+    /// The repository is only fetched once per instance, so that opening many Pull Requests in a
+    /// single run doesn't query it again for each of them. Failures aren't remembered.
+    fn get_repository(&self) -> Result<&(Repository, Option<String>)> {
+        if let Some(repository) = self.repository.get() {
+            return Ok(repository);
+        }
+        let repository = self.fetch_repository()?;
+        Ok(self.repository.get_or_init(|| repository))
+    }
+
+    fn fetch_repository(&self) -> Result<(Repository, Option<String>)> {
         let url = &self.repo_api_url;
 
         let res = self
@@ -221,16 +535,222 @@ impl GitHubRepoApi {
             .send()
             .with_context(|| format!("Failed to send GET request to {url}"))?;
 
+        let scopes = res
+            .headers()
+            .get("x-oauth-scopes")
+            .and_then(|value| value.to_str().ok())
+            .map(ToString::to_string);
+
         let status = res.status();
         if !status.is_success() {
             bail!(
-                "Failed to get repository information from {url}: {status}:\n{}",
-                res.text()?
+                "Failed to get repository information from {url}: {status}:\n{}{}",
+                res.text()?,
+                missing_scopes_hint(status, scopes.as_deref())
             )
         }
 
         let repository = res.json::<Repository>()?;
 
-        Ok(repository)
+        Ok((repository, scopes))
+    }
+}
+
+/// Find a token for github.com that is already configured for the GitHub CLI or git.
+///
+/// Tries `gh auth token` first and then the git credential helpers. Returns `None` if neither
+/// provides a token.
+pub fn find_token() -> Option<String> {
+    if let Some(token) = gh_auth_token() {
+        log::debug!("Using the token of the GitHub CLI");
+        return Some(token);
+    }
+    if let Some(token) = git_credential_token() {
+        log::debug!("Using the token of the git credential helper");
+        return Some(token);
+    }
+    None
+}
+
+fn gh_auth_token() -> Option<String> {
+    let output = Command::new("gh")
+        .args(["auth", "token", "--hostname", "github.com"])
+        .spawn_captured()
+        .ok()?
+        .wait()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let token = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (!token.is_empty()).then_some(token)
+}
+
+fn git_credential_token() -> Option<String> {
+    // Never ask for a password interactively.
+    let output = Command::new("git")
+        .args(["credential", "fill"])
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .spawn_captured_with_input(b"protocol=https\nhost=github.com\n\n")
+        .ok()?
+        .wait()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    password_from_credential(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Extract the password from the output of `git credential fill`.
+fn password_from_credential(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("password="))
+        .filter(|password| !password.is_empty())
+        .map(ToString::to_string)
+}
+
+/// Explain which scopes are missing from the token after a permission failure.
+///
+/// GitHub responds with 404 instead of 403 to hide private repositories from tokens without
+/// access. Returns an empty string if the failure isn't caused by missing scopes or the scopes
+/// are unknown.
+fn missing_scopes_hint(status: StatusCode, scopes: Option<&str>) -> String {
+    if !matches!(status, StatusCode::FORBIDDEN | StatusCode::NOT_FOUND) {
+        return String::new();
+    }
+    let Some(scopes) = scopes else {
+        return String::new();
+    };
+
+    let scopes = scopes
+        .split(',')
+        .map(str::trim)
+        .filter(|scope| !scope.is_empty())
+        .collect::<Vec<&str>>();
+
+    if scopes
+        .iter()
+        .any(|scope| PULL_REQUEST_SCOPES.contains(scope))
+    {
+        return String::new();
+    }
+
+    format!(
+        "\nThe token has the scopes [{}] but needs one of [{}]",
+        scopes.join(", "),
+        PULL_REQUEST_SCOPES.join(", ")
+    )
+}
+
+/// Match a name against a glob pattern that supports `*` and `?`.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+
+    // Position in the pattern after the last `*` and the position in the name it matched up to.
+    let mut backtrack = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, n));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                n += 1;
+            }
+            Some(c) if *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => {
+                let Some((star_p, star_n)) = backtrack else {
+                    return false;
+                };
+                backtrack = Some((star_p, star_n + 1));
+                p = star_p;
+                n = star_n + 1;
+            }
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_credential_output() {
+        assert_eq!(
+            password_from_credential(
+                "protocol=https\nhost=github.com\nusername=lon\npassword=ghp_secret\n"
+            ),
+            Some("ghp_secret".into())
+        );
+        assert_eq!(
+            password_from_credential("protocol=https\nhost=github.com\npassword=\n"),
+            None
+        );
+        assert_eq!(password_from_credential(""), None);
+    }
+
+    #[test]
+    fn match_asset_names() {
+        assert!(glob_match("foo-*.tar.gz", "foo-1.2.3.tar.gz"));
+        assert!(glob_match(
+            "foo-*-linux-*.tar.gz",
+            "foo-1.2.3-linux-x86_64.tar.gz"
+        ));
+        assert!(glob_match("foo-v?.zip", "foo-v2.zip"));
+        assert!(glob_match("*", "anything"));
+        assert!(!glob_match("foo-*.tar.gz", "foo-1.2.3.tar.gz.sha256"));
+        assert!(!glob_match("foo-*.tar.gz", "bar-1.2.3.tar.gz"));
+        assert!(!glob_match("foo-v?.zip", "foo-v10.zip"));
+    }
+
+    #[test]
+    fn find_release_asset() -> Result<()> {
+        let release = serde_json::from_str::<Release>(
+            r#"{
+                "tag_name": "v1.2.3",
+                "assets": [
+                    { "name": "foo-1.2.3.tar.gz", "browser_download_url": "https://example.com/foo-1.2.3.tar.gz" },
+                    { "name": "foo-1.2.3.tar.gz.sha256", "browser_download_url": "https://example.com/foo-1.2.3.tar.gz.sha256" }
+                ]
+            }"#,
+        )?;
+
+        assert_eq!(
+            release.find_asset("foo-*.tar.gz")?.browser_download_url,
+            "https://example.com/foo-1.2.3.tar.gz"
+        );
+        assert!(release.find_asset("foo-*").is_err());
+        assert!(release.find_asset("bar-*").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn explain_missing_scopes() {
+        assert_eq!(
+            missing_scopes_hint(StatusCode::FORBIDDEN, Some("read:org, gist")),
+            "\nThe token has the scopes [read:org, gist] but needs one of [repo, public_repo]"
+        );
+        assert_eq!(
+            missing_scopes_hint(StatusCode::NOT_FOUND, Some("")),
+            "\nThe token has the scopes [] but needs one of [repo, public_repo]"
+        );
+        assert_eq!(
+            missing_scopes_hint(StatusCode::FORBIDDEN, Some("repo, read:org")),
+            ""
+        );
+        assert_eq!(missing_scopes_hint(StatusCode::FORBIDDEN, None), "");
+        assert_eq!(
+            missing_scopes_hint(StatusCode::UNPROCESSABLE_ENTITY, Some("gist")),
+            ""
+        );
     }
 }
@@ -1,10 +1,12 @@
 use std::{collections::BTreeMap, path::Path};
 
 use anyhow::{Context, Result};
+use nix_compat::nixhash::HashAlgo;
 use serde::Deserialize;
 
 use crate::{
     init::Convertible,
+    nix::GitFetchOptions,
     sources::{GitHubSource, GitSource, Source, Sources},
 };
 
@@ -42,19 +44,26 @@ impl Convertible for LockFile {
                     &package.branch,
                     Some(&package.rev),
                     false,
+                    None,
+                    HashAlgo::Sha256,
                 )?;
 
-                sources.add(name, Source::GitHub(source));
+                let mut source = Source::GitHub(source);
+                source.lock_nar_hash()?;
+                sources.add(name, source);
             } else {
                 let source = GitSource::new(
                     &package.repo,
                     &package.branch,
                     Some(&package.rev),
+                    GitFetchOptions::default(),
                     false,
-                    false,
+                    None,
                 )?;
 
-                sources.add(name, Source::Git(source));
+                let mut source = Source::Git(source);
+                source.lock_nar_hash()?;
+                sources.add(name, source);
             }
         }
 
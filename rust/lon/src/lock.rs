@@ -1,10 +1,11 @@
 use std::{
     fs::File,
-    io::Write,
-    path::{Path, PathBuf},
+    io::{ErrorKind, Write},
+    path::Path,
 };
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use nix_compat::nixhash::NixHash;
 
 use serde::{Deserialize, Serialize};
 
@@ -22,21 +23,27 @@ pub enum Lock {
 }
 
 impl Lock {
-    const FILENAME: &'static str = "lon.lock";
-
-    pub fn read(directory: impl AsRef<Path>) -> Result<Self> {
-        Self::from_file(Self::path(directory))
-    }
-
-    pub fn write(&self, directory: impl AsRef<Path>) -> Result<()> {
-        self.to_file(Self::path(directory))
-    }
+    /// The default name of the lock file.
+    pub const FILENAME: &'static str = "lon.lock";
 
     pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
-        let lock_json = std::fs::read_to_string(path.as_ref())
-            .with_context(|| format!("Failed to read {:?}", path.as_ref()))?;
+        let path = path.as_ref();
+        let lock_json = match std::fs::read_to_string(path) {
+            Ok(lock_json) => lock_json,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                bail!("{path:?} doesn't exist. Run lon init to create it")
+            }
+            Err(err) => return Err(err).with_context(|| format!("Failed to read {path:?}")),
+        };
+
+        validate_hashes(&lock_json).with_context(|| format!("Invalid lock file {path:?}"))?;
 
-        serde_json::from_str(&lock_json).context("Failed to deserialize lock file")
+        serde_json::from_str(&lock_json).map_err(|err| {
+            let region = error_region(&lock_json, err.line(), err.column());
+            anyhow::Error::new(err).context(format!(
+                "Failed to deserialize lock file {path:?}:\n{region}"
+            ))
+        })
     }
 
     pub fn to_file(&self, path: impl AsRef<Path>) -> Result<()> {
@@ -46,10 +53,46 @@ impl Lock {
         file.write_all(b"\n")?;
         Ok(())
     }
+}
 
-    pub fn path(directory: impl AsRef<Path>) -> PathBuf {
-        directory.as_ref().join(Self::FILENAME)
+/// Check that the hashes of all sources can be parsed.
+///
+/// The deserialization would report a malformed hash (e.g. a copy-paste error in a hand-edited
+/// lock file) without naming the source. Content that isn't a lock at all is left to the
+/// deserialization to report.
+fn validate_hashes(lock_json: &str) -> Result<()> {
+    let Ok(serde_json::Value::Object(lock)) = serde_json::from_str(lock_json) else {
+        return Ok(());
+    };
+    let Some(serde_json::Value::Object(sources)) = lock.get("sources") else {
+        return Ok(());
+    };
+    for (name, source) in sources {
+        if let Some(serde_json::Value::String(hash)) = source.get("hash") {
+            NixHash::from_str(hash, None)
+                .with_context(|| format!("Source {name} has an invalid hash {hash:?}"))?;
+        }
     }
+    Ok(())
+}
+
+/// Show the line of the content an error occured in and point to the column.
+///
+/// Lines and columns start at 1. If the line doesn't exist (e.g. because the error occured at the
+/// end of the content), the last line is shown.
+fn error_region(content: &str, line: usize, column: usize) -> String {
+    let lines = content.lines().collect::<Vec<&str>>();
+    let Some(index) = line
+        .checked_sub(1)
+        .filter(|i| *i < lines.len())
+        .or_else(|| lines.len().checked_sub(1))
+    else {
+        return String::new();
+    };
+    let line_content = lines[index];
+    let number = format!("{} | ", index + 1);
+    let padding = " ".repeat(number.len() + column.saturating_sub(1));
+    format!("{number}{line_content}\n{padding}^")
 }
 
 #[cfg(test)]
@@ -57,10 +100,74 @@ mod tests {
     use super::*;
 
     use anyhow::Result;
+    use indoc::indoc;
 
     #[test]
     fn parse_lock() -> Result<()> {
         serde_json::from_str::<Lock>(include_str!("../tests/lon.lock"))?;
         Ok(())
     }
+
+    #[test]
+    fn missing_lock() -> Result<()> {
+        let tmpdir = tempfile::tempdir()?;
+        let err = Lock::from_file(tmpdir.path().join(Lock::FILENAME))
+            .err()
+            .context("Expected an error")?;
+        assert!(err.to_string().contains("Run lon init to create it"));
+        Ok(())
+    }
+
+    #[test]
+    fn lock_with_invalid_hash() -> Result<()> {
+        let tmpdir = tempfile::tempdir()?;
+        std::fs::write(
+            tmpdir.path().join(Lock::FILENAME),
+            indoc! {r#"
+                {
+                  "version": "1",
+                  "sources": {
+                    "nixpkgs": {
+                      "type": "GitHub",
+                      "fetchType": "tarball",
+                      "owner": "nixos",
+                      "repo": "nixpkgs",
+                      "branch": "master",
+                      "revision": "2d068ae5c6516b2d04562de50a58c682540de9bf",
+                      "url": "https://github.com/nixos/nixpkgs/archive/2d068ae5c6516b2d04562de50a58c682540de9bf.tar.gz",
+                      "hash": "sha256-e/fSi0WER06N8WCvpht62fkGtWfe5ckD"
+                    }
+                  }
+                }
+            "#},
+        )?;
+        let err = Lock::from_file(tmpdir.path().join(Lock::FILENAME))
+            .err()
+            .context("Expected an error")?;
+        let message = format!("{err:#}");
+        assert!(message.contains("Source nixpkgs has an invalid hash"));
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_lock() -> Result<()> {
+        let tmpdir = tempfile::tempdir()?;
+        std::fs::write(
+            tmpdir.path().join(Lock::FILENAME),
+            indoc! {r#"
+                {
+                  "version": "1",
+                  "sources": {,}
+                }
+            "#},
+        )?;
+        let err = Lock::from_file(tmpdir.path().join(Lock::FILENAME))
+            .err()
+            .context("Expected an error")?;
+        assert!(
+            err.to_string()
+                .ends_with("3 |   \"sources\": {,}\n                  ^")
+        );
+        Ok(())
+    }
 }
@@ -13,6 +13,37 @@ pub struct Lock {
 pub enum Source {
     Git(GitSource),
     GitHub(GitHubSource),
+    Bitbucket(BitbucketSource),
+    Forgejo(ForgejoSource),
+    GitHubRelease(GitHubReleaseSource),
+    Archive(ArchiveSource),
+    Channel(ChannelSource),
+    Flake(FlakeSource),
+    Url(UrlSource),
+    Path(PathSource),
+}
+
+/// Settings and bookkeeping that all sources have, whatever their type.
+///
+/// `frozen` isn't part of it so that it stays right after the type of a source in the lock file.
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceMeta {
+    /// Whether the source is skipped when all sources are updated
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub manual: bool,
+    /// Hash of the source as recorded by flakes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nar_hash: Option<NixHash>,
+    /// Minimum number of seconds between update checks by the bot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub update_interval: Option<u64>,
+    /// Unix timestamp of the last update check of this source by Lon
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_checked: Option<u64>,
+    /// Labels the bot adds to Pull Requests for this source
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
 }
 
 /// This type indicates what fetcher to use to download this source.
@@ -21,6 +52,9 @@ pub enum Source {
 pub enum FetchType {
     Git,
     Tarball,
+    File,
+    Path,
+    Flake,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -29,16 +63,39 @@ pub struct GitSource {
     pub fetch_type: FetchType,
     #[serde(default, skip_serializing_if = "std::ops::Not::not")]
     pub frozen: bool,
-
     pub branch: String,
+    /// Glob pattern the branch is resolved from on every update
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch_pattern: Option<String>,
     pub revision: String,
     pub url: String,
     pub hash: NixHash,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub last_modified: Option<u64>,
+    /// Fully qualified reference the revision was resolved from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    #[serde(flatten)]
+    pub fetch_options: GitFetchOptions,
+    /// Subdirectory of the fetched source to expose
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(flatten)]
+    pub meta: SourceMeta,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GitFetchOptions {
     /// Whether to fetch submodules
     #[serde(default)]
     pub submodules: bool,
+    /// Whether to keep the .git directory
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub leave_dot_git: bool,
+    /// Whether to fetch the whole history instead of only the revision
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deep_clone: bool,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -47,11 +104,183 @@ pub struct GitHubSource {
     pub fetch_type: FetchType,
     #[serde(default, skip_serializing_if = "std::ops::Not::not")]
     pub frozen: bool,
+    pub owner: String,
+    pub repo: String,
+    pub branch: String,
+    /// Glob pattern the branch is resolved from on every update
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch_pattern: Option<String>,
+    pub revision: String,
+    pub url: String,
+    pub hash: NixHash,
+    /// Fully qualified reference the revision was resolved from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    /// Subdirectory of the fetched source to expose
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(flatten)]
+    pub meta: SourceMeta,
+}
 
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BitbucketSource {
+    pub fetch_type: FetchType,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub frozen: bool,
+    pub workspace: String,
+    pub repo: String,
+    pub branch: String,
+    /// Glob pattern the branch is resolved from on every update
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch_pattern: Option<String>,
+    pub revision: String,
+    pub url: String,
+    pub hash: NixHash,
+    /// Fully qualified reference the revision was resolved from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    /// Subdirectory of the fetched source to expose
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(flatten)]
+    pub meta: SourceMeta,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForgejoSource {
+    pub fetch_type: FetchType,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub frozen: bool,
+    /// Host of the Forgejo or Gitea instance
+    pub host: String,
     pub owner: String,
     pub repo: String,
     pub branch: String,
+    /// Glob pattern the branch is resolved from on every update
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch_pattern: Option<String>,
     pub revision: String,
     pub url: String,
     pub hash: NixHash,
+    /// Fully qualified reference the revision was resolved from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    /// Subdirectory of the fetched source to expose
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(flatten)]
+    pub meta: SourceMeta,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GitHubReleaseSource {
+    pub fetch_type: FetchType,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub frozen: bool,
+    pub owner: String,
+    pub repo: String,
+    /// Tag of the locked release
+    pub tag: String,
+    /// Glob pattern the name of the asset is matched against
+    pub asset: String,
+    /// Revision the tag points to
+    pub revision: String,
+    pub url: String,
+    pub hash: NixHash,
+    #[serde(flatten)]
+    pub meta: SourceMeta,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveSource {
+    pub fetch_type: FetchType,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub frozen: bool,
+    /// URL of the git repository the branch is resolved from
+    pub repository: String,
+    /// Template of the URL of the archive of a revision
+    pub archive_url: String,
+    pub branch: String,
+    /// Glob pattern the branch is resolved from on every update
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch_pattern: Option<String>,
+    pub revision: String,
+    pub url: String,
+    pub hash: NixHash,
+    /// Fully qualified reference the revision was resolved from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    /// Subdirectory of the fetched source to expose
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(flatten)]
+    pub meta: SourceMeta,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelSource {
+    pub fetch_type: FetchType,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub frozen: bool,
+    /// Name of the channel (e.g. nixos-24.05)
+    pub channel: String,
+    /// Revision of nixpkgs the locked release was built from
+    pub revision: String,
+    /// URL of the nixexprs tarball of the locked release
+    pub url: String,
+    pub hash: NixHash,
+    #[serde(flatten)]
+    pub meta: SourceMeta,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlakeSource {
+    pub fetch_type: FetchType,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub frozen: bool,
+    /// Flake reference the source is resolved from (e.g. github:nixos/nixpkgs)
+    pub flake_ref: String,
+    /// Attributes of the locked flake reference as passed to `builtins.fetchTree`
+    pub locked: serde_json::Map<String, serde_json::Value>,
+    /// Revision the flake reference resolved to, if the fetcher reports one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+    /// The narHash of the source
+    pub hash: NixHash,
+    #[serde(flatten)]
+    pub meta: SourceMeta,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UrlSource {
+    pub fetch_type: FetchType,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub frozen: bool,
+    pub url: String,
+    pub hash: NixHash,
+    #[serde(flatten)]
+    pub meta: SourceMeta,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathSource {
+    pub fetch_type: FetchType,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub frozen: bool,
+    /// Path to the directory relative to lon.nix
+    pub local_path: String,
+    /// Hash of the NAR serialization of the directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<NixHash>,
+    #[serde(flatten)]
+    pub meta: SourceMeta,
 }
@@ -1,46 +1,235 @@
 use std::{
     fs::{self, File},
     io,
-    path::{Path, PathBuf},
+    path::{Component, Path},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use nix_compat::nixhash::HashAlgo;
+use serde::Deserialize;
 use sha2::{Digest, Sha256};
 
+use crate::{
+    config::Config,
+    sources::{Source, SourceKind, Sources},
+};
+
+/// The fetchers lon.nix uses to fetch the sources.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Fetchers {
+    /// The builtin fetchers (e.g. `builtins.fetchGit`) that fetch during evaluation.
+    ///
+    /// lon.nix can be imported directly.
+    #[default]
+    Builtins,
+    /// The fixed-output fetchers from nixpkgs (e.g. `pkgs.fetchgit`) that fetch during the build.
+    ///
+    /// lon.nix is a function that needs to be called with `pkgs`.
+    Nixpkgs,
+}
+
+impl Fetchers {
+    /// Return the bindings of lon.nix that fetch the sources.
+    fn bindings(self) -> &'static str {
+        match self {
+            Self::Builtins => include_str!("lon_nix/builtins.nix.in"),
+            Self::Nixpkgs => include_str!("lon_nix/nixpkgs.nix.in"),
+        }
+    }
+
+    /// Return what the fetchers need to fetch a source beyond what lon.nix needs anyway.
+    ///
+    /// lon.nix passes the hash as `sha256` to the fetchers of older nixpkgs that don't accept
+    /// `hash`. This only works for SHA-256 hashes. Flake sources are always fetched with
+    /// `builtins.fetchTree`.
+    fn requirement(self, source: &Source) -> Option<&'static str> {
+        match self {
+            _ if source.kind() == SourceKind::Flake => {
+                Some("the flakes experimental feature enabled")
+            }
+            Self::Nixpkgs
+                if source
+                    .hash()
+                    .is_some_and(|hash| !matches!(hash.algo(), HashAlgo::Sha256)) =>
+            {
+                Some("nixpkgs fetchers that accept `hash`")
+            }
+            Self::Builtins | Self::Nixpkgs => None,
+        }
+    }
+}
+
+/// The form of the expression lon.nix evaluates to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum NixStyle {
+    /// An attribute set of all sources
+    #[default]
+    Attrset,
+    /// A nixpkgs overlay that adds the attribute set of all sources as `sources`
+    ///
+    /// With the nixpkgs fetchers, the fetchers of the final package set are used.
+    Overlay,
+}
+
+impl NixStyle {
+    /// The value of the style in lon.toml.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Attrset => "attrset",
+            Self::Overlay => "overlay",
+        }
+    }
+
+    /// Render lon.nix in this style around the bindings of the fetchers.
+    fn render(self, fetchers: Fetchers) -> String {
+        let (arguments, pkgs) = match (self, fetchers) {
+            (Self::Attrset, Fetchers::Builtins) => ("", ""),
+            (Self::Attrset, Fetchers::Nixpkgs) => ("{ pkgs }:\n", ""),
+            (Self::Overlay, Fetchers::Builtins) => ("final: prev:\n", ""),
+            // The nixpkgs fetchers take the fetchers from the overlay instead of an argument.
+            (Self::Overlay, Fetchers::Nixpkgs) => ("final: prev:\n", "\n  pkgs = final;\n"),
+        };
+        let body = match self {
+            Self::Attrset => include_str!("lon_nix/attrset.nix.in"),
+            Self::Overlay => include_str!("lon_nix/overlay.nix.in"),
+        };
+
+        format!(
+            "# Generated by lon. Do not modify!\n{arguments}let\n{pkgs}{}in\n{body}",
+            fetchers.bindings()
+        )
+    }
+}
+
 pub struct LonNix;
 
 impl LonNix {
-    const FILENAME: &'static str = "lon.nix";
+    /// The default name of the generated Nix file.
+    pub const FILENAME: &'static str = "lon.nix";
 
-    const LON_NIX: &'static str = include_str!("lon.nix");
-    const LON_NIX_SHA256: &'static [u8; 32] =
-        include_bytes!(concat!(env!("OUT_DIR"), "lon.nix.sha256"));
+    /// The path to the lock file in the embedded lon.nix.
+    const LOCK_PATH: &'static str = "./lon.lock";
 
     /// Update lon.nix.
     ///
-    /// Only update if the file on disk doesn't match the hash of the currently embedded version.
-    pub fn update(directory: impl AsRef<Path>) -> Result<()> {
-        let actual_hash = hash_file(Self::path(&directory))
-            .with_context(|| format!("Failed to hash {}", Self::FILENAME))?;
+    /// Only update if the file on disk doesn't match the hash of the version Lon would generate.
+    /// If it doesn't exist (e.g. after `lon init --bare`), it isn't created.
+    pub fn update(directory: impl AsRef<Path>, config: &Config, sources: &Sources) -> Result<()> {
+        if !config.nix_path(&directory).exists() {
+            log::debug!(
+                "{} doesn't exist. Not creating it",
+                config.nix_file().display()
+            );
+            return Ok(());
+        }
+
+        let expected = Self::render(config)?;
+        let actual_hash = hash_file(config.nix_path(&directory))
+            .with_context(|| format!("Failed to hash {}", config.nix_file().display()))?;
 
-        if actual_hash != *Self::LON_NIX_SHA256 {
-            log::info!("Updating lon.nix...");
-            Self::write(directory)?;
+        if actual_hash[..] != Sha256::digest(&expected)[..] {
+            log::info!("Updating {}...", config.nix_file().display());
+            Self::write(directory, config, sources)?;
         }
         Ok(())
     }
 
+    /// Compare lon.nix on disk with the version Lon would generate.
+    ///
+    /// Returns a summary of the differences or `None` if they match.
+    pub fn diff(directory: impl AsRef<Path>, config: &Config) -> Result<Option<String>> {
+        let path = config.nix_path(&directory);
+        let actual = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+
+        Ok(diff_summary(&Self::render(config)?, &actual))
+    }
+
     /// Write lon.nix to disk.
-    pub fn write(directory: impl AsRef<Path>) -> Result<()> {
-        fs::write(Self::path(directory), Self::LON_NIX.as_bytes())
-            .context("Failed to write lon.nix")
+    ///
+    /// Warns about the sources that the configured fetchers can only fetch if they're recent.
+    pub fn write(directory: impl AsRef<Path>, config: &Config, sources: &Sources) -> Result<()> {
+        for name in sources.names() {
+            if let Some(source) = sources.get(name) {
+                Self::warn_incompatible(name, source, config);
+            }
+        }
+
+        let path = config.nix_path(&directory);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        fs::write(&path, Self::render(config)?.as_bytes())
+            .with_context(|| format!("Failed to write {}", path.display()))
     }
 
-    pub fn path(directory: impl AsRef<Path>) -> PathBuf {
-        directory.as_ref().join(Self::FILENAME)
+    /// Warn if lon.nix can only fetch the source with recent fetchers.
+    ///
+    /// Otherwise, this would only surface as an error when evaluating lon.nix.
+    pub fn warn_incompatible(name: &str, source: &Source, config: &Config) {
+        if let Some(requirement) = config.fetchers.requirement(source) {
+            log::warn!(
+                "{} can only fetch {name} with {requirement}",
+                config.nix_file().display()
+            );
+        }
+    }
+
+    /// Render lon.nix so that it reads the lock file at the configured path.
+    fn render(config: &Config) -> Result<String> {
+        let lon_nix = config.nix_style.render(config.fetchers);
+        let lock_path = relative_lock_path(config.nix_file(), config.lock_file())?;
+        Ok(lon_nix.replace(Self::LOCK_PATH, &lock_path))
     }
 }
 
+/// Return the path to the lock file relative to the Nix file as a Nix path literal.
+///
+/// Both paths are relative to the same directory.
+fn relative_lock_path(nix_file: &Path, lock_file: &Path) -> Result<String> {
+    let nix_directory = nix_file
+        .parent()
+        .map(|parent| parent.components().collect::<Vec<_>>())
+        .unwrap_or_default();
+    let lock_file = lock_file.components().collect::<Vec<_>>();
+
+    let common = nix_directory
+        .iter()
+        .zip(&lock_file)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut components = vec![if common == nix_directory.len() {
+        "."
+    } else {
+        ".."
+    }];
+    components.extend(std::iter::repeat_n(
+        "..",
+        nix_directory.len().saturating_sub(common + 1),
+    ));
+    for component in &lock_file[common..] {
+        let Component::Normal(component) = component else {
+            bail!("Unsupported path to the lock file {lock_file:?}");
+        };
+        let Some(component) = component.to_str().filter(|c| {
+            c.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '+'))
+        }) else {
+            bail!(
+                "The path to the lock file can only contain the characters a-z, A-Z, 0-9, ., _, -, and +"
+            );
+        };
+        components.push(component);
+    }
+
+    Ok(components.join("/"))
+}
+
 /// Hash a file with SHA256.
 fn hash_file(path: impl AsRef<Path>) -> Result<[u8; 32]> {
     let mut hasher = Sha256::new();
@@ -53,3 +242,94 @@ fn hash_file(path: impl AsRef<Path>) -> Result<[u8; 32]> {
 
     Ok(buffer)
 }
+
+/// Summarize the line-wise differences between the expected and the actual content.
+fn diff_summary(expected: &str, actual: &str) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+
+    let expected_lines = expected.lines().collect::<Vec<_>>();
+    let actual_lines = actual.lines().collect::<Vec<_>>();
+
+    let differing_lines = (0..expected_lines.len().max(actual_lines.len()))
+        .filter(|&i| expected_lines.get(i) != actual_lines.get(i))
+        .collect::<Vec<_>>();
+
+    let Some(first) = differing_lines.first() else {
+        // Only the trailing newline differs.
+        return Some("The trailing newline differs".into());
+    };
+
+    Some(format!(
+        "{} lines differ, starting at line {} (expected {} lines, found {})",
+        differing_lines.len(),
+        first + 1,
+        expected_lines.len(),
+        actual_lines.len(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use expect_test::expect_file;
+
+    #[test]
+    fn relative_lock_paths() -> Result<()> {
+        let relative = |nix: &str, lock: &str| relative_lock_path(Path::new(nix), Path::new(lock));
+
+        assert_eq!(relative("lon.nix", "lon.lock")?, "./lon.lock");
+        assert_eq!(relative("nix/lon.nix", "nix/lon.lock")?, "./lon.lock");
+        assert_eq!(relative("lon.nix", "nix/lon.lock")?, "./nix/lon.lock");
+        assert_eq!(relative("nix/lon.nix", "lon.lock")?, "../lon.lock");
+        assert_eq!(relative("a/b/lon.nix", "a/c/lon.lock")?, "../c/lon.lock");
+        assert!(relative("lon.nix", "my lock.json").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn render_styles() {
+        expect_file!["lon_nix/snapshots/attrset-builtins.nix"]
+            .assert_eq(&NixStyle::Attrset.render(Fetchers::Builtins));
+        expect_file!["lon_nix/snapshots/attrset-nixpkgs.nix"]
+            .assert_eq(&NixStyle::Attrset.render(Fetchers::Nixpkgs));
+        expect_file!["lon_nix/snapshots/overlay-builtins.nix"]
+            .assert_eq(&NixStyle::Overlay.render(Fetchers::Builtins));
+        expect_file!["lon_nix/snapshots/overlay-nixpkgs.nix"]
+            .assert_eq(&NixStyle::Overlay.render(Fetchers::Nixpkgs));
+    }
+
+    #[test]
+    fn require_recent_fetchers() -> Result<()> {
+        let lock_json = include_str!("../tests/lon.lock");
+        let sources = Sources::from(serde_json::from_str::<crate::lock::v1::Lock>(lock_json)?);
+        let nixpkgs = sources.get("nixpkgs").context("Missing source")?;
+        assert_eq!(Fetchers::Nixpkgs.requirement(nixpkgs), None);
+
+        let lock_json = lock_json.replace(
+            "sha256-h1zQVhXuYoKTgJWqgVa7veoCJlbuG+xyzLQAar1Np5Y=",
+            "sha512-z4PhNX7vuL3xVChQ1m2AB9Yg5AULVxXcg/SpIdNs6c5H0NE8XYXysP+DGNKHfuwvY7kxvUdBeoGlODJ6+SfaPg==",
+        );
+        let sources = Sources::from(serde_json::from_str::<crate::lock::v1::Lock>(&lock_json)?);
+        let nixpkgs = sources.get("nixpkgs").context("Missing source")?;
+        assert!(Fetchers::Nixpkgs.requirement(nixpkgs).is_some());
+        assert_eq!(Fetchers::Builtins.requirement(nixpkgs), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn summarize_diff() {
+        assert_eq!(diff_summary("a\nb\n", "a\nb\n"), None);
+        assert_eq!(
+            diff_summary("a\nb\nc\n", "a\nx\n"),
+            Some("2 lines differ, starting at line 2 (expected 3 lines, found 2)".into())
+        );
+        assert_eq!(
+            diff_summary("a\n", "a"),
+            Some("The trailing newline differs".into())
+        );
+    }
+}
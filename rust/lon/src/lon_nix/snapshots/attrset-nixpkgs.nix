@@ -0,0 +1,88 @@
+# Generated by lon. Do not modify!
+{ pkgs }:
+let
+
+  lock = builtins.fromJSON (builtins.readFile ./lon.lock);
+
+  # Override with a path defined in an environment variable. If no variable is
+  # set, the original path is used.
+  overrideFromEnv =
+    name: path:
+    let
+      replacement = builtins.getEnv "LON_OVERRIDE_${name}";
+    in
+    if replacement == "" then
+      path
+    else
+    # this turns the string into an actual Nix path (for both absolute and
+    # relative paths)
+    if builtins.substring 0 1 replacement == "/" then
+      /. + replacement
+    else
+      /. + builtins.getEnv "PWD" + "/${replacement}";
+
+  # Select a subdirectory of a source if one is configured.
+  selectPath =
+    args: src:
+    if args ? path then "${src}/${args.path}" else src;
+
+  # Pass the hash as `sha256` to fetchers of older nixpkgs that only accept
+  # `sha256`. They understand SRI hashes as well, but only SHA-256 ones.
+  hashArgs =
+    fetcher: hash:
+    let
+      fetcherArgs = pkgs.lib.functionArgs fetcher;
+    in
+    if fetcherArgs ? sha256 && !(fetcherArgs ? hash) then { sha256 = hash; } else { inherit hash; };
+
+  fetchSource =
+    args@{ fetchType, ... }:
+    if fetchType == "git" then
+      pkgs.fetchgit (
+        {
+          url = args.url;
+          rev = args.revision;
+          fetchSubmodules = args.submodules;
+          leaveDotGit = args.leaveDotGit or false;
+          deepClone = args.deepClone or false;
+        }
+        // hashArgs pkgs.fetchgit args.hash
+      )
+    else if fetchType == "tarball" then
+      # fetchzip strips the single top-level directory regardless of its name
+      # (e.g. {workspace}-{repo}-{short revision} on Bitbucket).
+      pkgs.fetchzip (
+        {
+          url = args.url;
+        }
+        // hashArgs pkgs.fetchzip args.hash
+      )
+    else if fetchType == "file" then
+      pkgs.fetchurl (
+        {
+          url = args.url;
+        }
+        // hashArgs pkgs.fetchurl args.hash
+      )
+    else if fetchType == "path" then
+      # A directory relative to lon.nix. It is only copied to the store if its
+      # hash is locked so that the hash can be verified.
+      if args ? hash then
+        builtins.path {
+          path = ./. + "/${args.localPath}";
+          name = "source";
+          sha256 = args.hash;
+        }
+      else
+        ./. + "/${args.localPath}"
+    else if fetchType == "flake" then
+      # Any flake reference. builtins.fetchTree requires the flakes experimental
+      # feature.
+      builtins.fetchTree (args.locked // { narHash = args.hash; })
+    else
+      builtins.throw "Unsupported source type ${fetchType}";
+
+in
+builtins.mapAttrs (
+  name: args: selectPath args (overrideFromEnv name (fetchSource args))
+) lock.sources
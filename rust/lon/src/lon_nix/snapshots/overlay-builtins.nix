@@ -0,0 +1,106 @@
+# Generated by lon. Do not modify!
+final: prev:
+let
+
+  lock = builtins.fromJSON (builtins.readFile ./lon.lock);
+
+  # Override with a path defined in an environment variable. If no variable is
+  # set, the original path is used.
+  overrideFromEnv =
+    name: path:
+    let
+      replacement = builtins.getEnv "LON_OVERRIDE_${name}";
+    in
+    if replacement == "" then
+      path
+    else
+    # this turns the string into an actual Nix path (for both absolute and
+    # relative paths)
+    if builtins.substring 0 1 replacement == "/" then
+      /. + replacement
+    else
+      /. + builtins.getEnv "PWD" + "/${replacement}";
+
+  # Select a subdirectory of a source if one is configured.
+  selectPath =
+    args: src:
+    if args ? path then "${src}/${args.path}" else src;
+
+  fetchSource =
+    args@{ fetchType, ... }:
+    if fetchType == "git" && args.leaveDotGit or false then
+      builtins.throw "builtins.fetchGit cannot keep the .git directory of ${args.url}"
+    else if fetchType == "git" then
+      builtins.fetchGit (
+        {
+          url = args.url;
+          ref = args.branch;
+          rev = args.revision;
+          narHash = args.hash;
+          submodules = args.submodules;
+        }
+        // (
+          # A deep clone fetches the whole history instead of a shallow one.
+          if args ? lastModified && !(args.deepClone or false) then
+            {
+              inherit (args) lastModified;
+              shallow = true;
+            }
+          else
+            { }
+        )
+      )
+    else if fetchType == "tarball" then
+      # The single top-level directory of a tarball is stripped regardless of
+      # its name (e.g. {workspace}-{repo}-{short revision} on Bitbucket).
+      #
+      # builtins.fetchTarball only supports SHA-256. Other hash algorithms are
+      # fetched via the builtin fetchurl derivation instead.
+      if builtins.substring 0 7 args.hash == "sha256-" then
+        builtins.fetchTarball {
+          url = args.url;
+          sha256 = args.hash;
+        }
+      else
+        import <nix/fetchurl.nix> {
+          url = args.url;
+          hash = args.hash;
+          name = "source";
+          unpack = true;
+        }
+    else if fetchType == "file" then
+      # A single file that is not unpacked (e.g. a release asset).
+      if builtins.substring 0 7 args.hash == "sha256-" then
+        builtins.fetchurl {
+          url = args.url;
+          sha256 = args.hash;
+        }
+      else
+        import <nix/fetchurl.nix> {
+          url = args.url;
+          hash = args.hash;
+        }
+    else if fetchType == "path" then
+      # A directory relative to lon.nix. It is only copied to the store if its
+      # hash is locked so that the hash can be verified.
+      if args ? hash then
+        builtins.path {
+          path = ./. + "/${args.localPath}";
+          name = "source";
+          sha256 = args.hash;
+        }
+      else
+        ./. + "/${args.localPath}"
+    else if fetchType == "flake" then
+      # Any flake reference. builtins.fetchTree requires the flakes experimental
+      # feature.
+      builtins.fetchTree (args.locked // { narHash = args.hash; })
+    else
+      builtins.throw "Unsupported source type ${fetchType}";
+
+in
+{
+  sources = builtins.mapAttrs (
+    name: args: selectPath args (overrideFromEnv name (fetchSource args))
+  ) lock.sources;
+}
@@ -1,14 +1,23 @@
+mod audit;
 mod bot;
+mod build_info;
 mod cli;
 mod commit_message;
 mod config;
+mod error;
+mod export;
 mod git;
 mod http;
 mod init;
 mod lock;
 mod lon_nix;
+mod manifest;
+mod nar;
 mod nix;
+mod process;
+mod report;
 mod sources;
+mod time;
 
 use std::process::ExitCode;
 
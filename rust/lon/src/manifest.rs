@@ -0,0 +1,116 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::nix::GitFetchOptions;
+
+/// A list of sources to add in one pass.
+///
+/// This makes bootstrapping a project with many sources easier than calling `lon add` for each
+/// of them.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub sources: Vec<Entry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Entry {
+    Git(GitEntry),
+    GitHub(GitHubEntry),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GitEntry {
+    pub name: String,
+    pub url: String,
+    pub branch: Option<String>,
+    pub revision: Option<String>,
+    #[serde(flatten)]
+    pub options: GitFetchOptions,
+    #[serde(default)]
+    pub frozen: bool,
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GitHubEntry {
+    pub identifier: String,
+    pub name: Option<String>,
+    pub branch: Option<String>,
+    pub revision: Option<String>,
+    #[serde(default)]
+    pub frozen: bool,
+    pub path: Option<String>,
+}
+
+impl Manifest {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read {:?}", path.as_ref()))?;
+
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse manifest {:?}", path.as_ref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use expect_test::expect;
+    use indoc::indoc;
+
+    #[test]
+    fn parse_manifest() -> Result<()> {
+        let manifest: Manifest = toml::from_str(indoc! {r#"
+            [[sources]]
+            type = "github"
+            identifier = "nixos/nixpkgs"
+            branch = "nixos-unstable"
+
+            [[sources]]
+            type = "git"
+            name = "lix"
+            url = "https://git.lix.systems/lix-project/lix.git"
+            submodules = true
+        "#})?;
+
+        let expected = expect![[r#"
+            Manifest {
+                sources: [
+                    GitHub(
+                        GitHubEntry {
+                            identifier: "nixos/nixpkgs",
+                            name: None,
+                            branch: Some(
+                                "nixos-unstable",
+                            ),
+                            revision: None,
+                            frozen: false,
+                            path: None,
+                        },
+                    ),
+                    Git(
+                        GitEntry {
+                            name: "lix",
+                            url: "https://git.lix.systems/lix-project/lix.git",
+                            branch: None,
+                            revision: None,
+                            options: GitFetchOptions {
+                                submodules: true,
+                                leave_dot_git: false,
+                                deep_clone: false,
+                            },
+                            frozen: false,
+                            path: None,
+                        },
+                    ),
+                ],
+            }"#]];
+        expected.assert_eq(&format!("{manifest:#?}"));
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    os::unix::{ffi::OsStrExt, fs::PermissionsExt},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use nix_compat::{
+    nar::writer::{self, Node},
+    nixhash::NixHash,
+};
+use sha2::{Digest, Sha256};
+
+/// Calculate the SHA-256 hash of the NAR serialization of a path.
+///
+/// This is the same hash that `nix hash path` reports.
+pub fn hash_path(path: impl AsRef<Path>) -> Result<NixHash> {
+    let mut hasher = Sha256::new();
+    let node = writer::open(&mut hasher).context("Failed to start the NAR serialization")?;
+    write_node(node, path.as_ref())?;
+    Ok(NixHash::Sha256(hasher.finalize().into()))
+}
+
+/// Serialize a path into a NAR node.
+///
+/// Directory entries are written sorted by name as NAR requires.
+fn write_node(node: Node, path: &Path) -> Result<()> {
+    let metadata = fs::symlink_metadata(path)
+        .with_context(|| format!("Failed to read metadata of {}", path.display()))?;
+
+    if metadata.is_symlink() {
+        let target = fs::read_link(path)
+            .with_context(|| format!("Failed to read link {}", path.display()))?;
+        node.symlink(target.as_os_str().as_bytes())?;
+    } else if metadata.is_dir() {
+        let mut names = fs::read_dir(path)
+            .with_context(|| format!("Failed to read directory {}", path.display()))?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<Result<Vec<_>, _>>()?;
+        names.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+
+        let mut directory = node.directory()?;
+        for name in names {
+            write_node(directory.entry(name.as_bytes())?, &path.join(&name))?;
+        }
+        directory.close()?;
+    } else {
+        // Like Nix, only consider the executable bit of the owner.
+        let executable = metadata.permissions().mode() & 0o100 != 0;
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        node.file(executable, metadata.len(), &mut BufReader::new(file))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn hash_empty_directory() -> Result<()> {
+        let tmpdir = tempdir()?;
+
+        assert_eq!(
+            hash_path(tmpdir.path())?.to_sri_string(),
+            "sha256-pQpattmS9VmO3ZIQUFn66az8GSmB4IvYhTTCFn6SUmo="
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn hash_directory() -> Result<()> {
+        let tmpdir = tempdir()?;
+        fs::write(tmpdir.path().join("b"), "hello\n")?;
+        fs::write(tmpdir.path().join("a.sh"), "#!/bin/sh\n")?;
+        fs::set_permissions(
+            tmpdir.path().join("a.sh"),
+            fs::Permissions::from_mode(0o755),
+        )?;
+        fs::create_dir(tmpdir.path().join("c"))?;
+        symlink("../b", tmpdir.path().join("c/link"))?;
+
+        assert_eq!(
+            hash_path(tmpdir.path())?.to_sri_string(),
+            "sha256-olfo3JizRd7DexPSwD89AKBOHP5/f9Hz2AjbiQXWdeI="
+        );
+
+        Ok(())
+    }
+}
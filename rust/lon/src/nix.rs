@@ -1,66 +1,352 @@
-use std::process::Command;
+use std::{
+    collections::HashMap,
+    fs,
+    hash::Hash,
+    io::Write,
+    process::Command,
+    sync::{LazyLock, Mutex},
+};
 
 use anyhow::{Context, Result, bail};
 use nix_compat::nixhash::{HashAlgo, NixHash};
 use serde::Deserialize;
+use tempfile::{NamedTempFile, TempDir};
+
+use crate::{
+    config::{self, ensure_online},
+    error::LonError,
+    git, nar,
+    process::CommandExt,
+    time,
+};
+
+/// Credentials to download from a host that requires authentication.
+pub struct Credentials<'a> {
+    pub host: &'a str,
+    pub token: &'a str,
+}
+
+impl Credentials<'_> {
+    /// Write the credentials to a temporary netrc file.
+    ///
+    /// Passing the token via a file ensures that it never shows up in the arguments of a process.
+    fn to_netrc_file(&self) -> Result<NamedTempFile> {
+        let mut file = NamedTempFile::new().context("Failed to create netrc file")?;
+        writeln!(
+            file,
+            "machine {} login x-access-token password {}",
+            self.host, self.token
+        )
+        .context("Failed to write netrc file")?;
+        Ok(file)
+    }
+}
+
+/// Options that change what is fetched from a git repository.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(default)]
+pub struct GitFetchOptions {
+    /// Whether to fetch submodules
+    pub submodules: bool,
+    /// Whether to keep the .git directory
+    pub leave_dot_git: bool,
+    /// Whether to fetch the whole history instead of only the revision
+    pub deep_clone: bool,
+}
 
 #[derive(Deserialize)]
 struct NixPrefetchGitResponse {
     hash: NixHash,
+    /// Commit date in ISO 8601 format
+    ///
+    /// Older versions of nix-prefetch-git don't report it.
+    date: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct NixFlakePrefetchResponse {
+    hash: NixHash,
+    locked: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Result of prefetching a flake input.
+pub struct FlakePrefetch {
+    pub hash: NixHash,
+    /// Attributes of the locked flake reference (e.g. type, owner, repo, and rev)
+    pub locked: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Result of prefetching a git source.
+#[derive(Clone)]
+pub struct GitPrefetch {
+    pub hash: NixHash,
+    /// Unix timestamp of the commit date of the fetched revision, if reported
+    pub last_modified: Option<u64>,
+}
+
+/// Results of earlier prefetches in this invocation.
+type Prefetches<K, V> = LazyLock<Mutex<HashMap<K, V>>>;
+
+/// Prefetches of git sources keyed by URL, revision and options.
+static GIT_PREFETCHES: Prefetches<(String, String, GitFetchOptions), GitPrefetch> =
+    LazyLock::new(Mutex::default);
+
+/// Prefetches of URLs keyed by URL, hash algorithm and whether they're unpacked.
+static URL_PREFETCHES: Prefetches<(String, String, bool), NixHash> = LazyLock::new(Mutex::default);
+
+/// Return the result of an earlier prefetch with the same key or prefetch now.
+///
+/// This way, sources that are locked more than once in a single invocation (e.g. the same
+/// repository under different names) are only fetched once. Failures aren't remembered.
+fn memoize<K: Eq + Hash, V: Clone>(
+    cache: &Mutex<HashMap<K, V>>,
+    key: K,
+    prefetch: impl FnOnce() -> Result<V>,
+) -> Result<V> {
+    if let Some(value) = cache.lock().ok().and_then(|cache| cache.get(&key).cloned()) {
+        log::debug!("Reusing an earlier prefetch");
+        return Ok(value);
+    }
+    let value = prefetch()?;
+    if let Ok(mut cache) = cache.lock() {
+        cache.insert(key, value.clone());
+    }
+    Ok(value)
 }
 
 /// Fetch a git source and calculate its hash.
 ///
 /// Uses the same store path (via `--name source`) as `builtins.fetchGit` to download the
 /// source only once.
-pub fn prefetch_git(url: &str, revision: &str, submodules: bool) -> Result<NixHash> {
+///
+/// The commit date is returned as well so that the revision doesn't need to be fetched again to
+/// determine its `lastModified`.
+pub fn prefetch_git(url: &str, revision: &str, options: GitFetchOptions) -> Result<GitPrefetch> {
+    ensure_online("prefetch a git source")?;
+
+    memoize(
+        &GIT_PREFETCHES,
+        (url.into(), revision.into(), options),
+        || prefetch_git_uncached(url, revision, options),
+    )
+}
+
+fn prefetch_git_uncached(
+    url: &str,
+    revision: &str,
+    options: GitFetchOptions,
+) -> Result<GitPrefetch> {
+    if config::builtin_hash() {
+        return prefetch_git_builtin(url, revision, options);
+    }
+
     let mut command = Command::new("nix-prefetch-git");
-    if submodules {
+    if options.submodules {
         command.arg("--fetch-submodules");
     }
+    if options.leave_dot_git {
+        command.arg("--leave-dotGit");
+    }
+    if options.deep_clone {
+        command.arg("--deepClone");
+    }
     let output = command
         .arg("--name")
         .arg("source")
-        .arg(url)
+        .arg(git::fetch_url(url).as_ref())
         .arg(revision)
-        .output()
-        .context("Failed to execute nix-prefetch-git. Most likely it's not on PATH")?;
+        .spawn_captured()
+        .context("Failed to execute nix-prefetch-git. Most likely it's not on PATH")?
+        .wait_for_download()?;
 
     if !output.status.success() {
-        bail!(
-            "Failed to prefetch git from {url}@{revision}\n{}",
-            String::from_utf8_lossy(&output.stderr)
-        );
+        bail!(LonError::PrefetchFailed {
+            url: format!("{url}@{revision}"),
+            stderr: String::from_utf8_lossy(&output.stderr).into(),
+        });
     }
 
     let response: NixPrefetchGitResponse = serde_json::from_slice(&output.stdout)
         .context("Failed to deserialize nix-prefetch-git JSON response")?;
 
-    Ok(response.hash)
+    let last_modified = response
+        .date
+        .as_deref()
+        .map(time::parse_timestamp)
+        .transpose()?;
+
+    Ok(GitPrefetch {
+        hash: response.hash,
+        last_modified,
+    })
+}
+
+/// Fetch a git source with git and calculate its hash without Nix.
+///
+/// The hash is the same as the one nix-prefetch-git reports. Keeping the .git directory isn't
+/// supported because nix-prefetch-git makes it reproducible in ways that can't be replicated.
+fn prefetch_git_builtin(
+    url: &str,
+    revision: &str,
+    options: GitFetchOptions,
+) -> Result<GitPrefetch> {
+    if options.leave_dot_git {
+        bail!("Keeping the .git directory is not supported when hashing without nix-prefetch-git");
+    }
+
+    let tmp_dir = TempDir::new()?;
+    let checkout = tmp_dir.path().join("source");
+    fs::create_dir(&checkout).context("Failed to create the checkout directory")?;
+
+    let last_modified = git::checkout(url, revision, options.submodules, &checkout)?;
+    let hash = nar::hash_path(&checkout)?;
+
+    Ok(GitPrefetch {
+        hash,
+        last_modified: Some(last_modified),
+    })
+}
+
+/// Fetch a flake input and return its narHash together with the locked flake reference.
+///
+/// These are the hash and the attributes flakes record for the input in flake.lock.
+pub fn prefetch_flake(flake_ref: &str) -> Result<FlakePrefetch> {
+    ensure_online("prefetch a flake input")?;
+
+    let output = Command::new("nix")
+        .args(["--extra-experimental-features", "nix-command flakes"])
+        .args(["flake", "prefetch", "--json"])
+        .arg(flake_ref)
+        .spawn_captured()
+        .context("Failed to execute nix flake prefetch. Most likely nix is not on PATH")?
+        .wait_for_download()?;
+
+    if !output.status.success() {
+        bail!(LonError::PrefetchFailed {
+            url: flake_ref.into(),
+            stderr: String::from_utf8_lossy(&output.stderr).into(),
+        });
+    }
+
+    let response: NixFlakePrefetchResponse = serde_json::from_slice(&output.stdout)
+        .context("Failed to deserialize nix flake prefetch JSON response")?;
+
+    Ok(FlakePrefetch {
+        hash: response.hash,
+        locked: response.locked,
+    })
 }
 
 /// Fetch a tarball and calculate its hash.
 ///
 /// Uses the same store path (via `--name source`) as `builtins.fetchTarball` to download the
 /// source only once.
-pub fn prefetch_tarball(url: &str) -> Result<NixHash> {
-    let output = Command::new("nix-prefetch-url")
-        .arg("--unpack")
-        .arg("--name")
-        .arg("source")
+///
+/// If credentials are provided, they are used to authenticate the download.
+pub fn prefetch_tarball(
+    url: &str,
+    credentials: Option<&Credentials>,
+    hash_algo: HashAlgo,
+) -> Result<NixHash> {
+    ensure_online("prefetch a tarball")?;
+    prefetch_url(url, credentials, hash_algo, true)
+}
+
+/// Fetch a single file without unpacking it and calculate its hash.
+///
+/// The store path is named after the file like the one of `fetchurl`.
+pub fn prefetch_file(url: &str, hash_algo: HashAlgo) -> Result<NixHash> {
+    ensure_online("prefetch a file")?;
+    prefetch_url(url, None, hash_algo, false)
+}
+
+/// Fetch a URL with nix-prefetch-url.
+///
+/// The credentials aren't part of the key for earlier prefetches because they don't change what
+/// is downloaded.
+fn prefetch_url(
+    url: &str,
+    credentials: Option<&Credentials>,
+    hash_algo: HashAlgo,
+    unpack: bool,
+) -> Result<NixHash> {
+    memoize(
+        &URL_PREFETCHES,
+        (url.into(), hash_algo.to_string(), unpack),
+        || prefetch_url_uncached(url, credentials, hash_algo, unpack),
+    )
+}
+
+fn prefetch_url_uncached(
+    url: &str,
+    credentials: Option<&Credentials>,
+    hash_algo: HashAlgo,
+    unpack: bool,
+) -> Result<NixHash> {
+    let mut command = Command::new("nix-prefetch-url");
+
+    // Keep the file alive until the command finishes.
+    let netrc_file = credentials.map(Credentials::to_netrc_file).transpose()?;
+    if let Some(netrc_file) = &netrc_file {
+        command
+            .arg("--option")
+            .arg("netrc-file")
+            .arg(netrc_file.path());
+    }
+
+    if unpack {
+        command.arg("--unpack").arg("--name").arg("source");
+    }
+
+    let output = command
         .arg("--type")
-        .arg("sha256")
+        .arg(hash_algo.to_string())
         .arg(url)
-        .output()
-        .context("Failed to execute nix-prefetch-url. Most likely it's not on PATH")?;
+        .spawn_captured()
+        .context("Failed to execute nix-prefetch-url. Most likely it's not on PATH")?
+        .wait_for_download()?;
 
     if !output.status.success() {
-        bail!(
-            "Failed to prefetch tarball from {url}\n{}",
-            String::from_utf8_lossy(&output.stderr)
-        );
+        bail!(LonError::PrefetchFailed {
+            url: url.into(),
+            stderr: String::from_utf8_lossy(&output.stderr).into(),
+        });
     }
 
     let stdout = String::from_utf8(output.stdout)?;
-    Ok(NixHash::from_str(stdout.trim(), Some(HashAlgo::Sha256))?)
+    Ok(NixHash::from_str(stdout.trim(), Some(hash_algo))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[test]
+    fn prefetch_identical_sources_once() -> Result<()> {
+        let cache = Mutex::default();
+        let prefetches = Cell::new(0);
+        let prefetch = || {
+            prefetches.set(prefetches.get() + 1);
+            Ok(prefetches.get())
+        };
+
+        let options = GitFetchOptions::default();
+        let key = |url: &str| (url.to_string(), "abc".to_string(), options);
+
+        // Two sources pointing at the same repository and revision
+        assert_eq!(memoize(&cache, key("https://example.com/a"), prefetch)?, 1);
+        assert_eq!(memoize(&cache, key("https://example.com/a"), prefetch)?, 1);
+        // Another source
+        assert_eq!(memoize(&cache, key("https://example.com/b"), prefetch)?, 2);
+        assert_eq!(prefetches.get(), 2);
+
+        // Failures are tried again.
+        let failing = Mutex::default();
+        assert!(memoize(&failing, 1, || -> Result<u32> { bail!("offline") }).is_err());
+        assert_eq!(memoize(&failing, 1, || Ok(1))?, 1);
+
+        Ok(())
+    }
 }
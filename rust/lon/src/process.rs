@@ -0,0 +1,242 @@
+use std::{
+    io::{self, IsTerminal, Read, Write},
+    os::unix::process::CommandExt as _,
+    process::{Child, Command, Output, Stdio},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, bail};
+use rustix::process::{Pid, Signal, kill_process_group};
+
+use crate::config;
+
+/// How often to check whether a running command has finished.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+pub trait CommandExt {
+    /// Spawn the command with its stdout and stderr captured.
+    ///
+    /// Like with `Command::output`, stdin is closed.
+    fn spawn_captured(&mut self) -> io::Result<RunningCommand>;
+
+    /// Spawn the command with its stdout and stderr captured and write the input to its stdin.
+    ///
+    /// Stdin is closed after the input is written.
+    fn spawn_captured_with_input(&mut self, input: &[u8]) -> io::Result<RunningCommand>;
+}
+
+impl CommandExt for Command {
+    fn spawn_captured(&mut self) -> io::Result<RunningCommand> {
+        self.stdin(Stdio::null());
+        RunningCommand::spawn(self, own_process_group())
+    }
+
+    fn spawn_captured_with_input(&mut self, input: &[u8]) -> io::Result<RunningCommand> {
+        self.stdin(Stdio::piped());
+        let mut command = RunningCommand::spawn(self, own_process_group())?;
+
+        // Write the input concurrently to reading the output so that the command doesn't block on
+        // a full pipe before it read all of its input. Dropping stdin closes it so that the command
+        // doesn't wait for more input. If the command exits early, the rest of the input doesn't
+        // matter.
+        if let Some(mut stdin) = command.child.stdin.take() {
+            let input = input.to_vec();
+            thread::spawn(move || {
+                let _ = stdin.write_all(&input);
+            });
+        }
+
+        Ok(command)
+    }
+}
+
+/// A command that was spawned via `CommandExt::spawn_captured`.
+pub struct RunningCommand {
+    program: String,
+    child: Child,
+    /// Whether the command leads its own process group
+    process_group: bool,
+}
+
+impl RunningCommand {
+    /// Spawn the command with its stdout and stderr captured.
+    ///
+    /// With `process_group`, the command leads its own process group.
+    fn spawn(command: &mut Command, process_group: bool) -> io::Result<Self> {
+        if process_group {
+            command.process_group(0);
+        }
+        let child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        Ok(Self {
+            program: command.get_program().to_string_lossy().into(),
+            child,
+            process_group,
+        })
+    }
+
+    /// Wait for the command to finish and collect its output.
+    ///
+    /// The command is killed when it runs longer than the timeout configured via
+    /// `LON_COMMAND_TIMEOUT`.
+    pub fn wait(self) -> Result<Output> {
+        let timeout = config::command_timeout()?;
+        self.wait_with_timeout(timeout, "LON_COMMAND_TIMEOUT")
+    }
+
+    /// Wait for a command that downloads a source (e.g. a clone or a prefetch) to finish.
+    ///
+    /// Downloading a large source can take much longer than any other command, so it is only
+    /// killed after the timeout configured via `LON_DOWNLOAD_TIMEOUT`. There is none by default.
+    pub fn wait_for_download(self) -> Result<Output> {
+        let timeout = config::download_timeout()?;
+        self.wait_with_timeout(timeout, "LON_DOWNLOAD_TIMEOUT")
+    }
+
+    /// Wait for the command to finish and collect its output.
+    ///
+    /// The command is killed when it runs longer than the timeout. A timeout of zero disables it.
+    /// `variable` names the environment variable the timeout is configured with.
+    fn wait_with_timeout(mut self, timeout: Duration, variable: &str) -> Result<Output> {
+        // Read the pipes concurrently so that the command doesn't block on a full pipe.
+        let stdout = self.child.stdout.take().map(read_to_end_in_background);
+        let stderr = self.child.stderr.take().map(read_to_end_in_background);
+
+        let deadline = Instant::now() + timeout;
+        let status = loop {
+            if let Some(status) = self.child.try_wait()? {
+                break status;
+            }
+            if !timeout.is_zero() && Instant::now() >= deadline {
+                self.kill();
+                bail!(
+                    "{} didn't finish within {timeout:?} and was killed. Increase {variable} if it needs more time",
+                    self.program
+                );
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+
+        Ok(Output {
+            status,
+            stdout: join_reader(stdout),
+            stderr: join_reader(stderr),
+        })
+    }
+
+    /// Kill the command.
+    ///
+    /// If the command leads its own process group, the whole group is killed so that no process
+    /// it started (e.g. `git-remote-https`) outlives it.
+    fn kill(&mut self) {
+        // The command is already failing. Errors while cleaning up don't add anything.
+        if self.process_group {
+            let _ = kill_process_group(Pid::from_child(&self.child), Signal::KILL);
+        } else {
+            let _ = self.child.kill();
+        }
+        let _ = self.child.wait();
+    }
+}
+
+/// Return whether commands run in their own process group.
+///
+/// Without a terminal, a command runs in its own process group so that it can be killed together
+/// with all processes it starts. With a terminal, it stays in the foreground process group instead
+/// so that Ctrl-C reaches it and it can prompt (e.g. for the passphrase of an SSH key).
+fn own_process_group() -> bool {
+    !io::stdin().is_terminal()
+}
+
+fn read_to_end_in_background(mut pipe: impl Read + Send + 'static) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let _ = pipe.read_to_end(&mut buffer);
+        buffer
+    })
+}
+
+fn join_reader(reader: Option<JoinHandle<Vec<u8>>>) -> Vec<u8> {
+    reader
+        .and_then(|reader| reader.join().ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collect_output() -> Result<()> {
+        let output = Command::new("echo")
+            .arg("hello")
+            .spawn_captured()?
+            .wait_with_timeout(Duration::from_secs(10), "LON_COMMAND_TIMEOUT")?;
+
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"hello\n");
+        Ok(())
+    }
+
+    #[test]
+    fn pass_input() -> Result<()> {
+        let output = Command::new("cat")
+            .spawn_captured_with_input(b"hello\n")?
+            .wait_with_timeout(Duration::from_secs(10), "LON_COMMAND_TIMEOUT")?;
+
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"hello\n");
+        Ok(())
+    }
+
+    #[test]
+    fn pass_input_larger_than_pipe() -> Result<()> {
+        // cat writes its output while reading its input, so the output pipe fills up before all of
+        // the input is written.
+        let input = vec![b'x'; 1 << 20];
+        let output = Command::new("cat")
+            .spawn_captured_with_input(&input)?
+            .wait_with_timeout(Duration::from_secs(10), "LON_COMMAND_TIMEOUT")?;
+
+        assert!(output.status.success());
+        assert_eq!(output.stdout, input);
+        Ok(())
+    }
+
+    #[test]
+    fn kill_after_timeout() -> Result<()> {
+        let result = Command::new("sleep")
+            .arg("10")
+            .spawn_captured()?
+            .wait_with_timeout(Duration::from_millis(100), "LON_COMMAND_TIMEOUT");
+
+        let err = result.err().map(|err| err.to_string()).unwrap_or_default();
+        assert!(err.starts_with("sleep didn't finish within 100ms"));
+        assert!(err.ends_with("Increase LON_COMMAND_TIMEOUT if it needs more time"));
+        Ok(())
+    }
+
+    #[test]
+    fn kill_started_processes_after_timeout() -> Result<()> {
+        let tmpdir = tempfile::tempdir()?;
+        let marker = tmpdir.path().join("marker");
+
+        // The shell waits for a process it started in the background.
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(format!("(sleep 0.5; touch {}) & wait", marker.display()))
+            .stdin(Stdio::null());
+        let result = RunningCommand::spawn(&mut command, true)?
+            .wait_with_timeout(Duration::from_millis(100), "LON_COMMAND_TIMEOUT");
+        assert!(result.is_err());
+
+        thread::sleep(Duration::from_secs(1));
+        assert!(!marker.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+//! Report the results of commands on stdout.
+//!
+//! By default, results are printed for humans. With `--porcelain`, they are printed in a stable
+//! line-oriented format for scripts instead. Each line consists of tab separated fields, the
+//! first of which is the kind of result. Fields are only ever appended to existing kinds.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::git::Revision;
+
+/// Whether results are printed in the porcelain format.
+static PORCELAIN: AtomicBool = AtomicBool::new(false);
+
+/// Print results in the porcelain format for the rest of the invocation.
+pub fn set_porcelain(porcelain: bool) {
+    PORCELAIN.store(porcelain, Ordering::Relaxed);
+}
+
+/// Return whether results are printed in the porcelain format.
+pub fn porcelain() -> bool {
+    PORCELAIN.load(Ordering::Relaxed)
+}
+
+/// A source was added and locked at a revision (or a URL for URL sources).
+pub fn added(name: &str, locked: &str) {
+    if porcelain() {
+        println!("added\t{name}\t{locked}");
+    } else {
+        println!("Added {name} at {locked}");
+    }
+}
+
+/// A source was updated from one revision to another.
+pub fn updated(name: &str, old_revision: &Revision, new_revision: &Revision) {
+    if porcelain() {
+        println!("updated\t{name}\t{old_revision}\t{new_revision}");
+    } else {
+        println!("Updated {name}: {old_revision} → {new_revision}");
+    }
+}
+
+/// A source was modified and is now locked at a revision (or a URL for URL sources).
+pub fn modified(name: &str, locked: &str) {
+    if porcelain() {
+        println!("modified\t{name}\t{locked}");
+    } else {
+        println!("Locked {name} at {locked}");
+    }
+}
+
+/// A source was removed.
+///
+/// Humans are informed via the log instead.
+pub fn removed(name: &str) {
+    if porcelain() {
+        println!("removed\t{name}");
+    }
+}
+
+/// A source was frozen or unfrozen.
+///
+/// Humans are informed via the log instead.
+pub fn frozen(name: &str, frozen: bool) {
+    if porcelain() {
+        let kind = if frozen { "frozen" } else { "unfrozen" };
+        println!("{kind}\t{name}");
+    }
+}
+
+/// A source was set to only be updated manually or to be updated with all others again.
+///
+/// Humans are informed via the log instead.
+pub fn manual(name: &str, manual: bool) {
+    if porcelain() {
+        let kind = if manual { "manual" } else { "automatic" };
+        println!("{kind}\t{name}");
+    }
+}
+
+/// A frozen source is behind the newest revision of its branch.
+///
+/// The number of commits in between is only reported if it is known.
+pub fn behind(name: &str, revision: &Revision, newest_revision: &Revision, commits: Option<usize>) {
+    if porcelain() {
+        let commits = commits.map(|c| c.to_string()).unwrap_or_default();
+        println!("behind\t{name}\t{revision}\t{newest_revision}\t{commits}");
+    } else if let Some(commits) = commits {
+        println!("{name} is frozen at {revision}, {commits} commits behind {newest_revision}");
+    } else {
+        println!("{name} is frozen at {revision}, behind {newest_revision}");
+    }
+}
+
+/// A source failed to update.
+///
+/// Humans are informed via the log instead.
+pub fn failed(name: &str) {
+    if porcelain() {
+        println!("failed\t{name}");
+    }
+}
+
+/// A Pull Request was opened for the update of a source.
+pub fn pull_request(name: &str, url: &str) {
+    if porcelain() {
+        println!("pull-request\t{name}\t{url}");
+    } else {
+        println!("Opened Pull Request: {url}");
+    }
+}
+
+/// A Pull Request would have been opened for the update of a source in a dry run.
+pub fn planned_pull_request(name: &str, branch: &str, title: &str, labels: &[String], body: &str) {
+    if porcelain() {
+        println!("planned-pull-request\t{name}\t{branch}");
+    } else {
+        println!("Would open Pull Request from {branch}: {title}");
+        if !labels.is_empty() {
+            println!("Labels: {}", labels.join(", "));
+        }
+        println!("\n{}\n", body.trim());
+    }
+}
+
+/// An issue listing the available updates would have been opened or updated in a dry run.
+pub fn planned_issue(title: &str, body: &str) {
+    if porcelain() {
+        println!("planned-issue");
+    } else {
+        println!(
+            "Would report available updates: {title}\n\n{}\n",
+            body.trim()
+        );
+    }
+}
+
+/// An issue listing the available updates was opened or updated.
+pub fn issue(url: &str) {
+    if porcelain() {
+        println!("issue\t{url}");
+    } else {
+        println!("Reported available updates: {url}");
+    }
+}
+
+/// An audit found a risk for a source.
+pub fn finding(severity: &str, name: &str, problem: &str, hint: &str) {
+    if porcelain() {
+        println!("finding\t{severity}\t{name}\t{problem}");
+    } else {
+        println!("{severity}: {name} {problem}\n  {hint}");
+    }
+}
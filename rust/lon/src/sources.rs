@@ -1,15 +1,27 @@
-use std::{collections::BTreeMap, path::Path};
+use std::{collections::BTreeMap, env, path::Path};
 
-use anyhow::{Context, Result};
-use nix_compat::nixhash::NixHash;
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use nix_compat::nixhash::{HashAlgo, NixHash};
 
 use crate::{
-    git::{self, RevList, Revision},
-    http::GitHubRepoApi,
-    lock, nix,
+    config,
+    error::LonError,
+    git::{self, CommitFilter, RemoteInfo, RevList, Revision},
+    http::{self, GitHubRepoApi},
+    lock, nar,
+    nix::{self, GitFetchOptions},
+    time,
 };
 
 const GITHUB_URL: &str = "https://github.com";
+const GITHUB_API_URL: &str = "https://api.github.com";
+const GITHUB_API_HOST: &str = "api.github.com";
+const BITBUCKET_URL: &str = "https://bitbucket.org";
+/// The repository all channels at channels.nixos.org are built from.
+const NIXPKGS_OWNER: &str = "NixOS";
+const NIXPKGS_REPO: &str = "nixpkgs";
+pub const FORGEJO_HOST: &str = "codeberg.org";
 
 /// Informaton summarizing an update.
 ///
@@ -44,16 +56,16 @@ pub struct Sources {
 }
 
 impl Sources {
-    /// Read lock from a directory and convert to sources.
-    pub fn read(directory: impl AsRef<Path>) -> Result<Self> {
-        let lock = lock::Lock::read(directory)?;
+    /// Read a lock file and convert it to sources.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let lock = lock::Lock::from_file(path)?;
         Ok(lock.into())
     }
 
-    /// Convert to Lock and write to file inside the specified directory.
-    pub fn write(&self, directory: impl AsRef<Path>) -> Result<()> {
+    /// Convert to Lock and write it to the lock file.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
         let lock = self.clone().into_latest_lock();
-        lock.write(directory)?;
+        lock.to_file(path)?;
         Ok(())
     }
 
@@ -72,6 +84,11 @@ impl Sources {
         self.map.remove(name);
     }
 
+    /// Get a source.
+    pub fn get(&self, name: &str) -> Option<&Source> {
+        self.map.get(name)
+    }
+
     /// Get a mutable source.
     pub fn get_mut(&mut self, name: &str) -> Option<&mut Source> {
         self.map.get_mut(name)
@@ -86,70 +103,743 @@ impl Sources {
     pub fn names(&self) -> Vec<&String> {
         self.map.keys().collect()
     }
+
+    /// Find the name of a source that fetches exactly the same as the provided source.
+    pub fn find_identical(&self, source: &Source) -> Option<&String> {
+        let identity = source.identity();
+        self.map
+            .iter()
+            .find(|(_, s)| s.identity() == identity)
+            .map(|(name, _)| name)
+    }
+
+    /// Return groups of sources that fetch exactly the same.
+    ///
+    /// Only groups with more than one source are returned.
+    pub fn duplicates(&self) -> Vec<Vec<&String>> {
+        let mut groups: BTreeMap<String, Vec<&String>> = BTreeMap::new();
+        for (name, source) in &self.map {
+            groups.entry(source.identity()).or_default().push(name);
+        }
+        groups
+            .into_values()
+            .filter(|names| names.len() > 1)
+            .collect()
+    }
 }
 
 #[derive(Clone)]
 pub enum Source {
     Git(GitSource),
     GitHub(GitHubSource),
+    Bitbucket(BitbucketSource),
+    Forgejo(ForgejoSource),
+    GitHubRelease(GitHubReleaseSource),
+    Archive(ArchiveSource),
+    Channel(ChannelSource),
+    Flake(FlakeSource),
+    Url(UrlSource),
+    Path(PathSource),
+}
+
+/// The type of a source, named like the subcommand of `lon add` that adds it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SourceKind {
+    Git,
+    #[value(name = "github")]
+    GitHub,
+    Bitbucket,
+    Forgejo,
+    #[value(name = "github-release")]
+    GitHubRelease,
+    Archive,
+    Channel,
+    Flake,
+    Url,
+    Path,
+}
+
+/// Settings and bookkeeping that all sources have, whatever their type.
+#[derive(Clone, Default)]
+pub struct SourceMeta {
+    pub frozen: bool,
+    /// Whether the source is skipped when all sources are updated
+    pub manual: bool,
+    /// Hash of the source as recorded by flakes
+    ///
+    /// Only [`Source::lock_nar_hash`] changes it so that it always belongs to the locked hash.
+    nar_hash: Option<NixHash>,
+    /// Minimum number of seconds between update checks by the bot
+    pub update_interval: Option<u64>,
+    /// Unix timestamp of the last update check of this source by Lon
+    pub last_checked: Option<u64>,
+    /// Labels the bot adds to Pull Requests for this source
+    pub labels: Vec<String>,
 }
 
 impl Source {
-    pub fn update(&mut self) -> Result<Option<UpdateSummary>> {
+    /// Return the type of the source.
+    pub fn kind(&self) -> SourceKind {
         match self {
+            Self::Git(_) => SourceKind::Git,
+            Self::GitHub(_) => SourceKind::GitHub,
+            Self::Bitbucket(_) => SourceKind::Bitbucket,
+            Self::Forgejo(_) => SourceKind::Forgejo,
+            Self::GitHubRelease(_) => SourceKind::GitHubRelease,
+            Self::Archive(_) => SourceKind::Archive,
+            Self::Channel(_) => SourceKind::Channel,
+            Self::Flake(_) => SourceKind::Flake,
+            Self::Url(_) => SourceKind::Url,
+            Self::Path(_) => SourceKind::Path,
+        }
+    }
+
+    pub fn update(&mut self) -> Result<Option<UpdateSummary>> {
+        let hash = self.hash().cloned();
+        let summary = match self {
             Self::Git(s) => s.update(),
             Self::GitHub(s) => s.update(),
+            Self::Bitbucket(s) => s.update(),
+            Self::Forgejo(s) => s.update(),
+            Self::Archive(s) => s.update(),
+            Self::GitHubRelease(s) => s.update(),
+            Self::Channel(s) => s.update(),
+            Self::Flake(s) => s.update(),
+            Self::Url(_) => {
+                log::info!("URL sources are only changed via lon modify --url");
+                Ok(None)
+            }
+            Self::Path(_) => {
+                log::info!("Path sources are used as they are");
+                Ok(None)
+            }
+        }?;
+        self.refresh_nar_hash(hash.as_ref())?;
+        if !self.meta().frozen {
+            self.record_check(time::now());
+        }
+        Ok(summary)
+    }
+
+    /// Record a successful update check for sources with an update interval.
+    ///
+    /// The check counts whether or not an update was found.
+    fn record_check(&mut self, now: u64) {
+        let meta = self.meta_mut();
+        if meta.update_interval.is_some() {
+            meta.last_checked = Some(now);
+        }
+    }
+
+    /// Find out whether an update is available without changing the source.
+    ///
+    /// Only the newest revision is resolved. Nothing is fetched or hashed.
+    pub fn pending_update(&self) -> Result<Option<UpdateSummary>> {
+        let (branch, branch_pattern, current_revision) = match self {
+            Self::Git(s) => (&s.branch, &s.branch_pattern, &s.revision),
+            Self::GitHub(s) => (
+                &s.tarball.branch,
+                &s.tarball.branch_pattern,
+                &s.tarball.revision,
+            ),
+            Self::Bitbucket(s) => (
+                &s.tarball.branch,
+                &s.tarball.branch_pattern,
+                &s.tarball.revision,
+            ),
+            Self::Forgejo(s) => (
+                &s.tarball.branch,
+                &s.tarball.branch_pattern,
+                &s.tarball.revision,
+            ),
+            Self::Archive(s) => (
+                &s.tarball.branch,
+                &s.tarball.branch_pattern,
+                &s.tarball.revision,
+            ),
+            Self::GitHubRelease(s) => return s.pending_update(),
+            Self::Channel(s) => return s.pending_update(),
+            Self::Flake(s) => return s.pending_update(),
+            Self::Url(_) | Self::Path(_) => return Ok(None),
+        };
+        let url = self.git_url()?;
+        let branch = match branch_pattern {
+            Some(branch_pattern) => git::find_newest_branch(&url, branch_pattern)?,
+            None => branch.clone(),
+        };
+
+        let newest_revision = match self {
+            Self::GitHub(s) => s.newest_revision(&branch)?,
+            _ => git::find_newest_revision(&url, &branch)?,
+        }
+        .revision();
+
+        if *current_revision == newest_revision {
+            return Ok(None);
+        }
+        Ok(Some(UpdateSummary::new(
+            current_revision.clone(),
+            newest_revision,
+        )))
+    }
+
+    /// Modify the source by changing its branch and/or its revision.
+    ///
+    /// Instead of an explicit revision, a reference can be supplied that is resolved to the
+    /// revision it currently points to. The tracked branch is not changed by this. A revision
+    /// that isn't a full commit hash (e.g. a tag name) is resolved the same way.
+    pub fn modify(
+        &mut self,
+        branch: Option<&String>,
+        revision: Option<&String>,
+        reference: Option<&String>,
+    ) -> Result<()> {
+        if let Self::GitHubRelease(_) = self {
+            if branch.is_some() || revision.is_some() || reference.is_some() {
+                bail!(
+                    "GitHub release sources always lock the newest release. Remove and add the source again to lock another release"
+                );
+            }
+            return Ok(());
+        }
+        if let Self::Channel(s) = self {
+            if revision.is_some() || reference.is_some() {
+                bail!(
+                    "Channel sources always lock the current release of their channel. Supply --branch to switch channels"
+                );
+            }
+            let hash = s.hash.clone();
+            s.modify(branch)?;
+            return self.refresh_nar_hash(Some(&hash));
+        }
+        if let Self::Flake(_) = self {
+            if branch.is_some() || revision.is_some() || reference.is_some() {
+                bail!(
+                    "Flake sources follow their flake reference. Remove and add the source again to lock another flake reference"
+                );
+            }
+            return Ok(());
+        }
+        if let Self::Url(_) = self {
+            if branch.is_some() || revision.is_some() || reference.is_some() {
+                bail!("URL sources don't track a branch. Supply --url to lock another URL");
+            }
+            return Ok(());
+        }
+        if let Self::Path(_) = self {
+            if branch.is_some() || revision.is_some() || reference.is_some() {
+                bail!("Path sources don't track a branch or lock a revision");
+            }
+            return Ok(());
+        }
+
+        let revision_reference = revision.filter(|revision| !Revision::is_commit_hash(revision));
+        if let Some(revision) = revision_reference {
+            log::info!("{revision} is not a commit hash. Resolving it as a reference...");
+        }
+        let reference = reference.or(revision_reference);
+        let remote_info = reference
+            .map(|reference| git::find_newest_revision_for_ref(&self.git_url()?, reference))
+            .transpose()?;
+        let revision = remote_info.as_ref().map(|r| &r.revision).or(revision);
+        let reference = remote_info.as_ref().map(|r| r.reference.clone());
+
+        let hash = self.hash().cloned();
+        match self {
+            Self::Git(s) => s.modify(branch, revision, reference),
+            Self::GitHub(s) => s.modify(branch, revision, reference),
+            Self::Bitbucket(s) => s.modify(branch, revision, reference),
+            Self::Forgejo(s) => s.modify(branch, revision, reference),
+            Self::Archive(s) => s.modify(branch, revision, reference),
+            Self::GitHubRelease(_)
+            | Self::Channel(_)
+            | Self::Flake(_)
+            | Self::Url(_)
+            | Self::Path(_) => Ok(()),
+        }?;
+        self.refresh_nar_hash(hash.as_ref())
+    }
+
+    /// Lock another URL.
+    ///
+    /// Only URL sources support this. The hash is computed again for the new URL.
+    pub fn set_url(&mut self, url: &str) -> Result<()> {
+        let hash = self.hash().cloned();
+        match self {
+            Self::Url(s) => s.set_url(url),
+            _ => bail!("Only URL sources can lock another URL"),
+        }?;
+        self.refresh_nar_hash(hash.as_ref())
+    }
+
+    /// Lock a revision together with a hash that was computed elsewhere.
+    ///
+    /// Nothing is fetched, so the hash is trusted as it is. The narHash and lastModified can't be
+    /// known without fetching and are dropped.
+    pub fn set_locked(&mut self, revision: &str, hash: NixHash) -> Result<()> {
+        match self {
+            Self::Git(_)
+            | Self::GitHub(_)
+            | Self::Bitbucket(_)
+            | Self::Forgejo(_)
+            | Self::Archive(_) => {}
+            Self::GitHubRelease(_) => {
+                bail!("GitHub release sources lock a release, not a revision")
+            }
+            Self::Channel(_) => bail!("Channel sources lock a release, not a revision"),
+            Self::Flake(_) => bail!("Flake sources lock a flake reference, not a revision"),
+            Self::Url(_) => bail!("URL sources lock a URL, not a revision"),
+            Self::Path(_) => bail!("Path sources don't lock a revision"),
+        }
+        if !Revision::is_commit_hash(revision) {
+            bail!("The revision {revision} must be a full commit hash");
+        }
+
+        let revision = Revision::new(revision);
+        if let Some(current_revision) = self.revision() {
+            log::info!("Updated revision: {current_revision} → {revision}");
+        }
+        match self {
+            Self::Git(s) => s.set_locked(revision, hash),
+            Self::GitHub(s) => {
+                let url = s.tarball_url(revision.as_str())?;
+                s.tarball.set_locked(&revision, None, url, hash);
+            }
+            Self::Bitbucket(s) => {
+                let url = s.tarball_url(revision.as_str())?;
+                s.tarball.set_locked(&revision, None, url, hash);
+            }
+            Self::Forgejo(s) => {
+                let url = s.tarball_url(revision.as_str())?;
+                s.tarball.set_locked(&revision, None, url, hash);
+            }
+            Self::Archive(s) => {
+                let url = s.tarball_url(revision.as_str())?;
+                s.tarball.set_locked(&revision, None, url, hash);
+            }
+            Self::GitHubRelease(_)
+            | Self::Channel(_)
+            | Self::Flake(_)
+            | Self::Url(_)
+            | Self::Path(_) => {}
+        }
+        if let Some(nar_hash) = self.nar_hash_mut() {
+            *nar_hash = None;
+        }
+        Ok(())
+    }
+
+    /// Lock the newest revision of the tracked branch again.
+    ///
+    /// This resumes following the branch after a revision or reference was locked via
+    /// [`Self::modify`].
+    pub fn unlock(&mut self) -> Result<Option<UpdateSummary>> {
+        if let Self::GitHubRelease(_) = self {
+            bail!("GitHub release sources don't track a branch and always lock the newest release");
+        }
+        if let Self::Channel(_) = self {
+            bail!("Channel sources always lock the current release of their channel");
+        }
+        if let Self::Flake(_) = self {
+            bail!("Flake sources don't track a branch and always follow their flake reference");
+        }
+        if let Self::Url(_) = self {
+            bail!("URL sources don't track a branch. Supply --url to lock another URL");
+        }
+        if let Self::Path(_) = self {
+            bail!("Path sources don't track a branch");
+        }
+        if self.meta().frozen {
+            bail!("The source is frozen. Unfreeze it first to follow its branch again");
+        }
+        self.update()
+    }
+
+    /// Enable or disable fetching submodules.
+    ///
+    /// The source is locked again at the same revision because the hash changes.
+    pub fn set_submodules(&mut self, submodules: bool) -> Result<()> {
+        let hash = self.hash().cloned();
+        match self {
+            Self::Git(s) => s.set_submodules(submodules),
+            Self::GitHub(_)
+            | Self::Bitbucket(_)
+            | Self::Forgejo(_)
+            | Self::GitHubRelease(_)
+            | Self::Archive(_)
+            | Self::Channel(_)
+            | Self::Flake(_)
+            | Self::Url(_)
+            | Self::Path(_) => {
+                bail!("Only git sources support submodules")
+            }
+        }?;
+        self.refresh_nar_hash(hash.as_ref())
+    }
+
+    /// Return the URL to the git repository of the source.
+    ///
+    /// URL sources aren't backed by a git repository.
+    pub fn git_url(&self) -> Result<String> {
+        let url = match self {
+            Self::Git(s) => s.url.clone(),
+            Self::GitHub(s) => GitHubSource::git_url(&s.owner, &s.repo),
+            Self::Bitbucket(s) => BitbucketSource::git_url(&s.workspace, &s.repo),
+            Self::Forgejo(s) => ForgejoSource::git_url(&s.host, &s.owner, &s.repo),
+            Self::Archive(s) => s.repository.clone(),
+            Self::GitHubRelease(s) => GitHubSource::git_url(&s.owner, &s.repo),
+            Self::Channel(_) => GitHubSource::git_url(NIXPKGS_OWNER, NIXPKGS_REPO),
+            Self::Flake(s) => bail!("The flake source {} isn't a git repository", s.flake_ref),
+            Self::Url(s) => bail!("The URL source {} isn't a git repository", s.url),
+            Self::Path(s) => bail!("The path source {} isn't a git repository", s.local_path),
+        };
+        Ok(url)
+    }
+
+    /// Return the settings and bookkeeping that don't depend on the type of the source.
+    pub fn meta(&self) -> &SourceMeta {
+        match self {
+            Self::Git(s) => &s.meta,
+            Self::GitHub(s) => &s.meta,
+            Self::Bitbucket(s) => &s.meta,
+            Self::Forgejo(s) => &s.meta,
+            Self::Archive(s) => &s.meta,
+            Self::GitHubRelease(s) => &s.meta,
+            Self::Channel(s) => &s.meta,
+            Self::Flake(s) => &s.meta,
+            Self::Url(s) => &s.meta,
+            Self::Path(s) => &s.meta,
+        }
+    }
+
+    /// Return the settings and bookkeeping of the source for changing them.
+    pub fn meta_mut(&mut self) -> &mut SourceMeta {
+        match self {
+            Self::Git(s) => &mut s.meta,
+            Self::GitHub(s) => &mut s.meta,
+            Self::Bitbucket(s) => &mut s.meta,
+            Self::Forgejo(s) => &mut s.meta,
+            Self::Archive(s) => &mut s.meta,
+            Self::GitHubRelease(s) => &mut s.meta,
+            Self::Channel(s) => &mut s.meta,
+            Self::Flake(s) => &mut s.meta,
+            Self::Url(s) => &mut s.meta,
+            Self::Path(s) => &mut s.meta,
+        }
+    }
+
+    /// Return the locked revision.
+    ///
+    /// URL and path sources don't lock a revision. Flake sources only lock one if their fetcher
+    /// reports it.
+    pub fn revision(&self) -> Option<&Revision> {
+        match self {
+            Self::Git(s) => Some(&s.revision),
+            Self::GitHub(s) => Some(&s.tarball.revision),
+            Self::Bitbucket(s) => Some(&s.tarball.revision),
+            Self::Forgejo(s) => Some(&s.tarball.revision),
+            Self::Archive(s) => Some(&s.tarball.revision),
+            Self::GitHubRelease(s) => Some(&s.revision),
+            Self::Channel(s) => Some(&s.revision),
+            Self::Flake(s) => s.revision.as_ref(),
+            Self::Url(_) | Self::Path(_) => None,
+        }
+    }
+
+    /// Return what the source is locked at for reporting.
+    ///
+    /// This is the revision or, for URL and path sources, the URL or the path. Flake sources
+    /// without a revision are reported by their narHash.
+    pub fn locked(&self) -> String {
+        match self {
+            Self::Flake(s) => FlakeSource::version(s.revision.as_ref(), &s.hash).to_string(),
+            Self::Url(s) => s.url.clone(),
+            Self::Path(s) => s.local_path.clone(),
+            _ => self
+                .revision()
+                .map_or_else(String::new, ToString::to_string),
         }
     }
 
-    pub fn modify(&mut self, branch: Option<&String>, revision: Option<&String>) -> Result<()> {
+    /// Return the tracked branch.
+    ///
+    /// For channel sources, this is the channel. GitHub release, flake, URL, and path sources
+    /// don't track a branch.
+    pub fn branch(&self) -> Option<&str> {
         match self {
-            Self::Git(s) => s.modify(branch, revision),
-            Self::GitHub(s) => s.modify(branch, revision),
+            Self::Git(s) => Some(&s.branch),
+            Self::GitHub(s) => Some(&s.tarball.branch),
+            Self::Bitbucket(s) => Some(&s.tarball.branch),
+            Self::Forgejo(s) => Some(&s.tarball.branch),
+            Self::Archive(s) => Some(&s.tarball.branch),
+            Self::Channel(s) => Some(&s.channel),
+            Self::GitHubRelease(_) | Self::Flake(_) | Self::Url(_) | Self::Path(_) => None,
         }
     }
 
-    pub fn freeze(&mut self) {
+    /// Return the locked hash.
+    ///
+    /// Path sources only have a hash if it was locked explicitly.
+    pub fn hash(&self) -> Option<&NixHash> {
         match self {
-            Self::Git(s) => s.frozen = true,
-            Self::GitHub(s) => s.frozen = true,
+            Self::Git(s) => Some(&s.hash),
+            Self::GitHub(s) => Some(&s.tarball.hash),
+            Self::Bitbucket(s) => Some(&s.tarball.hash),
+            Self::Forgejo(s) => Some(&s.tarball.hash),
+            Self::Archive(s) => Some(&s.tarball.hash),
+            Self::GitHubRelease(s) => Some(&s.hash),
+            Self::Channel(s) => Some(&s.hash),
+            Self::Flake(s) => Some(&s.hash),
+            Self::Url(s) => Some(&s.hash),
+            Self::Path(s) => s.hash.as_ref(),
         }
     }
 
-    pub fn unfreeze(&mut self) {
+    /// Return the narHash locked alongside the hash.
+    pub fn nar_hash(&self) -> Option<&NixHash> {
         match self {
-            Self::Git(s) => s.frozen = false,
-            Self::GitHub(s) => s.frozen = false,
+            Self::Flake(_) | Self::Path(_) => None,
+            _ => self.meta().nar_hash.as_ref(),
         }
     }
 
-    // Return whether source is frozen.
-    pub fn frozen(&self) -> bool {
+    /// Return where the narHash is stored.
+    ///
+    /// The hash of path and flake sources already is their narHash, so they don't store another.
+    fn nar_hash_mut(&mut self) -> Option<&mut Option<NixHash>> {
         match self {
-            Self::Git(s) => s.frozen,
-            Self::GitHub(s) => s.frozen,
+            Self::Flake(_) | Self::Path(_) => None,
+            _ => Some(&mut self.meta_mut().nar_hash),
+        }
+    }
+
+    /// Lock the narHash of flakes alongside the hash if `nar-hash` is enabled in lon.toml.
+    ///
+    /// Otherwise, the narHash is removed so that it never belongs to another revision than the
+    /// hash.
+    pub fn lock_nar_hash(&mut self) -> Result<()> {
+        if self.nar_hash_mut().is_none() {
+            return Ok(());
+        }
+
+        let nar_hash = if config::nar_hash() {
+            let flake_ref = self.flake_ref()?;
+            let nar_hash = nix::prefetch_flake(&flake_ref)
+                .with_context(|| format!("Failed to compute the narHash for {flake_ref}"))?
+                .hash;
+            log::info!("Locked narHash: {nar_hash}");
+            Some(nar_hash)
+        } else {
+            None
+        };
+
+        if let Some(slot) = self.nar_hash_mut() {
+            *slot = nar_hash;
+        }
+        Ok(())
+    }
+
+    /// Lock the narHash again if the hash changed from `old_hash`.
+    fn refresh_nar_hash(&mut self, old_hash: Option<&NixHash>) -> Result<()> {
+        if self.hash() == old_hash {
+            return Ok(());
         }
+        self.lock_nar_hash()
+    }
+
+    /// Return the flake reference that fetches the locked source.
+    fn flake_ref(&self) -> Result<String> {
+        let flake_ref = match self {
+            Self::Git(s) => {
+                if s.options.leave_dot_git {
+                    bail!("Flakes can't keep the .git directory of a source");
+                }
+                // Flakes don't understand the scp-like syntax (e.g. `git@github.com:nixos/nixpkgs`).
+                let url = git::fetch_url(&s.url);
+                let url = match url.split_once(':') {
+                    _ if url.contains("://") => url.to_string(),
+                    Some((host, path)) if git::is_remote_url(&url) => {
+                        format!("ssh://{host}/{path}")
+                    }
+                    _ => bail!("Flakes don't support the git URL {}", s.url),
+                };
+                let reference = s
+                    .reference
+                    .clone()
+                    .unwrap_or_else(|| format!("refs/heads/{}", s.branch));
+                let mut flake_ref = format!("git+{url}?ref={reference}&rev={}", s.revision);
+                if s.options.submodules {
+                    flake_ref.push_str("&submodules=1");
+                }
+                flake_ref
+            }
+            Self::GitHub(s) => format!("github:{}/{}/{}", s.owner, s.repo, s.tarball.revision),
+            Self::Bitbucket(s) => format!("tarball+{}", s.tarball.url),
+            Self::Forgejo(s) => format!("tarball+{}", s.tarball.url),
+            Self::Archive(s) => format!("tarball+{}", s.tarball.url),
+            Self::GitHubRelease(s) if s.unpack => format!("tarball+{}", s.url),
+            Self::GitHubRelease(s) => format!("file+{}", s.url),
+            Self::Channel(s) => format!("tarball+{}", s.url),
+            Self::Url(s) if s.unpack => format!("tarball+{}", s.url),
+            Self::Url(s) => format!("file+{}", s.url),
+            Self::Flake(s) => bail!("The flake source {} already locks its narHash", s.flake_ref),
+            Self::Path(s) => bail!("Flakes can't lock the path source {}", s.local_path),
+        };
+        Ok(flake_ref)
     }
 
-    pub fn rev_list(&self, summary: &UpdateSummary, num_commits: usize) -> Result<RevList> {
+    /// Return the Unix timestamp of the last modification of the locked revision.
+    ///
+    /// This is only known for git sources and flake sources whose fetcher reports it.
+    pub fn last_updated(&self) -> Option<u64> {
         match self {
-            Self::Git(s) => git::rev_list(
-                &s.url,
-                summary.old_revision.as_str(),
-                summary.new_revision.as_str(),
-                num_commits,
+            Self::Git(s) => s.last_modified,
+            Self::Flake(s) => s.last_modified(),
+            Self::GitHub(_)
+            | Self::Bitbucket(_)
+            | Self::Forgejo(_)
+            | Self::GitHubRelease(_)
+            | Self::Archive(_)
+            | Self::Channel(_)
+            | Self::Url(_)
+            | Self::Path(_) => None,
+        }
+    }
+
+    /// Return a key that uniquely identifies what is fetched for this source.
+    ///
+    /// Two sources with the same identity download the same content and expose the same
+    /// subdirectory of it.
+    pub fn identity(&self) -> String {
+        let identity = match self {
+            Self::Git(s) => format!(
+                "git:{}@{}?submodules={}&leaveDotGit={}&deepClone={}",
+                s.url,
+                s.revision,
+                s.options.submodules,
+                s.options.leave_dot_git,
+                s.options.deep_clone
             ),
-            Self::GitHub(s) => {
-                let github_repo_api =
-                    GitHubRepoApi::builder(&format!("{}/{}", s.owner, s.repo)).build()?;
+            Self::GitHub(s) => format!("tarball:{}", s.tarball.url),
+            Self::Bitbucket(s) => format!("tarball:{}", s.tarball.url),
+            Self::Forgejo(s) => format!("tarball:{}", s.tarball.url),
+            Self::Archive(s) => format!("tarball:{}", s.tarball.url),
+            Self::GitHubRelease(s) if s.unpack => format!("tarball:{}", s.url),
+            Self::GitHubRelease(s) => format!("file:{}", s.url),
+            Self::Channel(s) => format!("tarball:{}", s.url),
+            Self::Flake(s) => format!("flake:{}?narHash={}", s.flake_ref, s.hash),
+            Self::Url(s) if s.unpack => format!("tarball:{}", s.url),
+            Self::Url(s) => format!("file:{}", s.url),
+            Self::Path(s) => format!("path:{}", s.local_path),
+        };
+        match self.path() {
+            Some(path) => format!("{identity}#{path}"),
+            None => identity,
+        }
+    }
+
+    /// Return whether the source is due for an update check.
+    ///
+    /// Sources without an update interval are always due. Otherwise, the source is due when
+    /// neither the last update check by Lon nor the last modification of the locked revision happened
+    /// within the interval.
+    pub fn is_due(&self, now: u64) -> bool {
+        let SourceMeta {
+            update_interval,
+            last_checked,
+            ..
+        } = *self.meta();
+
+        let Some(update_interval) = update_interval else {
+            return true;
+        };
+
+        match last_checked.max(self.last_updated()) {
+            Some(last) => last.saturating_add(update_interval) <= now,
+            None => true,
+        }
+    }
+
+    /// Return the subdirectory of the fetched source that is exposed.
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Self::Git(s) => s.path.as_deref(),
+            Self::GitHub(s) => s.path.as_deref(),
+            Self::Bitbucket(s) => s.path.as_deref(),
+            Self::Forgejo(s) => s.path.as_deref(),
+            Self::Archive(s) => s.path.as_deref(),
+            Self::GitHubRelease(_)
+            | Self::Channel(_)
+            | Self::Flake(_)
+            | Self::Url(_)
+            | Self::Path(_) => None,
+        }
+    }
+
+    /// Count the commits of an update.
+    ///
+    /// Only the GitHub API counts them without fetching the history, so `None` is returned for
+    /// all other sources.
+    pub fn commits_behind(&self, summary: &UpdateSummary) -> Result<Option<usize>> {
+        match self {
+            Self::GitHub(_) | Self::GitHubRelease(_) | Self::Channel(_) => {
+                Ok(self.rev_list(summary, 0, &[], CommitFilter::All)?.total())
+            }
+            Self::Flake(s) if s.github_repo().is_some() && s.revision.is_some() => {
+                Ok(self.rev_list(summary, 0, &[], CommitFilter::All)?.total())
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// List the commits of an update.
+    ///
+    /// If `paths` isn't empty, only commits touching one of them are listed. The GitHub API
+    /// can't filter commits by path, so the history is fetched via git instead. The same applies
+    /// if the API doesn't list the newest commit when following the first parent.
+    pub fn rev_list(
+        &self,
+        summary: &UpdateSummary,
+        num_commits: usize,
+        paths: &[String],
+        filter: CommitFilter,
+    ) -> Result<RevList> {
+        let github_repo = match self {
+            Self::GitHub(GitHubSource { owner, repo, .. })
+            | Self::GitHubRelease(GitHubReleaseSource { owner, repo, .. }) => {
+                Some(format!("{owner}/{repo}"))
+            }
+            Self::Channel(_) => Some(format!("{NIXPKGS_OWNER}/{NIXPKGS_REPO}")),
+            Self::Flake(s) => s.github_repo(),
+            _ => None,
+        };
 
-                github_repo_api.compare_commits(
+        let git_url = match github_repo {
+            Some(github_repo) if paths.is_empty() => {
+                let github_repo_api = GitHubRepoApi::builder(&github_repo).build()?;
+
+                if let Some(rev_list) = github_repo_api.compare_commits(
                     summary.old_revision.as_str(),
                     summary.new_revision.as_str(),
                     num_commits,
-                )
+                    filter,
+                )? {
+                    return Ok(rev_list);
+                }
+                log::info!("The GitHub API didn't list all commits. Listing them via git...");
+                format!("{GITHUB_URL}/{github_repo}.git")
             }
-        }
+            _ => self.git_url()?,
+        };
+
+        git::rev_list(
+            &git_url,
+            summary.old_revision.as_str(),
+            summary.new_revision.as_str(),
+            num_commits,
+            paths,
+            filter,
+        )
     }
 }
 
@@ -157,14 +847,22 @@ impl Source {
 pub struct GitSource {
     url: String,
     branch: String,
+    /// Glob pattern the branch is resolved from on every update
+    branch_pattern: Option<String>,
     revision: Revision,
     hash: NixHash,
     last_modified: Option<u64>,
 
-    /// Whether to fetch submodules
-    submodules: bool,
+    /// Fully qualified reference the revision was resolved from
+    reference: Option<String>,
+
+    /// Options that change what is fetched
+    options: GitFetchOptions,
 
-    frozen: bool,
+    /// Subdirectory of the fetched source to expose
+    path: Option<String>,
+
+    meta: SourceMeta,
 }
 
 impl GitSource {
@@ -172,40 +870,55 @@ impl GitSource {
         url: &str,
         branch: &str,
         revision: Option<&String>,
-        submodules: bool,
+        options: GitFetchOptions,
         frozen: bool,
+        path: Option<&String>,
     ) -> Result<Self> {
-        let rev = match revision {
-            Some(rev) => rev,
-            None => &git::find_newest_revision(url, branch)?.to_string(),
+        let path = path.map(|p| normalize_path(p)).transpose()?;
+        let (branch, branch_pattern) = resolve_branch(url, branch)?;
+
+        let (rev, reference) = if let Some(rev) = revision {
+            (rev.clone(), None)
+        } else {
+            let remote_info = git::find_newest_revision(url, &branch)?;
+            (remote_info.revision, Some(remote_info.reference))
         };
         log::info!("Locked revision: {rev}");
 
-        let hash = Self::compute_hash(url, rev, submodules)?;
+        let fallback_ref = reference
+            .clone()
+            .unwrap_or_else(|| format!("refs/heads/{branch}"));
+        let (hash, last_modified) = Self::prefetch(url, &rev, options, &fallback_ref)?;
         log::info!("Locked hash: {hash}");
-
-        let last_modified = git::get_last_modified(url, rev)?;
         log::info!("Locked lastModified: {last_modified}");
 
         Ok(Self {
             url: url.into(),
-            branch: branch.into(),
-            revision: Revision::new(rev),
+            branch,
+            branch_pattern,
+            revision: Revision::new(&rev),
             hash,
             last_modified: Some(last_modified),
-            submodules,
-            frozen,
+            reference,
+            options,
+            meta: SourceMeta {
+                frozen,
+                ..SourceMeta::default()
+            },
+            path,
         })
     }
 
     /// Update the source by finding the newest commit.
     fn update(&mut self) -> Result<Option<UpdateSummary>> {
-        if self.frozen {
+        if self.meta.frozen {
             log::info!("Source is frozen");
             return Ok(None);
         }
 
-        let newest_revision = git::find_newest_revision(&self.url, &self.branch)?;
+        update_branch(&self.url, &mut self.branch, self.branch_pattern.as_deref())?;
+        let remote_info = git::find_newest_revision(&self.url, &self.branch)?;
+        let newest_revision = remote_info.revision();
 
         let current_revision = self.revision.clone();
 
@@ -214,19 +927,24 @@ impl GitSource {
             return Ok(None);
         }
         log::info!("Updated revision: {current_revision} → {newest_revision}");
-        self.lock(&newest_revision)?;
+        self.lock(&newest_revision, Some(remote_info.reference))?;
         Ok(Some(UpdateSummary::new(current_revision, newest_revision)))
     }
 
     /// Lock the source to a new revision.
     ///
-    /// In this case this means that the revision and hash.
-    fn lock(&mut self, revision: &Revision) -> Result<()> {
-        let new_hash = Self::compute_hash(&self.url, revision.as_str(), self.submodules)?;
+    /// In this case this means that the revision and hash. The reference records where the
+    /// revision was resolved from, if anywhere.
+    fn lock(&mut self, revision: &Revision, reference: Option<String>) -> Result<()> {
+        let fallback_ref = reference
+            .clone()
+            .unwrap_or_else(|| format!("refs/heads/{}", self.branch));
+        let (new_hash, last_modified) =
+            Self::prefetch(&self.url, revision.as_str(), self.options, &fallback_ref)?;
         log::info!("Updated hash: {} → {}", self.hash, new_hash);
         self.revision = revision.clone();
         self.hash = new_hash;
-        let last_modified = git::get_last_modified(self.url.as_str(), revision.as_str())?;
+        self.reference = reference;
         if let Some(value) = self.last_modified {
             log::info!("Updated lastModified: {value} → {last_modified}");
         } else {
@@ -237,13 +955,27 @@ impl GitSource {
     }
 
     /// Modify the source by changing its branch and/or its revision.
-    fn modify(&mut self, branch: Option<&String>, revision: Option<&String>) -> Result<()> {
+    ///
+    /// The reference records where the revision was resolved from, if anywhere.
+    fn modify(
+        &mut self,
+        branch: Option<&String>,
+        revision: Option<&String>,
+        reference: Option<String>,
+    ) -> Result<()> {
         if let Some(branch) = branch {
-            if self.branch == *branch {
+            if self.branch_pattern.as_ref().unwrap_or(&self.branch) == branch {
                 log::info!("Branch is already {branch}");
             } else {
-                log::info!("Changed branch: {} → {}", self.branch, branch);
-                self.branch = branch.into();
+                let (resolved, branch_pattern) = resolve_branch(&self.url, branch)?;
+                if let Some(revision) = revision
+                    && reference.is_none()
+                {
+                    ensure_on_branch(&self.url, revision, &resolved)?;
+                }
+                log::info!("Changed branch: {} → {}", self.branch, resolved);
+                self.branch = resolved;
+                self.branch_pattern = branch_pattern;
                 if revision.is_none() {
                     self.update()?;
                 }
@@ -254,143 +986,1306 @@ impl GitSource {
                 log::info!("Revision is already {revision}");
             } else {
                 log::info!("Changed revision: {} → {}", self.revision, revision);
-                self.lock(&Revision::new(revision))?;
+                self.lock(&Revision::new(revision), reference)?;
             }
         }
         Ok(())
     }
 
-    /// Computing the hash for this source type.
-    fn compute_hash(url: &str, revision: &str, submodules: bool) -> Result<NixHash> {
-        nix::prefetch_git(url, revision, submodules)
-            .with_context(|| format!("Failed to compute hash for {url}@{revision}"))
+    /// Record a revision and a hash that were computed elsewhere as the locked ones.
+    fn set_locked(&mut self, revision: Revision, hash: NixHash) {
+        log::info!("Updated hash: {} → {}", self.hash, hash);
+        if self.last_modified.is_some() {
+            log::warn!("Removed lastModified because it can't be known without fetching");
+        }
+        self.revision = revision;
+        self.hash = hash;
+        self.reference = None;
+        self.last_modified = None;
+    }
+
+    /// Enable or disable fetching submodules and lock the current revision again.
+    fn set_submodules(&mut self, submodules: bool) -> Result<()> {
+        let state = if submodules { "enabled" } else { "disabled" };
+        if self.options.submodules == submodules {
+            log::info!("Submodules are already {state}");
+            return Ok(());
+        }
+        log::info!("Submodules {state}");
+        self.options.submodules = submodules;
+        let revision = self.revision.clone();
+        self.lock(&revision, self.reference.clone())
+    }
+
+    /// Compute the hash and the last modification time for this source type.
+    ///
+    /// The last modification time is taken from the prefetch. Only if nix-prefetch-git doesn't
+    /// report it, the revision is fetched again from the fallback reference.
+    fn prefetch(
+        url: &str,
+        revision: &str,
+        options: GitFetchOptions,
+        fallback_ref: &str,
+    ) -> Result<(NixHash, u64)> {
+        let prefetch = nix::prefetch_git(url, revision, options)
+            .with_context(|| format!("Failed to compute hash for {url}@{revision}"))?;
+        let last_modified = match prefetch.last_modified {
+            Some(last_modified) => last_modified,
+            None => git::get_last_modified(url, revision, fallback_ref)?,
+        };
+        Ok((prefetch.hash, last_modified))
     }
 }
 
+/// The locked revision of a git repository that is fetched as a tarball from a forge.
 #[derive(Clone)]
-pub struct GitHubSource {
-    owner: String,
-    repo: String,
+struct Tarball {
     branch: String,
+    /// Glob pattern the branch is resolved from on every update
+    branch_pattern: Option<String>,
     revision: Revision,
     url: String,
     hash: NixHash,
+    /// Fully qualified reference the revision was resolved from
+    reference: Option<String>,
+}
 
-    frozen: bool,
+impl Tarball {
+    /// Record a prefetched revision as the locked one.
+    fn set_locked(
+        &mut self,
+        revision: &Revision,
+        reference: Option<String>,
+        url: String,
+        hash: NixHash,
+    ) {
+        log::info!("Updated hash: {} → {}", self.hash, hash);
+        self.revision = revision.clone();
+        self.hash = hash;
+        self.url = url;
+        self.reference = reference;
+    }
 }
 
-impl GitHubSource {
-    pub fn new(
-        owner: &str,
-        repo: &str,
-        branch: &str,
-        revision: Option<&String>,
-        frozen: bool,
-    ) -> Result<Self> {
-        let rev = match revision {
-            Some(rev) => rev,
-            None => &git::find_newest_revision(&Self::git_url(owner, repo), branch)?.to_string(),
-        };
-        log::info!("Locked revision: {rev}");
+/// A forge that serves tarballs of the revisions of its git repositories.
+///
+/// Forges only differ in where the repository and its tarballs are. Updating and modifying the
+/// locked [`Tarball`] is the same for all of them.
+trait TarballForge {
+    fn tarball(&self) -> &Tarball;
 
-        let url = Self::url(owner, repo, rev);
+    fn tarball_mut(&mut self) -> &mut Tarball;
 
-        let hash = Self::compute_hash(&url)?;
-        log::info!("Locked hash: {hash}");
+    fn frozen(&self) -> bool;
 
-        Ok(Self {
-            owner: owner.into(),
-            repo: repo.into(),
-            url,
-            branch: branch.into(),
-            revision: Revision::new(rev),
-            hash,
-            frozen,
-        })
+    /// Return the URL to the git repository.
+    fn repository_url(&self) -> String;
+
+    /// Return the URL to the tarball of a revision.
+    fn tarball_url(&self, revision: &str) -> Result<String>;
+
+    /// Compute the hash of the tarball of a revision.
+    fn hash_tarball(&self, _revision: &str, url: &str, hash_algo: HashAlgo) -> Result<NixHash> {
+        compute_tarball_hash(url, hash_algo)
+    }
+
+    /// Find the newest revision for a branch.
+    fn newest_revision(&self, branch: &str) -> Result<RemoteInfo> {
+        git::find_newest_revision(&self.repository_url(), branch)
     }
 
     /// Update the source by finding the newest commit.
     fn update(&mut self) -> Result<Option<UpdateSummary>> {
-        if self.frozen {
+        if self.frozen() {
             log::info!("Source is frozen");
             return Ok(None);
         }
 
-        let newest_revision =
-            git::find_newest_revision(&Self::git_url(&self.owner, &self.repo), &self.branch)?;
+        let repository_url = self.repository_url();
+        let tarball = self.tarball_mut();
+        update_branch(
+            &repository_url,
+            &mut tarball.branch,
+            tarball.branch_pattern.as_deref(),
+        )?;
+        let remote_info = self.newest_revision(&self.tarball().branch)?;
+        let newest_revision = remote_info.revision();
 
-        let current_revision = self.revision.clone();
+        let current_revision = self.tarball().revision.clone();
 
         if current_revision == newest_revision {
             log::info!("Already up to date");
             return Ok(None);
         }
 
+        // The archive of a new revision can be identical to the locked one (e.g. for empty
+        // commits). Don't churn the lock file in this case.
+        let (new_url, new_hash) = self.prefetch(&newest_revision)?;
+        if new_hash == self.tarball().hash {
+            log::info!("Content of {newest_revision} is unchanged. Keeping {current_revision}");
+            return Ok(None);
+        }
+
         log::info!("Updated revision: {current_revision} → {newest_revision}");
-        self.lock(&newest_revision)?;
+        self.tarball_mut().set_locked(
+            &newest_revision,
+            Some(remote_info.reference),
+            new_url,
+            new_hash,
+        );
         Ok(Some(UpdateSummary::new(current_revision, newest_revision)))
     }
 
     /// Lock the source to a specific revision.
     ///
-    /// In this case this means that the revision, hash, and URL is updated.
-    fn lock(&mut self, revision: &Revision) -> Result<()> {
-        let new_url = Self::url(&self.owner, &self.repo, revision.as_str());
-        let new_hash = Self::compute_hash(&new_url)?;
-        log::info!("Updated hash: {} → {}", self.hash, new_hash);
-        self.revision = revision.clone();
-        self.hash = new_hash;
-        self.url = new_url;
+    /// In this case this means that the revision, hash, and URL is updated. The hash is computed
+    /// with the same algorithm as the previous one. The reference records where the revision was
+    /// resolved from, if anywhere.
+    fn lock(&mut self, revision: &Revision, reference: Option<String>) -> Result<()> {
+        let (new_url, new_hash) = self.prefetch(revision)?;
+        self.tarball_mut()
+            .set_locked(revision, reference, new_url, new_hash);
         Ok(())
     }
 
+    /// Return the URL and the hash of the tarball for a revision.
+    fn prefetch(&self, revision: &Revision) -> Result<(String, NixHash)> {
+        let url = self.tarball_url(revision.as_str())?;
+        let hash = self.hash_tarball(revision.as_str(), &url, self.tarball().hash.algo())?;
+        Ok((url, hash))
+    }
+
     /// Modify the source by changing its branch and/or its revision.
-    fn modify(&mut self, branch: Option<&String>, revision: Option<&String>) -> Result<()> {
+    ///
+    /// The reference records where the revision was resolved from, if anywhere.
+    fn modify(
+        &mut self,
+        branch: Option<&String>,
+        revision: Option<&String>,
+        reference: Option<String>,
+    ) -> Result<()> {
         if let Some(branch) = branch {
-            if self.branch == *branch {
+            let tarball = self.tarball();
+            if tarball.branch_pattern.as_ref().unwrap_or(&tarball.branch) == branch {
                 log::info!("Branch is already {branch}");
             } else {
-                log::info!("Changed branch: {} → {}", self.branch, branch);
-                self.branch = branch.into();
+                let repository_url = self.repository_url();
+                let (resolved, branch_pattern) = resolve_branch(&repository_url, branch)?;
+                if let Some(revision) = revision
+                    && reference.is_none()
+                {
+                    ensure_on_branch(&repository_url, revision, &resolved)?;
+                }
+                let tarball = self.tarball_mut();
+                log::info!("Changed branch: {} → {}", tarball.branch, resolved);
+                tarball.branch = resolved;
+                tarball.branch_pattern = branch_pattern;
                 if revision.is_none() {
                     self.update()?;
                 }
             }
         }
         if let Some(revision) = revision {
-            if self.revision.as_str() == revision {
+            let current_revision = &self.tarball().revision;
+            if current_revision.as_str() == revision {
                 log::info!("Revision is already {revision}");
             } else {
-                log::info!("Changed revision: {} → {}", self.revision, revision);
-                self.lock(&Revision::new(revision))?;
+                log::info!("Changed revision: {current_revision} → {revision}");
+                self.lock(&Revision::new(revision), reference)?;
             }
         }
         Ok(())
     }
+}
 
-    /// Compute the hash for this source type.
-    fn compute_hash(url: &str) -> Result<NixHash> {
-        nix::prefetch_tarball(url).with_context(|| format!("Failed to compute hash for {url}"))
-    }
-
-    /// Return the URL to a GitHub tarball for the revision of the source.
-    fn url(owner: &str, repo: &str, revision: &str) -> String {
-        format!("{GITHUB_URL}/{owner}/{repo}/archive/{revision}.tar.gz")
-    }
+/// Compute the hash of a tarball that is downloaded without credentials.
+fn compute_tarball_hash(url: &str, hash_algo: HashAlgo) -> Result<NixHash> {
+    nix::prefetch_tarball(url, None, hash_algo)
+        .with_context(|| format!("Failed to compute hash for {url}"))
+}
 
-    /// Return the URL to the GitHub repository.
-    fn git_url(owner: &str, repo: &str) -> String {
-        format!("{GITHUB_URL}/{owner}/{repo}.git")
+/// Compute the hash of a file that is downloaded without credentials.
+///
+/// An unpacked file has the same hash as with `fetchTarball`, otherwise the same as with
+/// `fetchurl`.
+fn compute_file_hash(url: &str, unpack: bool, hash_algo: HashAlgo) -> Result<NixHash> {
+    if unpack {
+        return compute_tarball_hash(url, hash_algo);
     }
+    nix::prefetch_file(url, hash_algo).with_context(|| format!("Failed to compute hash for {url}"))
 }
 
-// Boilerplate to convert between the internal representation (Sources) and the external lock file
-// representation.
-//
-// This seems like a lot of duplication but it is mostly incidental duplication. Once we add more
-// lockfile versions this'll become clear.
+#[derive(Clone)]
+pub struct GitHubSource {
+    owner: String,
+    repo: String,
+    tarball: Tarball,
 
-impl From<lock::Lock> for Sources {
+    /// Subdirectory of the fetched source to expose
+    path: Option<String>,
+
+    meta: SourceMeta,
+}
+
+impl GitHubSource {
+    pub fn new(
+        owner: &str,
+        repo: &str,
+        branch: &str,
+        revision: Option<&String>,
+        frozen: bool,
+        path: Option<&String>,
+        hash_algo: HashAlgo,
+    ) -> Result<Self> {
+        let path = path.map(|p| normalize_path(p)).transpose()?;
+        let git_url = Self::git_url(owner, repo);
+        let (branch, branch_pattern) = resolve_branch(&git_url, branch)?;
+
+        let (rev, reference) = if let Some(rev) = revision {
+            (rev.clone(), None)
+        } else {
+            let remote_info = Self::find_newest_revision(owner, repo, &branch)?;
+            (remote_info.revision, Some(remote_info.reference))
+        };
+        log::info!("Locked revision: {rev}");
+
+        let url = Self::url(owner, repo, &rev);
+
+        let hash = Self::compute_hash(owner, repo, &rev, &url, hash_algo)?;
+        log::info!("Locked hash: {hash}");
+
+        Ok(Self {
+            owner: owner.into(),
+            repo: repo.into(),
+            tarball: Tarball {
+                branch,
+                branch_pattern,
+                revision: Revision::new(&rev),
+                url,
+                hash,
+                reference,
+            },
+            meta: SourceMeta {
+                frozen,
+                ..SourceMeta::default()
+            },
+            path,
+        })
+    }
+
+    /// Compute the hash for this source type.
+    ///
+    /// If `LON_GITHUB_TOKEN` is set, the tarball is downloaded via the authenticated GitHub API so
+    /// that private repositories can be fetched. The resulting hash is the same as for the public
+    /// tarball URL.
+    fn compute_hash(
+        owner: &str,
+        repo: &str,
+        revision: &str,
+        url: &str,
+        hash_algo: HashAlgo,
+    ) -> Result<NixHash> {
+        if let Ok(token) = env::var("LON_GITHUB_TOKEN") {
+            let api_url = format!("{GITHUB_API_URL}/repos/{owner}/{repo}/tarball/{revision}");
+            let credentials = nix::Credentials {
+                host: GITHUB_API_HOST,
+                token: &token,
+            };
+            return nix::prefetch_tarball(&api_url, Some(&credentials), hash_algo)
+                .with_context(|| format!("Failed to compute hash for {api_url}"));
+        }
+
+        nix::prefetch_tarball(url, None, hash_algo)
+            .with_context(|| format!("Failed to compute hash for {url}"))
+    }
+
+    /// Find the newest revision for a branch.
+    ///
+    /// If `LON_GITHUB_TOKEN` is set, the branch is resolved via the GitHub API instead of git so
+    /// that no git access to GitHub is required.
+    fn find_newest_revision(owner: &str, repo: &str, branch: &str) -> Result<RemoteInfo> {
+        let Ok(token) = env::var("LON_GITHUB_TOKEN") else {
+            return git::find_newest_revision(&Self::git_url(owner, repo), branch);
+        };
+
+        let revision = GitHubRepoApi::builder(&format!("{owner}/{repo}"))
+            .token(&token)
+            .build()?
+            .branch_revision(branch)
+            .with_context(|| {
+                format!("Failed to find newest revision for {owner}/{repo} ({branch})")
+            })?;
+        let remote_info = RemoteInfo {
+            revision,
+            reference: format!("refs/heads/{branch}"),
+        };
+        log::info!(
+            "Resolved {} → {}",
+            remote_info.reference,
+            remote_info.revision
+        );
+        Ok(remote_info)
+    }
+
+    /// Return the URL to a GitHub tarball for the revision of the source.
+    fn url(owner: &str, repo: &str, revision: &str) -> String {
+        format!("{GITHUB_URL}/{owner}/{repo}/archive/{revision}.tar.gz")
+    }
+
+    /// Return the URL to the GitHub repository.
+    fn git_url(owner: &str, repo: &str) -> String {
+        format!("{GITHUB_URL}/{owner}/{repo}.git")
+    }
+}
+
+impl TarballForge for GitHubSource {
+    fn tarball(&self) -> &Tarball {
+        &self.tarball
+    }
+
+    fn tarball_mut(&mut self) -> &mut Tarball {
+        &mut self.tarball
+    }
+
+    fn frozen(&self) -> bool {
+        self.meta.frozen
+    }
+
+    fn repository_url(&self) -> String {
+        Self::git_url(&self.owner, &self.repo)
+    }
+
+    fn tarball_url(&self, revision: &str) -> Result<String> {
+        Ok(Self::url(&self.owner, &self.repo, revision))
+    }
+
+    fn hash_tarball(&self, revision: &str, url: &str, hash_algo: HashAlgo) -> Result<NixHash> {
+        Self::compute_hash(&self.owner, &self.repo, revision, url, hash_algo)
+    }
+
+    fn newest_revision(&self, branch: &str) -> Result<RemoteInfo> {
+        Self::find_newest_revision(&self.owner, &self.repo, branch)
+    }
+}
+
+#[derive(Clone)]
+pub struct BitbucketSource {
+    workspace: String,
+    repo: String,
+    tarball: Tarball,
+
+    /// Subdirectory of the fetched source to expose
+    path: Option<String>,
+
+    meta: SourceMeta,
+}
+
+impl BitbucketSource {
+    pub fn new(
+        workspace: &str,
+        repo: &str,
+        branch: &str,
+        revision: Option<&String>,
+        frozen: bool,
+        path: Option<&String>,
+    ) -> Result<Self> {
+        let path = path.map(|p| normalize_path(p)).transpose()?;
+        let git_url = Self::git_url(workspace, repo);
+        let (branch, branch_pattern) = resolve_branch(&git_url, branch)?;
+
+        let (rev, reference) = if let Some(rev) = revision {
+            (rev.clone(), None)
+        } else {
+            let remote_info = git::find_newest_revision(&git_url, &branch)?;
+            (remote_info.revision, Some(remote_info.reference))
+        };
+        log::info!("Locked revision: {rev}");
+
+        let url = Self::url(workspace, repo, &rev);
+
+        // The single top-level directory of the tarball ({workspace}-{repo}-{short revision}) is
+        // stripped when unpacking, just like for GitHub tarballs.
+        let hash = compute_tarball_hash(&url, HashAlgo::Sha256)?;
+        log::info!("Locked hash: {hash}");
+
+        Ok(Self {
+            workspace: workspace.into(),
+            repo: repo.into(),
+            tarball: Tarball {
+                branch,
+                branch_pattern,
+                revision: Revision::new(&rev),
+                url,
+                hash,
+                reference,
+            },
+            meta: SourceMeta {
+                frozen,
+                ..SourceMeta::default()
+            },
+            path,
+        })
+    }
+
+    /// Return the URL to a Bitbucket tarball for the revision of the source.
+    fn url(workspace: &str, repo: &str, revision: &str) -> String {
+        format!("{BITBUCKET_URL}/{workspace}/{repo}/get/{revision}.tar.gz")
+    }
+
+    /// Return the URL to the Bitbucket repository.
+    fn git_url(workspace: &str, repo: &str) -> String {
+        format!("{BITBUCKET_URL}/{workspace}/{repo}.git")
+    }
+}
+
+impl TarballForge for BitbucketSource {
+    fn tarball(&self) -> &Tarball {
+        &self.tarball
+    }
+
+    fn tarball_mut(&mut self) -> &mut Tarball {
+        &mut self.tarball
+    }
+
+    fn frozen(&self) -> bool {
+        self.meta.frozen
+    }
+
+    fn repository_url(&self) -> String {
+        Self::git_url(&self.workspace, &self.repo)
+    }
+
+    fn tarball_url(&self, revision: &str) -> Result<String> {
+        Ok(Self::url(&self.workspace, &self.repo, revision))
+    }
+}
+
+/// A repository on a Forgejo or Gitea instance (e.g. Codeberg).
+#[derive(Clone)]
+pub struct ForgejoSource {
+    /// Host of the Forgejo or Gitea instance
+    host: String,
+    owner: String,
+    repo: String,
+    tarball: Tarball,
+
+    /// Subdirectory of the fetched source to expose
+    path: Option<String>,
+
+    meta: SourceMeta,
+}
+
+impl ForgejoSource {
+    pub fn new(
+        host: &str,
+        owner: &str,
+        repo: &str,
+        branch: &str,
+        revision: Option<&String>,
+        frozen: bool,
+        path: Option<&String>,
+    ) -> Result<Self> {
+        let path = path.map(|p| normalize_path(p)).transpose()?;
+        let git_url = Self::git_url(host, owner, repo);
+        let (branch, branch_pattern) = resolve_branch(&git_url, branch)?;
+
+        let (rev, reference) = if let Some(rev) = revision {
+            (rev.clone(), None)
+        } else {
+            let remote_info = git::find_newest_revision(&git_url, &branch)?;
+            (remote_info.revision, Some(remote_info.reference))
+        };
+        log::info!("Locked revision: {rev}");
+
+        let url = Self::url(host, owner, repo, &rev);
+
+        let hash = compute_tarball_hash(&url, HashAlgo::Sha256)?;
+        log::info!("Locked hash: {hash}");
+
+        Ok(Self {
+            host: host.into(),
+            owner: owner.into(),
+            repo: repo.into(),
+            tarball: Tarball {
+                branch,
+                branch_pattern,
+                revision: Revision::new(&rev),
+                url,
+                hash,
+                reference,
+            },
+            meta: SourceMeta {
+                frozen,
+                ..SourceMeta::default()
+            },
+            path,
+        })
+    }
+
+    /// Return the URL to a Forgejo tarball for the revision of the source.
+    fn url(host: &str, owner: &str, repo: &str, revision: &str) -> String {
+        format!("https://{host}/{owner}/{repo}/archive/{revision}.tar.gz")
+    }
+
+    /// Return the URL to the Forgejo repository.
+    fn git_url(host: &str, owner: &str, repo: &str) -> String {
+        format!("https://{host}/{owner}/{repo}.git")
+    }
+}
+
+impl TarballForge for ForgejoSource {
+    fn tarball(&self) -> &Tarball {
+        &self.tarball
+    }
+
+    fn tarball_mut(&mut self) -> &mut Tarball {
+        &mut self.tarball
+    }
+
+    fn frozen(&self) -> bool {
+        self.meta.frozen
+    }
+
+    fn repository_url(&self) -> String {
+        Self::git_url(&self.host, &self.owner, &self.repo)
+    }
+
+    fn tarball_url(&self, revision: &str) -> Result<String> {
+        Ok(Self::url(&self.host, &self.owner, &self.repo, revision))
+    }
+}
+
+/// Templates of the archive URLs of known forges.
+///
+/// All of them strip the single top-level directory of the archive when unpacking.
+const ARCHIVE_URL_PRESETS: &[(&str, &str)] = &[
+    (
+        "github",
+        "https://{host}/{owner}/{repo}/archive/{rev}.tar.gz",
+    ),
+    (
+        "forgejo",
+        "https://{host}/{owner}/{repo}/archive/{rev}.tar.gz",
+    ),
+    (
+        "gitea",
+        "https://{host}/{owner}/{repo}/archive/{rev}.tar.gz",
+    ),
+    (
+        "gitlab",
+        "https://{host}/{owner}/{repo}/-/archive/{rev}/{repo}-{rev}.tar.gz",
+    ),
+    (
+        "sourcehut",
+        "https://{host}/{owner}/{repo}/archive/{rev}.tar.gz",
+    ),
+    ("cgit", "https://{host}/{path}/snapshot/{repo}-{rev}.tar.gz"),
+];
+
+/// The placeholders an archive URL template can contain.
+///
+/// `{host}`, `{owner}`, `{repo}` and `{path}` are taken from the URL of the repository. `{owner}`
+/// is everything before the last path component (e.g. nested GitLab groups) and `{path}` is the
+/// whole path as given.
+const ARCHIVE_URL_PLACEHOLDERS: &[&str] = &["host", "owner", "repo", "path", "rev"];
+
+/// Components of the URL of a git repository.
+pub struct RepositoryUrl<'a> {
+    pub host: &'a str,
+    pub owner: &'a str,
+    pub repo: &'a str,
+    pub path: &'a str,
+}
+
+impl<'a> RepositoryUrl<'a> {
+    /// Split a URL like `https://codeberg.org/owner/repo.git` into its components.
+    pub fn parse(url: &'a str) -> Result<Self> {
+        let (_, rest) = url
+            .split_once("://")
+            .with_context(|| format!("Failed to parse repository URL {url}. Expected a scheme"))?;
+        let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+        let host = authority.rsplit('@').next().unwrap_or(authority);
+        let path = path.trim_matches('/');
+        let (owner, repo) = path.rsplit_once('/').unwrap_or(("", path));
+        let repo = repo.strip_suffix(".git").unwrap_or(repo);
+
+        if host.is_empty() || repo.is_empty() {
+            bail!("Failed to parse repository URL {url}. Expected {{scheme}}://{{host}}/{{path}}")
+        }
+
+        Ok(Self {
+            host,
+            owner,
+            repo,
+            path,
+        })
+    }
+}
+
+/// Resolve the archive URL template for `lon add archive`.
+///
+/// Accepts the name of a preset (e.g. `forgejo`) or a template containing `{rev}`.
+pub fn archive_url_template(value: &str) -> Result<String> {
+    if let Some((_, template)) = ARCHIVE_URL_PRESETS.iter().find(|(name, _)| *name == value) {
+        return Ok((*template).into());
+    }
+
+    if !value.contains("{rev}") {
+        bail!(
+            "The archive URL {value} must either contain {{rev}} or be one of the presets {}",
+            ARCHIVE_URL_PRESETS
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    for placeholder in value.split('{').skip(1).filter_map(|s| s.split_once('}')) {
+        if !ARCHIVE_URL_PLACEHOLDERS.contains(&placeholder.0) {
+            bail!(
+                "Unknown placeholder {{{}}} in the archive URL {value}. Supported placeholders: {}",
+                placeholder.0,
+                ARCHIVE_URL_PLACEHOLDERS
+                    .iter()
+                    .map(|p| format!("{{{p}}}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+    }
+    Ok(value.into())
+}
+
+/// A git repository that is fetched as a tarball from an archive URL.
+///
+/// This supports any forge that serves archives of revisions without a dedicated source type.
+#[derive(Clone)]
+pub struct ArchiveSource {
+    /// URL of the git repository the branch is resolved from
+    repository: String,
+    /// Template of the URL of the archive of a revision
+    archive_url: String,
+    tarball: Tarball,
+
+    /// Subdirectory of the fetched source to expose
+    path: Option<String>,
+
+    meta: SourceMeta,
+}
+
+impl ArchiveSource {
+    pub fn new(
+        repository: &str,
+        archive_url: &str,
+        branch: &str,
+        revision: Option<&String>,
+        frozen: bool,
+        path: Option<&String>,
+    ) -> Result<Self> {
+        let path = path.map(|p| normalize_path(p)).transpose()?;
+        let (branch, branch_pattern) = resolve_branch(repository, branch)?;
+
+        let (rev, reference) = if let Some(rev) = revision {
+            (rev.clone(), None)
+        } else {
+            let remote_info = git::find_newest_revision(repository, &branch)?;
+            (remote_info.revision, Some(remote_info.reference))
+        };
+        log::info!("Locked revision: {rev}");
+
+        let url = Self::url(archive_url, repository, &rev)?;
+        log::info!("Locked archive: {url}");
+
+        let hash = compute_tarball_hash(&url, HashAlgo::Sha256)?;
+        log::info!("Locked hash: {hash}");
+
+        Ok(Self {
+            repository: repository.into(),
+            archive_url: archive_url.into(),
+            tarball: Tarball {
+                branch,
+                branch_pattern,
+                revision: Revision::new(&rev),
+                url,
+                hash,
+                reference,
+            },
+            meta: SourceMeta {
+                frozen,
+                ..SourceMeta::default()
+            },
+            path,
+        })
+    }
+
+    /// Render the archive URL template for a revision.
+    fn url(template: &str, repository: &str, revision: &str) -> Result<String> {
+        let repository = RepositoryUrl::parse(repository)?;
+        Ok(template
+            .replace("{host}", repository.host)
+            .replace("{owner}", repository.owner)
+            .replace("{repo}", repository.repo)
+            .replace("{path}", repository.path)
+            .replace("{rev}", revision))
+    }
+}
+
+impl TarballForge for ArchiveSource {
+    fn tarball(&self) -> &Tarball {
+        &self.tarball
+    }
+
+    fn tarball_mut(&mut self) -> &mut Tarball {
+        &mut self.tarball
+    }
+
+    fn frozen(&self) -> bool {
+        self.meta.frozen
+    }
+
+    fn repository_url(&self) -> String {
+        self.repository.clone()
+    }
+
+    fn tarball_url(&self, revision: &str) -> Result<String> {
+        Self::url(&self.archive_url, &self.repository, revision)
+    }
+}
+
+/// An asset of the newest release of a GitHub repository.
+///
+/// The asset is the one whose name matches a glob pattern. It is locked either as a single file or
+/// unpacked.
+#[derive(Clone)]
+pub struct GitHubReleaseSource {
+    owner: String,
+    repo: String,
+    /// Tag of the locked release
+    tag: String,
+    /// Glob pattern the name of the asset is matched against
+    asset: String,
+    /// Revision the tag points to
+    revision: Revision,
+    url: String,
+    hash: NixHash,
+    /// Whether the asset is an archive that is unpacked instead of a single file
+    unpack: bool,
+
+    meta: SourceMeta,
+}
+
+impl GitHubReleaseSource {
+    /// Lock an asset of a release.
+    ///
+    /// If no tag is supplied, the newest release is used.
+    pub fn new(
+        owner: &str,
+        repo: &str,
+        tag: Option<&String>,
+        asset: &str,
+        unpack: bool,
+        frozen: bool,
+        hash_algo: HashAlgo,
+    ) -> Result<Self> {
+        let api = Self::api(owner, repo)?;
+        let release = match tag {
+            Some(tag) => api.release(tag)?,
+            None => api.latest_release()?,
+        };
+        log::info!("Locked release: {}", release.tag_name);
+
+        let url = release.find_asset(asset)?.browser_download_url.clone();
+        log::info!("Locked asset: {url}");
+
+        let revision = Self::resolve_tag(owner, repo, &release.tag_name)?;
+        log::info!("Locked revision: {revision}");
+
+        let hash = compute_file_hash(&url, unpack, hash_algo)?;
+        log::info!("Locked hash: {hash}");
+
+        Ok(Self {
+            owner: owner.into(),
+            repo: repo.into(),
+            tag: release.tag_name,
+            asset: asset.into(),
+            revision,
+            url,
+            hash,
+            unpack,
+            meta: SourceMeta {
+                frozen,
+                ..SourceMeta::default()
+            },
+        })
+    }
+
+    /// Update the source to the matching asset of the newest release.
+    fn update(&mut self) -> Result<Option<UpdateSummary>> {
+        if self.meta.frozen {
+            log::info!("Source is frozen");
+            return Ok(None);
+        }
+
+        let release = Self::api(&self.owner, &self.repo)?.latest_release()?;
+        if release.tag_name == self.tag {
+            log::info!("Already up to date");
+            return Ok(None);
+        }
+
+        let url = release
+            .find_asset(&self.asset)?
+            .browser_download_url
+            .clone();
+        let newest_revision = Self::resolve_tag(&self.owner, &self.repo, &release.tag_name)?;
+        let hash = compute_file_hash(&url, self.unpack, self.hash.algo())?;
+
+        log::info!("Updated release: {} → {}", self.tag, release.tag_name);
+        log::info!("Updated hash: {} → {}", self.hash, hash);
+        let current_revision = self.revision.clone();
+        self.tag = release.tag_name;
+        self.revision = newest_revision.clone();
+        self.url = url;
+        self.hash = hash;
+
+        Ok(Some(UpdateSummary::new(current_revision, newest_revision)))
+    }
+
+    /// Find out whether a newer release is available without changing the source.
+    fn pending_update(&self) -> Result<Option<UpdateSummary>> {
+        let release = Self::api(&self.owner, &self.repo)?.latest_release()?;
+        if release.tag_name == self.tag {
+            return Ok(None);
+        }
+        let newest_revision = Self::resolve_tag(&self.owner, &self.repo, &release.tag_name)?;
+        Ok(Some(UpdateSummary::new(
+            self.revision.clone(),
+            newest_revision,
+        )))
+    }
+
+    /// Return a client for the GitHub API of the repository.
+    ///
+    /// If `LON_GITHUB_TOKEN` is set, requests are authenticated to raise the rate limit.
+    fn api(owner: &str, repo: &str) -> Result<GitHubRepoApi> {
+        let mut builder = GitHubRepoApi::builder(&format!("{owner}/{repo}"));
+        if let Ok(token) = env::var("LON_GITHUB_TOKEN") {
+            builder = builder.token(&token);
+        }
+        builder.build()
+    }
+
+    /// Return the revision a release tag points to.
+    fn resolve_tag(owner: &str, repo: &str, tag: &str) -> Result<Revision> {
+        let git_url = GitHubSource::git_url(owner, repo);
+        git::find_tag_revision(&git_url, tag)?
+            .with_context(|| format!("The repository {git_url} doesn't contain the tag {tag}"))
+    }
+}
+
+/// A Nix channel (e.g. nixos-24.05) that is locked to its current release.
+#[derive(Clone)]
+pub struct ChannelSource {
+    /// Name of the channel
+    channel: String,
+    /// Revision of nixpkgs the locked release was built from
+    revision: Revision,
+    /// URL of the nixexprs tarball of the locked release
+    url: String,
+    hash: NixHash,
+
+    meta: SourceMeta,
+}
+
+impl ChannelSource {
+    /// Lock the current release of a channel.
+    pub fn new(channel: &str, frozen: bool) -> Result<Self> {
+        let release = http::resolve_channel(channel)?;
+        log::info!("Locked release: {}", release.url);
+        log::info!("Locked revision: {}", release.revision);
+
+        let hash = Self::compute_hash(&release.url)?;
+        log::info!("Locked hash: {hash}");
+
+        Ok(Self {
+            channel: channel.into(),
+            revision: release.revision,
+            url: release.url,
+            hash,
+            meta: SourceMeta {
+                frozen,
+                ..SourceMeta::default()
+            },
+        })
+    }
+
+    /// Compute the hash of the nixexprs tarball of a release.
+    ///
+    /// The single top-level directory of the tarball (the name of the release) is stripped when
+    /// unpacking.
+    fn compute_hash(url: &str) -> Result<NixHash> {
+        nix::prefetch_tarball(url, None, HashAlgo::Sha256)
+            .with_context(|| format!("Failed to compute hash for {url}"))
+    }
+
+    /// Update the source to the current release of the channel.
+    fn update(&mut self) -> Result<Option<UpdateSummary>> {
+        if self.meta.frozen {
+            log::info!("Source is frozen");
+            return Ok(None);
+        }
+        self.lock_current_release()
+    }
+
+    /// Lock the current release of the channel if it isn't locked already.
+    ///
+    /// Unlike [`Self::update`], this also applies to frozen sources.
+    fn lock_current_release(&mut self) -> Result<Option<UpdateSummary>> {
+        let release = http::resolve_channel(&self.channel)?;
+        if release.url == self.url {
+            log::info!("Already up to date");
+            return Ok(None);
+        }
+
+        let hash = Self::compute_hash(&release.url)?;
+
+        log::info!("Updated release: {} → {}", self.url, release.url);
+        log::info!("Updated hash: {} → {}", self.hash, hash);
+        let current_revision = self.revision.clone();
+        self.revision = release.revision.clone();
+        self.url = release.url;
+        self.hash = hash;
+
+        Ok(Some(UpdateSummary::new(current_revision, release.revision)))
+    }
+
+    /// Find out whether the channel has a newer release without changing the source.
+    fn pending_update(&self) -> Result<Option<UpdateSummary>> {
+        let release = http::resolve_channel(&self.channel)?;
+        if release.url == self.url {
+            return Ok(None);
+        }
+        Ok(Some(UpdateSummary::new(
+            self.revision.clone(),
+            release.revision,
+        )))
+    }
+
+    /// Switch to another channel and lock its current release.
+    ///
+    /// Frozen sources switch too because the locked release must belong to the channel.
+    fn modify(&mut self, channel: Option<&String>) -> Result<()> {
+        let Some(channel) = channel else {
+            return Ok(());
+        };
+        if self.channel == *channel {
+            log::info!("Channel is already {channel}");
+            return Ok(());
+        }
+        log::info!("Changed channel: {} → {channel}", self.channel);
+        self.channel.clone_from(channel);
+        self.lock_current_release()?;
+        Ok(())
+    }
+}
+
+/// Anything Nix can fetch, described by a flake reference (e.g. `git+https://...`).
+#[derive(Clone)]
+pub struct FlakeSource {
+    /// Flake reference the source is resolved from on every update
+    flake_ref: String,
+    /// Attributes of the locked flake reference as passed to `builtins.fetchTree`
+    ///
+    /// The narHash is kept in `hash` instead.
+    locked: serde_json::Map<String, serde_json::Value>,
+    /// Revision the flake reference resolved to
+    ///
+    /// Not all fetchers (e.g. tarballs) report one.
+    revision: Option<Revision>,
+    /// The narHash of the source
+    hash: NixHash,
+
+    meta: SourceMeta,
+}
+
+impl FlakeSource {
+    /// Lock a flake reference via `nix flake prefetch`.
+    pub fn new(flake_ref: &str, frozen: bool) -> Result<Self> {
+        let prefetch = Self::prefetch(flake_ref)?;
+        let revision = Self::locked_revision(&prefetch.locked);
+        if let Some(revision) = &revision {
+            log::info!("Locked revision: {revision}");
+        }
+        log::info!("Locked hash: {}", prefetch.hash);
+
+        Ok(Self {
+            flake_ref: flake_ref.into(),
+            locked: prefetch.locked,
+            revision,
+            hash: prefetch.hash,
+            meta: SourceMeta {
+                frozen,
+                ..SourceMeta::default()
+            },
+        })
+    }
+
+    /// Resolve and fetch the flake reference.
+    ///
+    /// The narHash is removed from the locked attributes because it is locked as the hash.
+    fn prefetch(flake_ref: &str) -> Result<nix::FlakePrefetch> {
+        let mut prefetch = nix::prefetch_flake(flake_ref)
+            .with_context(|| format!("Failed to prefetch {flake_ref}"))?;
+        prefetch.locked.remove("narHash");
+        Ok(prefetch)
+    }
+
+    /// Return the revision of the locked flake reference attributes.
+    fn locked_revision(locked: &serde_json::Map<String, serde_json::Value>) -> Option<Revision> {
+        locked
+            .get("rev")
+            .and_then(serde_json::Value::as_str)
+            .map(Revision::new)
+    }
+
+    /// Return the locked revision, or the narHash in the Nix base32 format if there is none.
+    ///
+    /// This is what updates are reported with.
+    fn version(revision: Option<&Revision>, hash: &NixHash) -> Revision {
+        revision.cloned().unwrap_or_else(|| {
+            Revision::new(&nix_compat::nixbase32::encode(hash.digest_as_bytes()))
+        })
+    }
+
+    /// Return the Unix timestamp of the last modification reported by the fetcher.
+    fn last_modified(&self) -> Option<u64> {
+        self.locked
+            .get("lastModified")
+            .and_then(serde_json::Value::as_u64)
+    }
+
+    /// Return the {owner}/{repo} of flake references that point to GitHub.
+    fn github_repo(&self) -> Option<String> {
+        if self.locked.get("type")?.as_str()? != "github" {
+            return None;
+        }
+        let owner = self.locked.get("owner")?.as_str()?;
+        let repo = self.locked.get("repo")?.as_str()?;
+        Some(format!("{owner}/{repo}"))
+    }
+
+    /// Fail if the locked revision was fetched again with a different narHash.
+    ///
+    /// The content of a revision never changes, so this is never treated as an update.
+    fn ensure_hash_matches(&self, revision: Option<&Revision>, hash: &NixHash) -> Result<()> {
+        if revision.is_some() && revision == self.revision.as_ref() && *hash != self.hash {
+            bail!(LonError::HashMismatch {
+                url: self.flake_ref.clone(),
+                expected: self.hash.clone(),
+                actual: hash.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Resolve the flake reference again and lock it if its content changed.
+    fn update(&mut self) -> Result<Option<UpdateSummary>> {
+        if self.meta.frozen {
+            log::info!("Source is frozen");
+            return Ok(None);
+        }
+
+        let prefetch = Self::prefetch(&self.flake_ref)?;
+        if prefetch.hash == self.hash {
+            log::info!("Already up to date");
+            return Ok(None);
+        }
+        let revision = Self::locked_revision(&prefetch.locked);
+        self.ensure_hash_matches(revision.as_ref(), &prefetch.hash)?;
+        let hash = prefetch.hash;
+
+        let current_version = Self::version(self.revision.as_ref(), &self.hash);
+        let new_version = Self::version(revision.as_ref(), &hash);
+
+        if let (Some(current_revision), Some(revision)) = (&self.revision, &revision) {
+            log::info!("Updated revision: {current_revision} → {revision}");
+        }
+        log::info!("Updated hash: {} → {}", self.hash, hash);
+        self.locked = prefetch.locked;
+        self.revision = revision;
+        self.hash = hash;
+
+        Ok(Some(UpdateSummary::new(current_version, new_version)))
+    }
+
+    /// Find out whether the flake reference resolves to something else without changing the
+    /// source.
+    ///
+    /// Unlike for other sources, this fetches the source because Nix resolves and fetches flake
+    /// references in one go.
+    fn pending_update(&self) -> Result<Option<UpdateSummary>> {
+        let prefetch = Self::prefetch(&self.flake_ref)?;
+        if prefetch.hash == self.hash {
+            return Ok(None);
+        }
+        let revision = Self::locked_revision(&prefetch.locked);
+        self.ensure_hash_matches(revision.as_ref(), &prefetch.hash)?;
+        Ok(Some(UpdateSummary::new(
+            Self::version(self.revision.as_ref(), &self.hash),
+            Self::version(revision.as_ref(), &prefetch.hash),
+        )))
+    }
+}
+
+/// A single file or archive downloaded from an arbitrary URL.
+///
+/// There is nothing to resolve, so updates don't change the source. Another URL is locked via
+/// `lon modify --url` instead.
+#[derive(Clone)]
+pub struct UrlSource {
+    url: String,
+    hash: NixHash,
+    /// Whether the file is an archive that is unpacked instead of a single file
+    unpack: bool,
+
+    meta: SourceMeta,
+}
+
+impl UrlSource {
+    pub fn new(url: &str, unpack: bool, frozen: bool, hash_algo: HashAlgo) -> Result<Self> {
+        let hash = compute_file_hash(url, unpack, hash_algo)?;
+        log::info!("Locked hash: {hash}");
+
+        Ok(Self {
+            url: url.into(),
+            hash,
+            unpack,
+            meta: SourceMeta {
+                frozen,
+                ..SourceMeta::default()
+            },
+        })
+    }
+
+    /// Lock another URL with the same hash algorithm.
+    fn set_url(&mut self, url: &str) -> Result<()> {
+        if self.url == url {
+            log::info!("URL is already {url}");
+            return Ok(());
+        }
+        let hash = compute_file_hash(url, self.unpack, self.hash.algo())?;
+        log::info!("Changed URL: {} → {url}", self.url);
+        log::info!("Updated hash: {} → {hash}", self.hash);
+        self.url = url.into();
+        self.hash = hash;
+        Ok(())
+    }
+}
+
+/// A directory relative to lon.nix that is used as it is instead of being fetched.
+///
+/// This is meant for developing against a local checkout. If a hash is locked, lon.nix copies the
+/// directory to the store and verifies it.
+#[derive(Clone)]
+pub struct PathSource {
+    /// Path to the directory relative to lon.nix
+    local_path: String,
+    /// Hash of the NAR serialization of the directory
+    hash: Option<NixHash>,
+
+    meta: SourceMeta,
+}
+
+impl PathSource {
+    /// Use the directory at `local_path` relative to `directory`, the directory of lon.nix.
+    ///
+    /// If `lock_hash` is set, the hash of the current content of the directory is locked too.
+    pub fn new(directory: &Path, local_path: &str, lock_hash: bool, frozen: bool) -> Result<Self> {
+        if Path::new(local_path).is_absolute() {
+            bail!("The path {local_path} must be relative to the directory of lon.nix");
+        }
+        let local_path = local_path.trim_end_matches('/');
+        let full_path = directory.join(local_path);
+        if !full_path.is_dir() {
+            bail!("The path {} is not a directory", full_path.display());
+        }
+
+        let hash = if lock_hash {
+            let hash = nar::hash_path(&full_path)
+                .with_context(|| format!("Failed to compute hash for {}", full_path.display()))?;
+            log::info!("Locked hash: {hash}");
+            Some(hash)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            local_path: local_path.into(),
+            hash,
+            meta: SourceMeta {
+                frozen,
+                ..SourceMeta::default()
+            },
+        })
+    }
+}
+
+/// Resolve the branch a new source tracks.
+///
+/// A branch pattern (e.g. `release/*`) is resolved to the highest matching branch. The pattern is
+/// returned as well so that updates can resolve it again.
+fn resolve_branch(url: &str, branch: &str) -> Result<(String, Option<String>)> {
+    if !git::is_branch_pattern(branch) {
+        return Ok((branch.into(), None));
+    }
+    let resolved = git::find_newest_branch(url, branch)?;
+    Ok((resolved, Some(branch.into())))
+}
+
+/// Ensure that an explicit revision is part of the history of the branch it is locked for.
+///
+/// Otherwise, the source would claim to track a branch that never contained its revision.
+fn ensure_on_branch(url: &str, revision: &str, branch: &str) -> Result<()> {
+    if !git::is_on_branch(url, revision, branch)? {
+        bail!("The revision {revision} is not on the branch {branch}");
+    }
+    Ok(())
+}
+
+/// Switch to the highest branch matching the branch pattern of a source, if it has one.
+fn update_branch(url: &str, branch: &mut String, branch_pattern: Option<&str>) -> Result<()> {
+    let Some(branch_pattern) = branch_pattern else {
+        return Ok(());
+    };
+    let newest_branch = git::find_newest_branch(url, branch_pattern)?;
+    if *branch != newest_branch {
+        log::info!("Updated branch: {branch} → {newest_branch}");
+        *branch = newest_branch;
+    }
+    Ok(())
+}
+
+/// Normalize a subdirectory of a source.
+///
+/// Leading and trailing slashes are stripped so that the path can be appended to the fetched
+/// source in lon.nix.
+fn normalize_path(path: &str) -> Result<String> {
+    let normalized = path.trim_matches('/');
+    if normalized.is_empty() {
+        bail!("The path {path:?} doesn't point to a subdirectory");
+    }
+    if normalized.split('/').any(|component| component == "..") {
+        bail!("The path {path:?} must not leave the source");
+    }
+    Ok(normalized.into())
+}
+
+// Boilerplate to convert between the internal representation (Sources) and the external lock file
+// representation.
+//
+// This seems like a lot of duplication but it is mostly incidental duplication. Once we add more
+// lockfile versions this'll become clear.
+
+impl From<lock::Lock> for Sources {
     fn from(value: lock::Lock) -> Self {
         match value {
             lock::Lock::V1(l) => Sources::from(l),
@@ -414,6 +2309,28 @@ impl From<lock::v1::Source> for Source {
         match value {
             lock::v1::Source::Git(s) => Self::Git(s.into()),
             lock::v1::Source::GitHub(s) => Self::GitHub(s.into()),
+            lock::v1::Source::Bitbucket(s) => Self::Bitbucket(s.into()),
+            lock::v1::Source::Forgejo(s) => Self::Forgejo(s.into()),
+            lock::v1::Source::Archive(s) => Self::Archive(s.into()),
+            lock::v1::Source::GitHubRelease(s) => Self::GitHubRelease(s.into()),
+            lock::v1::Source::Channel(s) => Self::Channel(s.into()),
+            lock::v1::Source::Flake(s) => Self::Flake(s.into()),
+            lock::v1::Source::Url(s) => Self::Url(s.into()),
+            lock::v1::Source::Path(s) => Self::Path(s.into()),
+        }
+    }
+}
+
+impl SourceMeta {
+    /// Combine the settings and bookkeeping of a locked source with whether it is frozen.
+    fn from_lock(frozen: bool, value: lock::v1::SourceMeta) -> Self {
+        Self {
+            frozen,
+            manual: value.manual,
+            nar_hash: value.nar_hash,
+            update_interval: value.update_interval,
+            last_checked: value.last_checked,
+            labels: value.labels,
         }
     }
 }
@@ -422,12 +2339,19 @@ impl From<lock::v1::GitSource> for GitSource {
     fn from(value: lock::v1::GitSource) -> Self {
         Self {
             branch: value.branch,
+            branch_pattern: value.branch_pattern,
             revision: Revision::new(&value.revision),
             url: value.url,
             hash: value.hash,
             last_modified: value.last_modified,
-            submodules: value.submodules,
-            frozen: value.frozen,
+            reference: value.reference,
+            options: GitFetchOptions {
+                submodules: value.fetch_options.submodules,
+                leave_dot_git: value.fetch_options.leave_dot_git,
+                deep_clone: value.fetch_options.deep_clone,
+            },
+            meta: SourceMeta::from_lock(value.frozen, value.meta),
+            path: value.path,
         }
     }
 }
@@ -437,11 +2361,135 @@ impl From<lock::v1::GitHubSource> for GitHubSource {
         Self {
             owner: value.owner,
             repo: value.repo,
-            branch: value.branch,
+            tarball: Tarball {
+                branch: value.branch,
+                branch_pattern: value.branch_pattern,
+                revision: Revision::new(&value.revision),
+                url: value.url,
+                hash: value.hash,
+                reference: value.reference,
+            },
+            meta: SourceMeta::from_lock(value.frozen, value.meta),
+            path: value.path,
+        }
+    }
+}
+
+impl From<lock::v1::BitbucketSource> for BitbucketSource {
+    fn from(value: lock::v1::BitbucketSource) -> Self {
+        Self {
+            workspace: value.workspace,
+            repo: value.repo,
+            tarball: Tarball {
+                branch: value.branch,
+                branch_pattern: value.branch_pattern,
+                revision: Revision::new(&value.revision),
+                url: value.url,
+                hash: value.hash,
+                reference: value.reference,
+            },
+            meta: SourceMeta::from_lock(value.frozen, value.meta),
+            path: value.path,
+        }
+    }
+}
+
+impl From<lock::v1::ForgejoSource> for ForgejoSource {
+    fn from(value: lock::v1::ForgejoSource) -> Self {
+        Self {
+            host: value.host,
+            owner: value.owner,
+            repo: value.repo,
+            tarball: Tarball {
+                branch: value.branch,
+                branch_pattern: value.branch_pattern,
+                revision: Revision::new(&value.revision),
+                url: value.url,
+                hash: value.hash,
+                reference: value.reference,
+            },
+            meta: SourceMeta::from_lock(value.frozen, value.meta),
+            path: value.path,
+        }
+    }
+}
+
+impl From<lock::v1::GitHubReleaseSource> for GitHubReleaseSource {
+    fn from(value: lock::v1::GitHubReleaseSource) -> Self {
+        Self {
+            owner: value.owner,
+            repo: value.repo,
+            tag: value.tag,
+            asset: value.asset,
+            revision: Revision::new(&value.revision),
+            url: value.url,
+            hash: value.hash,
+            unpack: matches!(value.fetch_type, lock::v1::FetchType::Tarball),
+            meta: SourceMeta::from_lock(value.frozen, value.meta),
+        }
+    }
+}
+
+impl From<lock::v1::ArchiveSource> for ArchiveSource {
+    fn from(value: lock::v1::ArchiveSource) -> Self {
+        Self {
+            repository: value.repository,
+            archive_url: value.archive_url,
+            tarball: Tarball {
+                branch: value.branch,
+                branch_pattern: value.branch_pattern,
+                revision: Revision::new(&value.revision),
+                url: value.url,
+                hash: value.hash,
+                reference: value.reference,
+            },
+            meta: SourceMeta::from_lock(value.frozen, value.meta),
+            path: value.path,
+        }
+    }
+}
+
+impl From<lock::v1::ChannelSource> for ChannelSource {
+    fn from(value: lock::v1::ChannelSource) -> Self {
+        Self {
+            channel: value.channel,
             revision: Revision::new(&value.revision),
             url: value.url,
             hash: value.hash,
-            frozen: value.frozen,
+            meta: SourceMeta::from_lock(value.frozen, value.meta),
+        }
+    }
+}
+
+impl From<lock::v1::FlakeSource> for FlakeSource {
+    fn from(value: lock::v1::FlakeSource) -> Self {
+        Self {
+            flake_ref: value.flake_ref,
+            locked: value.locked,
+            revision: value.revision.as_deref().map(Revision::new),
+            hash: value.hash,
+            meta: SourceMeta::from_lock(value.frozen, value.meta),
+        }
+    }
+}
+
+impl From<lock::v1::UrlSource> for UrlSource {
+    fn from(value: lock::v1::UrlSource) -> Self {
+        Self {
+            url: value.url,
+            hash: value.hash,
+            unpack: matches!(value.fetch_type, lock::v1::FetchType::Tarball),
+            meta: SourceMeta::from_lock(value.frozen, value.meta),
+        }
+    }
+}
+
+impl From<lock::v1::PathSource> for PathSource {
+    fn from(value: lock::v1::PathSource) -> Self {
+        Self {
+            local_path: value.local_path,
+            hash: value.hash,
+            meta: SourceMeta::from_lock(value.frozen, value.meta),
         }
     }
 }
@@ -462,6 +2510,26 @@ impl From<Source> for lock::v1::Source {
         match value {
             Source::Git(s) => Self::Git(s.into()),
             Source::GitHub(s) => Self::GitHub(s.into()),
+            Source::Bitbucket(s) => Self::Bitbucket(s.into()),
+            Source::Forgejo(s) => Self::Forgejo(s.into()),
+            Source::Archive(s) => Self::Archive(s.into()),
+            Source::GitHubRelease(s) => Self::GitHubRelease(s.into()),
+            Source::Channel(s) => Self::Channel(s.into()),
+            Source::Flake(s) => Self::Flake(s.into()),
+            Source::Url(s) => Self::Url(s.into()),
+            Source::Path(s) => Self::Path(s.into()),
+        }
+    }
+}
+
+impl From<SourceMeta> for lock::v1::SourceMeta {
+    fn from(value: SourceMeta) -> Self {
+        Self {
+            manual: value.manual,
+            nar_hash: value.nar_hash,
+            update_interval: value.update_interval,
+            last_checked: value.last_checked,
+            labels: value.labels,
         }
     }
 }
@@ -470,13 +2538,21 @@ impl From<GitSource> for lock::v1::GitSource {
     fn from(value: GitSource) -> Self {
         Self {
             fetch_type: lock::v1::FetchType::Git,
+            frozen: value.meta.frozen,
             branch: value.branch,
+            branch_pattern: value.branch_pattern,
             revision: value.revision.to_string(),
             url: value.url,
             hash: value.hash,
             last_modified: value.last_modified,
-            submodules: value.submodules,
-            frozen: value.frozen,
+            reference: value.reference,
+            fetch_options: lock::v1::GitFetchOptions {
+                submodules: value.options.submodules,
+                leave_dot_git: value.options.leave_dot_git,
+                deep_clone: value.options.deep_clone,
+            },
+            meta: value.meta.into(),
+            path: value.path,
         }
     }
 }
@@ -485,13 +2561,152 @@ impl From<GitHubSource> for lock::v1::GitHubSource {
     fn from(value: GitHubSource) -> Self {
         Self {
             fetch_type: lock::v1::FetchType::Tarball,
+            frozen: value.meta.frozen,
             owner: value.owner,
             repo: value.repo,
-            branch: value.branch,
+            branch: value.tarball.branch,
+            branch_pattern: value.tarball.branch_pattern,
+            revision: value.tarball.revision.to_string(),
+            url: value.tarball.url,
+            hash: value.tarball.hash,
+            reference: value.tarball.reference,
+            meta: value.meta.into(),
+            path: value.path,
+        }
+    }
+}
+
+impl From<BitbucketSource> for lock::v1::BitbucketSource {
+    fn from(value: BitbucketSource) -> Self {
+        Self {
+            fetch_type: lock::v1::FetchType::Tarball,
+            frozen: value.meta.frozen,
+            workspace: value.workspace,
+            repo: value.repo,
+            branch: value.tarball.branch,
+            branch_pattern: value.tarball.branch_pattern,
+            revision: value.tarball.revision.to_string(),
+            url: value.tarball.url,
+            hash: value.tarball.hash,
+            reference: value.tarball.reference,
+            meta: value.meta.into(),
+            path: value.path,
+        }
+    }
+}
+
+impl From<ForgejoSource> for lock::v1::ForgejoSource {
+    fn from(value: ForgejoSource) -> Self {
+        Self {
+            fetch_type: lock::v1::FetchType::Tarball,
+            frozen: value.meta.frozen,
+            host: value.host,
+            owner: value.owner,
+            repo: value.repo,
+            branch: value.tarball.branch,
+            branch_pattern: value.tarball.branch_pattern,
+            revision: value.tarball.revision.to_string(),
+            url: value.tarball.url,
+            hash: value.tarball.hash,
+            reference: value.tarball.reference,
+            meta: value.meta.into(),
+            path: value.path,
+        }
+    }
+}
+
+impl From<GitHubReleaseSource> for lock::v1::GitHubReleaseSource {
+    fn from(value: GitHubReleaseSource) -> Self {
+        Self {
+            fetch_type: if value.unpack {
+                lock::v1::FetchType::Tarball
+            } else {
+                lock::v1::FetchType::File
+            },
+            frozen: value.meta.frozen,
+            owner: value.owner,
+            repo: value.repo,
+            tag: value.tag,
+            asset: value.asset,
+            revision: value.revision.to_string(),
+            url: value.url,
+            hash: value.hash,
+            meta: value.meta.into(),
+        }
+    }
+}
+
+impl From<ArchiveSource> for lock::v1::ArchiveSource {
+    fn from(value: ArchiveSource) -> Self {
+        Self {
+            fetch_type: lock::v1::FetchType::Tarball,
+            frozen: value.meta.frozen,
+            repository: value.repository,
+            archive_url: value.archive_url,
+            branch: value.tarball.branch,
+            branch_pattern: value.tarball.branch_pattern,
+            revision: value.tarball.revision.to_string(),
+            url: value.tarball.url,
+            hash: value.tarball.hash,
+            reference: value.tarball.reference,
+            meta: value.meta.into(),
+            path: value.path,
+        }
+    }
+}
+
+impl From<ChannelSource> for lock::v1::ChannelSource {
+    fn from(value: ChannelSource) -> Self {
+        Self {
+            fetch_type: lock::v1::FetchType::Tarball,
+            frozen: value.meta.frozen,
+            channel: value.channel,
             revision: value.revision.to_string(),
             url: value.url,
             hash: value.hash,
-            frozen: value.frozen,
+            meta: value.meta.into(),
+        }
+    }
+}
+
+impl From<FlakeSource> for lock::v1::FlakeSource {
+    fn from(value: FlakeSource) -> Self {
+        Self {
+            fetch_type: lock::v1::FetchType::Flake,
+            frozen: value.meta.frozen,
+            flake_ref: value.flake_ref,
+            locked: value.locked,
+            revision: value.revision.map(|revision| revision.to_string()),
+            hash: value.hash,
+            meta: value.meta.into(),
+        }
+    }
+}
+
+impl From<UrlSource> for lock::v1::UrlSource {
+    fn from(value: UrlSource) -> Self {
+        Self {
+            fetch_type: if value.unpack {
+                lock::v1::FetchType::Tarball
+            } else {
+                lock::v1::FetchType::File
+            },
+            frozen: value.meta.frozen,
+            url: value.url,
+            hash: value.hash,
+            meta: value.meta.into(),
+        }
+    }
+}
+
+impl From<PathSource> for lock::v1::PathSource {
+    fn from(value: PathSource) -> Self {
+        Self {
+            fetch_type: lock::v1::FetchType::Path,
+            frozen: value.meta.frozen,
+            local_path: value.local_path,
+            hash: value.hash,
+            meta: value.meta.into(),
         }
     }
 }
@@ -515,4 +2730,270 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn parse_and_convert_github_release() -> Result<()> {
+        let lock_json = indoc::indoc! {r#"
+            {
+              "version": "1",
+              "sources": {
+                "foo": {
+                  "type": "GitHubRelease",
+                  "fetchType": "file",
+                  "owner": "example",
+                  "repo": "foo",
+                  "tag": "v1.2.3",
+                  "asset": "foo-*.tar.gz",
+                  "revision": "0f8e9ab4bcc1a7e1d9c6d3a2d3f5cfe1a4d5b0c7",
+                  "url": "https://github.com/example/foo/releases/download/v1.2.3/foo-1.2.3.tar.gz",
+                  "hash": "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=",
+                  "narHash": "sha256-h1zQVhXuYoKTgJWqgVa7veoCJlbuG+xyzLQAar1Np5Y="
+                }
+              }
+            }"#};
+        let lock = serde_json::from_str::<lock::v1::Lock>(lock_json)?;
+        let sources = Sources::from(lock);
+        let source = sources.get("foo").context("Missing source")?;
+        assert_eq!(source.git_url()?, "https://github.com/example/foo.git");
+        assert!(source.path().is_none());
+        assert!(source.identity().starts_with("file:"));
+
+        let latest_lock_json = serde_json::to_string_pretty(&sources.into_latest_lock())?;
+        assert_eq!(lock_json, latest_lock_json);
+
+        // An unpacked asset
+        let lock_json = lock_json.replace(r#""fetchType": "file""#, r#""fetchType": "tarball""#);
+        let sources = Sources::from(serde_json::from_str::<lock::v1::Lock>(&lock_json)?);
+        let source = sources.get("foo").context("Missing source")?;
+        assert!(source.identity().starts_with("tarball:"));
+
+        let latest_lock_json = serde_json::to_string_pretty(&sources.into_latest_lock())?;
+        assert_eq!(lock_json, latest_lock_json);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_and_convert_forgejo() -> Result<()> {
+        let lock_json = indoc::indoc! {r#"
+            {
+              "version": "1",
+              "sources": {
+                "lix": {
+                  "type": "Forgejo",
+                  "fetchType": "tarball",
+                  "host": "git.lix.systems",
+                  "owner": "lix-project",
+                  "repo": "lix",
+                  "branch": "main",
+                  "revision": "0f8e9ab4bcc1a7e1d9c6d3a2d3f5cfe1a4d5b0c7",
+                  "url": "https://git.lix.systems/lix-project/lix/archive/0f8e9ab4bcc1a7e1d9c6d3a2d3f5cfe1a4d5b0c7.tar.gz",
+                  "hash": "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+                }
+              }
+            }"#};
+        let lock = serde_json::from_str::<lock::v1::Lock>(lock_json)?;
+        let sources = Sources::from(lock);
+        let source = sources.get("lix").context("Missing source")?;
+        assert_eq!(source.kind(), SourceKind::Forgejo);
+        assert_eq!(
+            source.git_url()?,
+            "https://git.lix.systems/lix-project/lix.git"
+        );
+        assert_eq!(
+            source.flake_ref()?,
+            "tarball+https://git.lix.systems/lix-project/lix/archive/0f8e9ab4bcc1a7e1d9c6d3a2d3f5cfe1a4d5b0c7.tar.gz"
+        );
+
+        let latest_lock_json = serde_json::to_string_pretty(&sources.into_latest_lock())?;
+        assert_eq!(lock_json, latest_lock_json);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_and_convert_meta() -> Result<()> {
+        let lock_json = indoc::indoc! {r#"
+            {
+              "version": "1",
+              "sources": {
+                "nixpkgs": {
+                  "type": "Channel",
+                  "fetchType": "tarball",
+                  "frozen": true,
+                  "channel": "nixos-unstable",
+                  "revision": "a9858885e197f984d92d7fe64e9fff6b2e488d40",
+                  "url": "https://releases.nixos.org/nixos/unstable/nixos-24.11pre123456.a9858885e197/nixexprs.tar.xz",
+                  "hash": "sha256-h1zQVhXuYoKTgJWqgVa7veoCJlbuG+xyzLQAar1Np5Y=",
+                  "manual": true,
+                  "narHash": "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=",
+                  "updateInterval": 86400,
+                  "lastChecked": 1722329086,
+                  "labels": [
+                    "nixpkgs"
+                  ]
+                }
+              }
+            }"#};
+        let sources = Sources::from(serde_json::from_str::<lock::v1::Lock>(lock_json)?);
+        let source = sources.get("nixpkgs").context("Missing source")?;
+        let meta = source.meta();
+        assert!(meta.frozen && meta.manual);
+        assert_eq!(meta.update_interval, Some(86_400));
+        assert_eq!(meta.last_checked, Some(1_722_329_086));
+        assert_eq!(meta.labels, ["nixpkgs"]);
+        assert!(source.nar_hash().is_some());
+
+        let latest_lock_json = serde_json::to_string_pretty(&sources.into_latest_lock())?;
+        assert_eq!(lock_json, latest_lock_json);
+
+        Ok(())
+    }
+
+    #[test]
+    fn build_flake_refs() -> Result<()> {
+        let lock = serde_json::from_str::<lock::v1::Lock>(include_str!("../tests/lon.lock"))?;
+        let sources = Sources::from(lock);
+        let flake_ref = |name| {
+            sources
+                .get(name)
+                .context("Missing source")
+                .and_then(Source::flake_ref)
+        };
+
+        assert_eq!(
+            flake_ref("lanzaboote")?,
+            "git+ssh://git@github.com/nix-community/lanzaboote.git?ref=refs/heads/master&rev=f5a3a7dff44d131807fc1a89fbd8576cd870334a"
+        );
+        assert_eq!(
+            flake_ref("nixpkgs")?,
+            "github:nixos/nixpkgs/a9858885e197f984d92d7fe64e9fff6b2e488d40"
+        );
+        assert_eq!(
+            flake_ref("python-bitbucket")?,
+            "tarball+https://bitbucket.org/atlassian/python-bitbucket/get/3b9d6d4ec1b6c2aa1fbe4c31b5de1b4e4c37e1e5.tar.gz"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn render_archive_urls() -> Result<()> {
+        let revision = "0f8e9ab4bcc1a7e1d9c6d3a2d3f5cfe1a4d5b0c7";
+        let url = |template: &str, repository| {
+            ArchiveSource::url(&archive_url_template(template)?, repository, revision)
+        };
+
+        assert_eq!(
+            url("forgejo", "https://codeberg.org/forgejo/forgejo.git")?,
+            format!("https://codeberg.org/forgejo/forgejo/archive/{revision}.tar.gz")
+        );
+        assert_eq!(
+            url("gitlab", "https://gitlab.com/group/subgroup/project")?,
+            format!(
+                "https://gitlab.com/group/subgroup/project/-/archive/{revision}/project-{revision}.tar.gz"
+            )
+        );
+        assert_eq!(
+            url(
+                "cgit",
+                "https://git.kernel.org/pub/scm/linux/kernel/git/torvalds/linux.git"
+            )?,
+            format!(
+                "https://git.kernel.org/pub/scm/linux/kernel/git/torvalds/linux.git/snapshot/linux-{revision}.tar.gz"
+            )
+        );
+        assert_eq!(
+            url(
+                "https://{host}/archive/{repo}/{rev}.zip",
+                "https://user@git.example.com/repo/"
+            )?,
+            format!("https://git.example.com/archive/repo/{revision}.zip")
+        );
+
+        assert!(archive_url_template("https://example.com/archive.tar.gz").is_err());
+        assert!(archive_url_template("https://example.com/{version}/{rev}.tar.gz").is_err());
+        assert!(url("forgejo", "codeberg.org/forgejo/forgejo").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_and_convert_archive() -> Result<()> {
+        let lock_json = indoc::indoc! {r#"
+            {
+              "version": "1",
+              "sources": {
+                "forgejo": {
+                  "type": "Archive",
+                  "fetchType": "tarball",
+                  "repository": "https://codeberg.org/forgejo/forgejo.git",
+                  "archiveUrl": "https://{host}/{owner}/{repo}/archive/{rev}.tar.gz",
+                  "branch": "forgejo",
+                  "revision": "0f8e9ab4bcc1a7e1d9c6d3a2d3f5cfe1a4d5b0c7",
+                  "url": "https://codeberg.org/forgejo/forgejo/archive/0f8e9ab4bcc1a7e1d9c6d3a2d3f5cfe1a4d5b0c7.tar.gz",
+                  "hash": "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+                }
+              }
+            }"#};
+        let sources = Sources::from(serde_json::from_str::<lock::v1::Lock>(lock_json)?);
+        let source = sources.get("forgejo").context("Missing source")?;
+        assert_eq!(
+            source.git_url()?,
+            "https://codeberg.org/forgejo/forgejo.git"
+        );
+        assert_eq!(source.branch(), Some("forgejo"));
+
+        let latest_lock_json = serde_json::to_string_pretty(&sources.into_latest_lock())?;
+        assert_eq!(lock_json, latest_lock_json);
+
+        Ok(())
+    }
+
+    #[test]
+    fn update_schedule() -> Result<()> {
+        let lock = serde_json::from_str::<lock::v1::Lock>(include_str!("../tests/lon.lock"))?;
+        let mut sources = Sources::from(lock);
+        let source = sources.get_mut("lanzaboote").context("Missing source")?;
+
+        let last_modified = 1_722_329_086;
+        assert!(source.is_due(last_modified));
+
+        source.meta_mut().update_interval = Some(86_400);
+        assert!(!source.is_due(last_modified + 3_600));
+        assert!(source.is_due(last_modified + 86_400));
+
+        // A check without an update postpones the next one as well.
+        source.record_check(last_modified + 86_400);
+        assert!(!source.is_due(last_modified + 2 * 86_400 - 1));
+        assert!(source.is_due(last_modified + 2 * 86_400));
+
+        Ok(())
+    }
+
+    #[test]
+    fn find_duplicates() -> Result<()> {
+        let lock_json = include_str!("../tests/lon.lock");
+        let sources = Sources::from(serde_json::from_str::<lock::v1::Lock>(lock_json)?);
+        // lanzaboote-2 exposes a subdirectory of the same fetch.
+        assert!(sources.duplicates().is_empty());
+
+        let lock_json = lock_json.replace(",\n      \"path\": \"nix/modules\"", "");
+        let sources = Sources::from(serde_json::from_str::<lock::v1::Lock>(&lock_json)?);
+        assert_eq!(
+            sources.duplicates(),
+            vec![vec!["lanzaboote", "lanzaboote-2"]]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn normalize_subdirectory() -> Result<()> {
+        assert_eq!(normalize_path("/pkgs/tools/")?, "pkgs/tools");
+        assert_eq!(normalize_path("pkgs")?, "pkgs");
+        assert!(normalize_path("/").is_err());
+        assert!(normalize_path("pkgs/../..").is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,149 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+
+/// Return the current time as a Unix timestamp.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Parse a human readable duration (e.g. `12h`, `1d`, `2w`) into seconds.
+pub fn parse_duration(s: &str) -> Result<u64> {
+    let Some(unit) = s.chars().last() else {
+        bail!("Empty duration");
+    };
+
+    let seconds_per_unit = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => bail!("Unknown unit in duration {s}. Use one of s, m, h, d, w"),
+    };
+
+    let value = s[..s.len() - unit.len_utf8()]
+        .parse::<u64>()
+        .with_context(|| format!("Failed to parse duration {s}"))?;
+
+    value
+        .checked_mul(seconds_per_unit)
+        .with_context(|| format!("The duration {s} is too long"))
+}
+
+/// Format a number of seconds as a human readable duration in its largest whole unit (e.g. `3d`).
+pub fn format_duration(seconds: u64) -> String {
+    let (unit, seconds_per_unit) = [('d', 24 * 60 * 60), ('h', 60 * 60), ('m', 60)]
+        .into_iter()
+        .find(|(_, seconds_per_unit)| seconds >= *seconds_per_unit)
+        .unwrap_or(('s', 1));
+
+    format!("{}{unit}", seconds / seconds_per_unit)
+}
+
+/// Parse an ISO 8601 timestamp with an offset (e.g. `2024-07-30T08:44:46+02:00`) into a Unix
+/// timestamp.
+///
+/// Fractional seconds are ignored.
+pub fn parse_timestamp(s: &str) -> Result<u64> {
+    let parse = || -> Option<i64> {
+        let (date, time) = s.split_once('T')?;
+        let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+        let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+
+        let (clock, offset) = time.split_at_checked(8)?;
+        let mut clock = clock.splitn(3, ':').map(str::parse::<i64>);
+        let (hour, minute, second) = (
+            clock.next()?.ok()?,
+            clock.next()?.ok()?,
+            clock.next()?.ok()?,
+        );
+
+        let offset =
+            parse_offset(offset.trim_start_matches(|c: char| c == '.' || c.is_ascii_digit()))?;
+
+        let seconds = days_from_civil(year, month, day) * 24 * 60 * 60
+            + hour * 60 * 60
+            + minute * 60
+            + second;
+        Some(seconds - offset)
+    };
+
+    parse()
+        .and_then(|seconds| u64::try_from(seconds).ok())
+        .with_context(|| format!("Failed to parse timestamp {s}"))
+}
+
+/// Parse a UTC offset (e.g. `Z`, `+02:00`, `-0500`) into seconds.
+fn parse_offset(offset: &str) -> Option<i64> {
+    if offset == "Z" {
+        return Some(0);
+    }
+    let (sign, offset) = offset.split_at_checked(1)?;
+    let sign = match sign {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let offset = offset.replace(':', "");
+    let (hours, minutes) = offset.split_at_checked(2)?;
+    Some(sign * (hours.parse::<i64>().ok()? * 60 * 60 + minutes.parse::<i64>().ok()? * 60))
+}
+
+/// Return the number of days since the Unix epoch for a date in the proleptic Gregorian calendar.
+///
+/// See <https://howardhinnant.github.io/date_algorithms.html#days_from_civil>.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_durations() -> Result<()> {
+        assert_eq!(parse_duration("30s")?, 30);
+        assert_eq!(parse_duration("12h")?, 43_200);
+        assert_eq!(parse_duration("1d")?, 86_400);
+        assert_eq!(parse_duration("2w")?, 1_209_600);
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("7").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("1y").is_err());
+        assert!(parse_duration("99999999999999999w").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_timestamps() -> Result<()> {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z")?, 0);
+        assert_eq!(parse_timestamp("2024-07-30T08:44:46+02:00")?, 1_722_321_886);
+        assert_eq!(parse_timestamp("2024-07-30T01:44:46-0500")?, 1_722_321_886);
+        assert_eq!(parse_timestamp("2000-02-29T12:00:00+00:00")?, 951_825_600);
+        assert_eq!(
+            parse_timestamp("2000-02-29T15:00:00.123+03:00")?,
+            951_825_600
+        );
+        assert!(parse_timestamp("2024-07-30").is_err());
+        assert!(parse_timestamp("2024-07-30T08:44:46~02:00").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn format_durations() {
+        assert_eq!(format_duration(0), "0s");
+        assert_eq!(format_duration(59), "59s");
+        assert_eq!(format_duration(3_600), "1h");
+        assert_eq!(format_duration(86_399), "23h");
+        assert_eq!(format_duration(90 * 86_400 + 5), "90d");
+    }
+}
@@ -0,0 +1,320 @@
+use std::fs;
+
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::{Fetchers, git, init, locked_source, lon};
+
+#[test]
+fn interactive_requires_terminal() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+
+    let output = lon(tmpdir.path(), ["add", "--interactive"])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("Interactive mode requires a terminal"));
+
+    Ok(())
+}
+
+#[test]
+fn interactive_makes_arguments_optional() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+
+    // The arguments are prompted for, so parsing gets as far as looking for a terminal.
+    for source_type in [
+        "git",
+        "github",
+        "bitbucket",
+        "forgejo",
+        "github-release",
+        "archive",
+        "channel",
+        "flake",
+        "url",
+        "path",
+    ] {
+        let output = lon(tmpdir.path(), ["add", source_type, "--interactive"])?;
+        assert!(!output.status.success());
+        let stderr = String::from_utf8(output.stderr)?;
+        assert!(
+            stderr.contains("Interactive mode requires a terminal"),
+            "{source_type}: {stderr}"
+        );
+    }
+
+    // Without --interactive, the arguments are still required.
+    let output = lon(tmpdir.path(), ["add", "github"])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("<IDENTIFIER>"));
+
+    let output = lon(
+        tmpdir.path(),
+        ["add", "archive", "https://example.com/a/b.git"],
+    )?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("--archive-url"));
+
+    Ok(())
+}
+
+#[test]
+fn add_local_directory() -> Result<()> {
+    let tmpdir = tempdir()?;
+    let project = tmpdir.path().join("project");
+    let fork = tmpdir.path().join("fork");
+
+    fs::create_dir(&project)?;
+    fs::create_dir(&fork)?;
+    fs::write(fork.join("default.nix"), "{ }\n")?;
+    init(&project)?;
+
+    let output = lon(&project, ["add", "path", "fork", "/fork"])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("must be relative"));
+
+    let output = lon(&project, ["add", "path", "fork", "../fork/", "--hash"])?;
+    assert!(output.status.success());
+
+    let lock = fs::read_to_string(project.join("lon.lock"))?;
+    let lock = serde_json::from_str::<serde_json::Value>(&lock)?;
+    let source = &lock["sources"]["fork"];
+    assert_eq!(source["type"], "Path");
+    assert_eq!(source["fetchType"], "path");
+    assert_eq!(source["localPath"], "../fork");
+    assert!(
+        source["hash"]
+            .as_str()
+            .is_some_and(|hash| hash.starts_with("sha256-"))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn add_local_directory_relative_to_nix_file() -> Result<()> {
+    let tmpdir = tempdir()?;
+    let fork = tmpdir.path().join("fork");
+
+    fs::create_dir(&fork)?;
+    fs::write(fork.join("default.nix"), "{ }\n")?;
+    fs::write(
+        tmpdir.path().join("lon.toml"),
+        "nix-file = \"nix/sources.nix\"\n",
+    )?;
+    init(tmpdir.path())?;
+
+    // The path is relative to nix/, not to the project directory.
+    let output = lon(tmpdir.path(), ["add", "path", "fork", "fork"])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("is not a directory"));
+
+    let output = lon(tmpdir.path(), ["add", "path", "fork", "../fork"])?;
+    assert!(output.status.success());
+
+    let lock = fs::read_to_string(tmpdir.path().join("lon.lock"))?;
+    let lock = serde_json::from_str::<serde_json::Value>(&lock)?;
+    assert_eq!(lock["sources"]["fork"]["localPath"], "../fork");
+
+    Ok(())
+}
+
+#[test]
+fn add_forgejo() -> Result<()> {
+    let tmpdir = tempdir()?;
+    let project = tmpdir.path().join("project");
+    let remotes = tmpdir.path().join("remotes");
+    let upstream = remotes.join("forgejo/forgejo.git");
+
+    fs::create_dir_all(&upstream)?;
+    git(&upstream, &["init", "--quiet", "--initial-branch", "main"])?;
+    git(
+        &upstream,
+        &["commit", "--quiet", "--allow-empty", "--message", "init"],
+    )?;
+
+    let mut fetchers = Fetchers::new(&tmpdir.path().join("bin"))?;
+    fetchers.redirect_git("https://codeberg.org/", &remotes);
+    fetchers.prefetch_url_by_url()?;
+
+    fs::create_dir(&project)?;
+    init(&project)?;
+
+    let output = fetchers.lon(&project, ["add", "forgejo", "forgejo/forgejo", "main"])?;
+    assert!(output.status.success());
+
+    let added = locked_source(&project, "forgejo")?;
+    assert_eq!(added["type"], "Forgejo");
+    assert_eq!(added["host"], "codeberg.org");
+    assert_eq!(added["owner"], "forgejo");
+    assert_eq!(added["repo"], "forgejo");
+    assert_eq!(added["branch"], "main");
+    let revision = added["revision"].as_str().unwrap_or_default().to_string();
+    assert_eq!(
+        added["url"],
+        format!("https://codeberg.org/forgejo/forgejo/archive/{revision}.tar.gz")
+    );
+
+    git(
+        &upstream,
+        &["commit", "--quiet", "--allow-empty", "--message", "Fix it"],
+    )?;
+
+    let output = fetchers.lon(&project, ["update"])?;
+    assert!(output.status.success());
+    let updated = locked_source(&project, "forgejo")?;
+    assert_ne!(updated["revision"], added["revision"]);
+    assert_ne!(updated["hash"], added["hash"]);
+
+    let output = fetchers.lon(&project, ["modify", "forgejo", "--revision", &revision])?;
+    assert!(output.status.success());
+    let modified = locked_source(&project, "forgejo")?;
+    for key in ["revision", "url", "hash"] {
+        assert_eq!(modified[key], added[key]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn add_url_and_lock_another_url() -> Result<()> {
+    let tmpdir = tempdir()?;
+    let project = tmpdir.path().join("project");
+
+    let fetchers = Fetchers::new(&tmpdir.path().join("bin"))?;
+    fetchers.prefetch_url_by_url()?;
+
+    fs::create_dir(&project)?;
+    init(&project)?;
+
+    let output = fetchers.lon(
+        &project,
+        [
+            "add",
+            "url",
+            "foo",
+            "https://example.com/foo-1.0.tar.gz",
+            "--unpack",
+        ],
+    )?;
+    assert!(output.status.success());
+
+    let added = locked_source(&project, "foo")?;
+    assert_eq!(added["type"], "Url");
+    assert_eq!(added["fetchType"], "tarball");
+    assert_eq!(added["url"], "https://example.com/foo-1.0.tar.gz");
+    assert!(added.get("revision").is_none());
+
+    // A URL is never updated.
+    let output = fetchers.lon(&project, ["update"])?;
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(locked_source(&project, "foo")?["hash"], added["hash"]);
+
+    let output = fetchers.lon(&project, ["modify", "foo", "--revision", "main"])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("Supply --url to lock another URL"));
+
+    let output = fetchers.lon(
+        &project,
+        [
+            "modify",
+            "foo",
+            "--url",
+            "https://example.com/foo-2.0.tar.gz",
+        ],
+    )?;
+    assert!(output.status.success());
+    let modified = locked_source(&project, "foo")?;
+    assert_eq!(modified["url"], "https://example.com/foo-2.0.tar.gz");
+    assert_ne!(modified["hash"], added["hash"]);
+
+    Ok(())
+}
+
+#[test]
+fn add_flake_and_update() -> Result<()> {
+    let tmpdir = tempdir()?;
+    let project = tmpdir.path().join("project");
+    let revision = tmpdir.path().join("revision");
+
+    let fetchers = Fetchers::new(&tmpdir.path().join("bin"))?;
+    // Locks the revision in the file next to bin/ and derives the hash from it and the content
+    // of the file tampered if it exists.
+    fetchers.add(
+        "nix",
+        indoc::indoc! {r#"
+            #!/bin/sh
+            dir=$(dirname "$0")/..
+            rev=$(cat "$dir/revision")
+            hash=$(cat "$dir/revision" "$dir/tampered" 2>/dev/null | sha256sum | cut -d ' ' -f 1)
+            cat <<JSON
+            {
+              "hash": "sha256:$hash",
+              "locked": {
+                "lastModified": 1718397548,
+                "narHash": "sha256:$hash",
+                "owner": "nixos",
+                "repo": "nixpkgs",
+                "rev": "$rev",
+                "type": "github"
+              }
+            }
+            JSON
+        "#},
+    )?;
+    fs::write(&revision, "a9858885e197f984d92d7fe64e9fff6b2e488d40")?;
+
+    fs::create_dir(&project)?;
+    init(&project)?;
+
+    let output = fetchers.lon(
+        &project,
+        [
+            "add",
+            "flake",
+            "nixpkgs",
+            "github:nixos/nixpkgs/nixos-unstable",
+        ],
+    )?;
+    assert!(output.status.success());
+
+    let added = locked_source(&project, "nixpkgs")?;
+    assert_eq!(added["type"], "Flake");
+    assert_eq!(added["flakeRef"], "github:nixos/nixpkgs/nixos-unstable");
+    assert_eq!(
+        added["revision"],
+        "a9858885e197f984d92d7fe64e9fff6b2e488d40"
+    );
+    // The hash is locked next to the flake reference, not inside it.
+    assert!(added["locked"].get("narHash").is_none());
+
+    let output = fetchers.lon(&project, ["update"])?;
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(locked_source(&project, "nixpkgs")?["hash"], added["hash"]);
+
+    fs::write(&revision, "b134951a4c9f3c995fd7be05f3243f8ecd65d798")?;
+    let output = fetchers.lon(&project, ["update"])?;
+    assert!(output.status.success());
+    let updated = locked_source(&project, "nixpkgs")?;
+    assert_eq!(
+        updated["revision"],
+        "b134951a4c9f3c995fd7be05f3243f8ecd65d798"
+    );
+    assert_eq!(
+        updated["locked"]["rev"],
+        "b134951a4c9f3c995fd7be05f3243f8ecd65d798"
+    );
+    assert_ne!(updated["hash"], added["hash"]);
+
+    // The same revision with other content is never locked.
+    fs::write(tmpdir.path().join("tampered"), "evil")?;
+    let output = fetchers.lon(&project, ["update", "nixpkgs"])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("Hash mismatch"));
+    assert_eq!(locked_source(&project, "nixpkgs")?["hash"], updated["hash"]);
+
+    Ok(())
+}
@@ -0,0 +1,25 @@
+use std::fs;
+
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::{init, lon};
+
+#[test]
+fn audit_sources() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+    fs::copy("tests/lon.lock", tmpdir.path().join("lon.lock"))?;
+
+    let output = lon(tmpdir.path(), ["--porcelain", "audit", "--max-age", "90d"])?;
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("finding\tmedium\tlanzaboote-2\t"));
+    assert!(lines[1].starts_with("finding\tlow\tlanzaboote\ttracks master"));
+
+    Ok(())
+}
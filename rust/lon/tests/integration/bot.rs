@@ -0,0 +1,369 @@
+use std::process::Command as StdCommand;
+
+use anyhow::Result;
+use assert_cmd::Command;
+use tempfile::tempdir;
+
+use crate::{Fetchers, git, init};
+
+#[test]
+fn bot_refuses_dirty_working_tree() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    let status = StdCommand::new("git")
+        .arg("init")
+        .arg("--quiet")
+        .arg(tmpdir.path())
+        .status()?;
+    assert!(status.success());
+
+    // The freshly written lon.{nix,lock} are not committed.
+    init(tmpdir.path())?;
+
+    let output = Command::cargo_bin("lon")?
+        .arg("--directory")
+        .arg(tmpdir.path())
+        .args(["bot", "github"])
+        .env("GITHUB_REPOSITORY", "nixos/nixpkgs")
+        .env("LON_TOKEN", "token")
+        .output()?;
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("The working tree has uncommitted changes"));
+    assert!(stderr.contains("--allow-dirty"));
+
+    Ok(())
+}
+
+#[test]
+fn bot_report_ignores_dirty_working_tree() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    let git = |args: &[&str]| -> Result<()> {
+        let status = StdCommand::new("git")
+            .arg("-C")
+            .arg(tmpdir.path())
+            .args(["-c", "user.name=test", "-c", "user.email=test@test"])
+            .args(args)
+            .status()?;
+        assert!(status.success());
+        Ok(())
+    };
+
+    git(&["init", "--quiet"])?;
+    init(tmpdir.path())?;
+    git(&["add", "."])?;
+    git(&["commit", "--quiet", "--message", "init"])?;
+    std::fs::write(tmpdir.path().join("uncommitted"), "")?;
+
+    // Without sources, nothing is checked and no issue is opened.
+    let output = Command::cargo_bin("lon")?
+        .arg("--directory")
+        .arg(tmpdir.path())
+        .args(["bot", "github", "--report"])
+        .env("GITHUB_REPOSITORY", "nixos/nixpkgs")
+        .env("LON_TOKEN", "token")
+        .output()?;
+    assert!(output.status.success());
+
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(!stderr.contains("The working tree has uncommitted changes"));
+
+    Ok(())
+}
+
+#[test]
+fn bot_clones_remote_directory() -> Result<()> {
+    let tmpdir = tempdir()?;
+    let upstream = tmpdir.path().join("upstream");
+    std::fs::create_dir(&upstream)?;
+
+    let git = |args: &[&str]| -> Result<()> {
+        let status = StdCommand::new("git")
+            .arg("-C")
+            .arg(&upstream)
+            .args(["-c", "user.name=test", "-c", "user.email=test@test"])
+            .args(args)
+            .status()?;
+        assert!(status.success());
+        Ok(())
+    };
+
+    git(&["init", "--quiet"])?;
+    init(&upstream)?;
+    git(&["add", "."])?;
+    git(&["commit", "--quiet", "--message", "init"])?;
+
+    let url = format!("file://{}", upstream.display());
+
+    // Without sources, nothing is checked and no issue is opened.
+    let output = Command::cargo_bin("lon")?
+        .arg("--directory")
+        .arg(&url)
+        .args(["bot", "github", "--report"])
+        .env("GITHUB_REPOSITORY", "nixos/nixpkgs")
+        .env("LON_TOKEN", "token")
+        .output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains(&format!("Cloning {url}")));
+
+    // Other commands would lose their changes with the clone.
+    let output = Command::cargo_bin("lon")?
+        .arg("--directory")
+        .arg(&url)
+        .arg("list")
+        .output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("only be the URL of a git repository"));
+
+    Ok(())
+}
+
+#[test]
+fn bot_writes_report_file() -> Result<()> {
+    let tmpdir = tempdir()?;
+    let upstream = tmpdir.path().join("upstream");
+    let project = tmpdir.path().join("project");
+
+    let git = |directory: &std::path::Path, args: &[&str]| -> Result<()> {
+        let status = StdCommand::new("git")
+            .arg("-C")
+            .arg(directory)
+            .args(["-c", "user.name=test", "-c", "user.email=test@test"])
+            .args(args)
+            .status()?;
+        assert!(status.success());
+        Ok(())
+    };
+
+    for directory in [&upstream, &project] {
+        std::fs::create_dir(directory)?;
+        git(directory, &["init", "--quiet", "--initial-branch", "main"])?;
+    }
+    git(
+        &upstream,
+        &["commit", "--quiet", "--allow-empty", "--message", "init"],
+    )?;
+
+    init(&project)?;
+    let url = format!("file://{}", upstream.display());
+    for name in ["current", "frozen"] {
+        let output = Command::cargo_bin("lon")?
+            .arg("--directory")
+            .arg(&project)
+            .args(["--hasher", "builtin", "add", "git", &url, "main"])
+            .args(["--name", name])
+            .output()?;
+        assert!(output.status.success());
+    }
+    let output = Command::cargo_bin("lon")?
+        .arg("--directory")
+        .arg(&project)
+        .args(["freeze", "frozen"])
+        .output()?;
+    assert!(output.status.success());
+    git(&project, &["add", "."])?;
+    git(&project, &["commit", "--quiet", "--message", "init"])?;
+
+    let report_file = tmpdir.path().join("run.json");
+    let output = Command::cargo_bin("lon")?
+        .arg("--directory")
+        .arg(&project)
+        .args(["--hasher", "builtin", "bot", "github", "--report-file"])
+        .arg(&report_file)
+        .env("GITHUB_REPOSITORY", "nixos/nixpkgs")
+        .env("LON_TOKEN", "token")
+        .output()?;
+    assert!(output.status.success());
+
+    let report =
+        serde_json::from_str::<serde_json::Value>(&std::fs::read_to_string(&report_file)?)?;
+    let outcomes = report["sources"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|source| (source["name"].clone(), source["outcome"].clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        outcomes,
+        [
+            ("current".into(), "unchanged".into()),
+            ("frozen".into(), "frozen".into()),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn bot_writes_report_file_on_error() -> Result<()> {
+    let tmpdir = tempdir()?;
+    let project = tmpdir.path().join("project");
+    std::fs::create_dir(&project)?;
+    let status = StdCommand::new("git")
+        .arg("-C")
+        .arg(&project)
+        .args(["init", "--quiet", "--initial-branch", "main"])
+        .status()?;
+    assert!(status.success());
+    // The uncommitted files stop the bot before it checks any source.
+    init(&project)?;
+
+    let report_file = tmpdir.path().join("run.json");
+    let output = Command::cargo_bin("lon")?
+        .arg("--directory")
+        .arg(&project)
+        .args(["bot", "github", "--report-file"])
+        .arg(&report_file)
+        .env("GITHUB_REPOSITORY", "nixos/nixpkgs")
+        .env("LON_TOKEN", "token")
+        .output()?;
+    assert!(!output.status.success());
+
+    let report =
+        serde_json::from_str::<serde_json::Value>(&std::fs::read_to_string(&report_file)?)?;
+    assert!(
+        report["error"]
+            .as_str()
+            .is_some_and(|error| error.contains("uncommitted changes"))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn bot_dry_run_prints_pull_requests() -> Result<()> {
+    let tmpdir = tempdir()?;
+    let upstream = tmpdir.path().join("upstream");
+    let project = tmpdir.path().join("project");
+
+    let git = |directory: &std::path::Path, args: &[&str]| -> Result<()> {
+        let status = StdCommand::new("git")
+            .arg("-C")
+            .arg(directory)
+            .args(["-c", "user.name=test", "-c", "user.email=test@test"])
+            .args(args)
+            .status()?;
+        assert!(status.success());
+        Ok(())
+    };
+
+    for directory in [&upstream, &project] {
+        std::fs::create_dir(directory)?;
+        git(directory, &["init", "--quiet", "--initial-branch", "main"])?;
+    }
+    git(
+        &upstream,
+        &["commit", "--quiet", "--allow-empty", "--message", "init"],
+    )?;
+
+    init(&project)?;
+    let url = format!("file://{}", upstream.display());
+    let output = Command::cargo_bin("lon")?
+        .arg("--directory")
+        .arg(&project)
+        .args(["--hasher", "builtin", "add", "git", &url, "main"])
+        .args(["--name", "upstream", "--label", "dependencies"])
+        .output()?;
+    assert!(output.status.success());
+    let lock = std::fs::read_to_string(project.join("lon.lock"))?;
+
+    git(
+        &upstream,
+        &["commit", "--quiet", "--allow-empty", "--message", "second"],
+    )?;
+
+    // The working tree is dirty, but nothing is committed anyway.
+    let output = Command::cargo_bin("lon")?
+        .arg("--directory")
+        .arg(&project)
+        .args(["--hasher", "builtin", "bot", "github", "--dry-run"])
+        .env("GITHUB_REPOSITORY", "nixos/nixpkgs")
+        .env("LON_TOKEN", "token")
+        .env("LON_LABELS", "lon")
+        .output()?;
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("Would open Pull Request from lon/upstream: lon: update upstream"));
+    assert!(stdout.contains("Labels: lon, dependencies"));
+    assert!(stdout.contains("upstream:"));
+
+    // Nothing was changed.
+    assert_eq!(std::fs::read_to_string(project.join("lon.lock"))?, lock);
+    let branches = StdCommand::new("git")
+        .arg("-C")
+        .arg(&project)
+        .args(["branch", "--list", "lon/*"])
+        .output()?;
+    assert!(branches.stdout.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn bot_remembers_checks_without_update() -> Result<()> {
+    let tmpdir = tempdir()?;
+    let project = tmpdir.path().join("project");
+    let remotes = tmpdir.path().join("remotes");
+    let upstream = remotes.join("forgejo/forgejo.git");
+
+    std::fs::create_dir_all(&upstream)?;
+    git(&upstream, &["init", "--quiet", "--initial-branch", "main"])?;
+    git(
+        &upstream,
+        &["commit", "--quiet", "--allow-empty", "--message", "init"],
+    )?;
+
+    let mut fetchers = Fetchers::new(&tmpdir.path().join("bin"))?;
+    fetchers.redirect_git("https://codeberg.org/", &remotes);
+    fetchers.prefetch_url_by_url()?;
+
+    std::fs::create_dir(&project)?;
+    git(&project, &["init", "--quiet", "--initial-branch", "main"])?;
+    init(&project)?;
+    // Forgejo sources don't know when they were last updated, so only the remembered check can
+    // delay the next one.
+    let output = fetchers.lon(
+        &project,
+        [
+            "add",
+            "forgejo",
+            "forgejo/forgejo",
+            "main",
+            "--update-interval",
+            "1d",
+        ],
+    )?;
+    assert!(output.status.success());
+    git(&project, &["add", "."])?;
+    git(&project, &["commit", "--quiet", "--message", "init"])?;
+
+    let report_file = tmpdir.path().join("run.json");
+    let mut outcomes = Vec::new();
+    for _ in 0..2 {
+        let output = fetchers
+            .command(&project, ["bot", "github", "--report-file"])?
+            .arg(&report_file)
+            .env("GITHUB_REPOSITORY", "nixos/nixpkgs")
+            .env("LON_TOKEN", "token")
+            .output()?;
+        assert!(output.status.success());
+
+        let report =
+            serde_json::from_str::<serde_json::Value>(&std::fs::read_to_string(&report_file)?)?;
+        outcomes.push(report["sources"][0]["outcome"].clone());
+    }
+    assert_eq!(outcomes, ["unchanged", "skipped"]);
+
+    // The check is remembered outside of the working tree.
+    let output = StdCommand::new("git")
+        .arg("-C")
+        .arg(&project)
+        .args(["status", "--porcelain"])
+        .output()?;
+    assert!(output.stdout.is_empty());
+
+    Ok(())
+}
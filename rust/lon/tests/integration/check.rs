@@ -0,0 +1,26 @@
+use std::fs;
+
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::{init, lon};
+
+#[test]
+fn check() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+
+    let output0 = lon(tmpdir.path(), ["check"])?;
+    assert!(output0.status.success());
+
+    let lon_nix_path = tmpdir.path().join("lon.nix");
+    let lon_nix = fs::read_to_string(&lon_nix_path)?;
+    fs::write(&lon_nix_path, lon_nix.replacen("lock", "edited", 1))?;
+
+    let output1 = lon(tmpdir.path(), ["check"])?;
+    assert!(!output1.status.success());
+    assert!(String::from_utf8(output1.stderr)?.contains("lon.nix is out of date"));
+
+    Ok(())
+}
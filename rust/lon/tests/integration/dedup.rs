@@ -0,0 +1,40 @@
+use std::fs;
+
+use anyhow::{Context, Result};
+use tempfile::tempdir;
+
+use crate::{init, lon};
+
+#[test]
+fn dedup() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+    // lanzaboote-2 exposes a subdirectory of the same fetch, so only lanzaboote-3 is a duplicate.
+    let mut lock =
+        serde_json::from_str::<serde_json::Value>(&fs::read_to_string("tests/lon.lock")?)?;
+    let sources = lock["sources"].as_object_mut().context("Missing sources")?;
+    let lanzaboote = sources["lanzaboote"].clone();
+    sources.insert("lanzaboote-3".into(), lanzaboote);
+    fs::write(
+        tmpdir.path().join("lon.lock"),
+        serde_json::to_string_pretty(&lock)?,
+    )?;
+
+    let output0 = lon(tmpdir.path(), ["dedup"])?;
+    assert!(output0.status.success());
+    assert_eq!(
+        String::from_utf8(output0.stdout)?,
+        "lanzaboote lanzaboote-3\n"
+    );
+
+    let output1 = lon(tmpdir.path(), ["dedup", "--remove"])?;
+    assert!(output1.status.success());
+
+    let lock = fs::read_to_string(tmpdir.path().join("lon.lock"))?;
+    assert!(lock.contains("\"lanzaboote\""));
+    assert!(lock.contains("\"lanzaboote-2\""));
+    assert!(!lock.contains("\"lanzaboote-3\""));
+
+    Ok(())
+}
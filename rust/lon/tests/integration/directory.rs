@@ -0,0 +1,27 @@
+use std::fs;
+
+use anyhow::Result;
+use assert_cmd::Command;
+use tempfile::tempdir;
+
+use crate::init;
+
+#[test]
+fn find_directory_from_subdirectory() -> Result<()> {
+    let tmpdir = tempdir()?;
+    let subdir = tmpdir.path().join("sub");
+    fs::create_dir(&subdir)?;
+
+    init(tmpdir.path())?;
+
+    let output = Command::cargo_bin("lon")?
+        .current_dir(&subdir)
+        .env_remove("LON_DIRECTORY")
+        .arg("list")
+        .output()?;
+    assert!(output.status.success());
+    assert!(!subdir.join("lon.lock").exists());
+    assert!(!subdir.join("lon.nix").exists());
+
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use std::fs;
+
+use anyhow::Result;
+use assert_cmd::Command;
+use tempfile::tempdir;
+
+use crate::{init, lon};
+
+#[test]
+fn export_niv() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+    fs::copy("tests/lon.lock", tmpdir.path().join("lon.lock"))?;
+
+    let output = lon(tmpdir.path(), ["export", "--format", "niv"])?;
+    assert!(output.status.success());
+
+    let sources = serde_json::from_slice::<serde_json::Value>(&output.stdout)?;
+    assert_eq!(sources["nixpkgs"]["type"], "tarball");
+    assert_eq!(
+        sources["nixpkgs"]["rev"],
+        "a9858885e197f984d92d7fe64e9fff6b2e488d40"
+    );
+    assert_eq!(sources["lanzaboote"]["type"], "git");
+
+    let path = tmpdir.path().join("sources.json");
+    let output = Command::cargo_bin("lon")?
+        .arg("--directory")
+        .arg(tmpdir.path())
+        .args(["export", "--format", "niv", "--output"])
+        .arg(&path)
+        .output()?;
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&fs::read_to_string(&path)?)?,
+        sources
+    );
+
+    Ok(())
+}
@@ -12,6 +12,46 @@ fn add_ssh() -> Result<()> {
     add("git@remote:repo.git")
 }
 
+#[test]
+#[ignore]
+fn add_tag_as_branch() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+
+    let output = lon(
+        tmpdir.path(),
+        ["add", "git", "repo", "http://remote/repo.git", "23.42.69"],
+    )?;
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("Did you mean to lock the tag? Use --revision"));
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn add_without_branch() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+
+    let output = lon(
+        tmpdir.path(),
+        ["add", "git", "repo", "http://remote/repo.git"],
+    )?;
+    if !output.status.success() {
+        bail!("Failed to add repo");
+    }
+
+    let lock = fs::read_to_string(tmpdir.path().join("lon.lock"))?;
+    assert!(lock.contains(r#""branch": "main""#));
+
+    Ok(())
+}
+
 fn add(url: &'static str) -> Result<()> {
     let tmpdir = tempdir()?;
 
@@ -37,6 +77,7 @@ fn add(url: &'static str) -> Result<()> {
               "url": "git@remote:repo.git",
               "hash": "sha256-5wJChh/6lrQodEtR+tPll4Xb6ZzbSF7bGaKwH00toO0=",
               "lastModified": 1745335431,
+              "reference": "refs/heads/main",
               "submodules": false
             }
           }
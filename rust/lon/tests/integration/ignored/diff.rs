@@ -0,0 +1,40 @@
+use std::fs;
+
+use anyhow::{Result, bail};
+use tempfile::tempdir;
+
+use crate::{init, lon};
+
+#[test]
+#[ignore]
+fn diff_without_writing() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+
+    let output = lon(
+        tmpdir.path(),
+        ["add", "git", "repo", "http://remote/repo.git", "main"],
+    )?;
+    if !output.status.success() {
+        bail!("Failed to add repo");
+    }
+
+    // Track a branch that is two commits ahead of the locked revision.
+    let lock_path = tmpdir.path().join("lon.lock");
+    let lock =
+        fs::read_to_string(&lock_path)?.replace(r#""branch": "main""#, r#""branch": "myBranch""#);
+    fs::write(&lock_path, &lock)?;
+
+    let output = lon(tmpdir.path(), ["diff", "repo", "--commits", "5"])?;
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("→ "));
+    assert!(stdout.contains("Last 2 commits:"));
+    assert!(stdout.contains("commit for tag 23.42.69"));
+
+    assert_eq!(fs::read_to_string(&lock_path)?, lock);
+
+    Ok(())
+}
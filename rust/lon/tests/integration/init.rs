@@ -1,7 +1,9 @@
+use std::fs;
+
 use anyhow::Result;
 use tempfile::tempdir;
 
-use crate::init;
+use crate::{init, lon};
 
 #[test]
 fn create_files() -> Result<()> {
@@ -14,3 +16,58 @@ fn create_files() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn create_lon_nix_with_nixpkgs_fetchers() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    fs::write(tmpdir.path().join("lon.toml"), "fetchers = \"nixpkgs\"\n")?;
+
+    init(tmpdir.path())?;
+
+    let lon_nix = fs::read_to_string(tmpdir.path().join("lon.nix"))?;
+    assert!(lon_nix.contains("{ pkgs }:"));
+    assert!(lon_nix.contains("pkgs.fetchgit"));
+
+    Ok(())
+}
+
+#[test]
+fn create_only_lock_when_bare() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    let output = lon(tmpdir.path(), ["init", "--bare"])?;
+    assert!(output.status.success());
+    assert!(!tmpdir.path().join("lon.nix").exists());
+    assert!(tmpdir.path().join("lon.lock").exists());
+
+    // Commands that write the lock don't create lon.nix either.
+    let output = lon(tmpdir.path(), ["dedup", "--remove"])?;
+    assert!(output.status.success());
+    assert!(!tmpdir.path().join("lon.nix").exists());
+
+    let output = lon(tmpdir.path(), ["check"])?;
+    assert!(output.status.success());
+
+    Ok(())
+}
+
+#[test]
+fn create_overlay() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    let output = lon(tmpdir.path(), ["init", "--nix-style", "overlay"])?;
+    assert!(output.status.success());
+
+    let lon_toml = fs::read_to_string(tmpdir.path().join("lon.toml"))?;
+    assert_eq!(lon_toml, "nix-style = \"overlay\"\n");
+    let lon_nix = fs::read_to_string(tmpdir.path().join("lon.nix"))?;
+    assert!(lon_nix.contains("final: prev:"));
+    assert!(lon_nix.contains("  sources = builtins.mapAttrs ("));
+
+    // Other commands keep the style.
+    let output = lon(tmpdir.path(), ["check"])?;
+    assert!(output.status.success());
+
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use std::fs;
+
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::{init, lon};
+
+#[test]
+fn list_stale() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+    fs::copy("tests/lon.lock", tmpdir.path().join("lon.lock"))?;
+
+    let output = lon(tmpdir.path(), ["list", "--stale", "90d"])?;
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("lanzaboote ") && lines[0].ends_with("d ago"));
+    assert_eq!(lines[1], "lanzaboote-2 unknown");
+    assert_eq!(lines[2], "nixpkgs unknown");
+    assert_eq!(lines[3], "python-bitbucket unknown");
+
+    Ok(())
+}
+
+#[test]
+fn list_by_type() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+    fs::copy("tests/lon.lock", tmpdir.path().join("lon.lock"))?;
+
+    let output = lon(tmpdir.path(), ["list", "--type", "git"])?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let names = stdout
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["lanzaboote", "lanzaboote-2"]);
+
+    let output = lon(tmpdir.path(), ["list", "--type", "github"])?;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout)?, "nixpkgs unknown\n");
+
+    let output = lon(tmpdir.path(), ["list", "--type", "github-release"])?;
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr)?.contains("No matching sources found"));
+
+    Ok(())
+}
@@ -1,11 +1,32 @@
-use std::{path::Path, process::Output};
+use std::{
+    env,
+    ffi::OsStr,
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::Output,
+};
 
 use anyhow::{Result, bail};
 use assert_cmd::Command;
 
+mod add;
+mod audit;
+mod bot;
+mod check;
+mod dedup;
+mod directory;
+mod export;
 mod ignored;
 mod init;
+mod list;
+mod modify;
+mod offline;
+mod porcelain;
 mod remove;
+mod show;
+mod update;
+mod version;
 
 pub fn lon(tmpdir: &Path, args: impl IntoIterator<Item = &'static str>) -> Result<Output> {
     let mut cmd = Command::cargo_bin("lon")?;
@@ -25,6 +46,19 @@ pub fn lon(tmpdir: &Path, args: impl IntoIterator<Item = &'static str>) -> Resul
     Ok(output)
 }
 
+pub fn git(directory: &Path, args: &[&str]) -> Result<()> {
+    let status = std::process::Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args(["-c", "user.name=test", "-c", "user.email=test@test"])
+        .args(args)
+        .status()?;
+    if !status.success() {
+        bail!("Failed to run git {}", args.join(" "));
+    }
+    Ok(())
+}
+
 fn init(tmpdir: &Path) -> Result<Output> {
     let output = lon(tmpdir, ["init"])?;
     if !output.status.success() {
@@ -32,3 +66,89 @@ fn init(tmpdir: &Path) -> Result<Output> {
     }
     Ok(output)
 }
+
+/// Return the locked source `name` of the project.
+pub fn locked_source(project: &Path, name: &str) -> Result<serde_json::Value> {
+    let lock = fs::read_to_string(project.join("lon.lock"))?;
+    Ok(serde_json::from_str::<serde_json::Value>(&lock)?["sources"][name].clone())
+}
+
+/// Stand-ins for the programs Lon fetches sources with.
+///
+/// Scripts written via [`Fetchers::add`] take precedence over the real programs on PATH.
+pub struct Fetchers {
+    bin: PathBuf,
+    env: Vec<(String, String)>,
+}
+
+impl Fetchers {
+    pub fn new(bin: &Path) -> Result<Self> {
+        fs::create_dir_all(bin)?;
+        Ok(Self {
+            bin: bin.to_path_buf(),
+            env: Vec::new(),
+        })
+    }
+
+    /// Replace the program `name` with a shell script.
+    pub fn add(&self, name: &str, script: &str) -> Result<()> {
+        let path = self.bin.join(name);
+        fs::write(&path, script)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+        Ok(())
+    }
+
+    /// Replace nix-prefetch-url with a script that derives a different hash from every URL.
+    pub fn prefetch_url_by_url(&self) -> Result<()> {
+        self.add(
+            "nix-prefetch-url",
+            indoc::indoc! {r#"
+                #!/bin/sh
+                for url; do :; done
+                printf '%s' "$url" | sha256sum | cut -d ' ' -f 1
+            "#},
+        )
+    }
+
+    /// Make git fetch repositories below `prefix` (e.g. `https://codeberg.org/`) from `directory`.
+    pub fn redirect_git(&mut self, prefix: &str, directory: &Path) {
+        let index = self.env.len() / 2;
+        self.env.push((
+            format!("GIT_CONFIG_KEY_{index}"),
+            format!("url.file://{}/.insteadOf", directory.display()),
+        ));
+        self.env
+            .push((format!("GIT_CONFIG_VALUE_{index}"), prefix.to_string()));
+    }
+
+    pub fn lon<S: AsRef<OsStr>>(
+        &self,
+        directory: &Path,
+        args: impl IntoIterator<Item = S>,
+    ) -> Result<Output> {
+        let output = self.command(directory, args)?.output()?;
+
+        print!("{}", String::from_utf8(output.stdout.clone())?);
+        print!("{}", String::from_utf8(output.stderr.clone())?);
+
+        Ok(output)
+    }
+
+    /// Return the command that runs Lon with the stand-ins without running it.
+    pub fn command<S: AsRef<OsStr>>(
+        &self,
+        directory: &Path,
+        args: impl IntoIterator<Item = S>,
+    ) -> Result<Command> {
+        let path = env::var("PATH")?;
+        let mut cmd = Command::cargo_bin("lon")?;
+        cmd.arg("-vv")
+            .arg("--directory")
+            .arg(directory)
+            .args(args)
+            .env("PATH", format!("{}:{path}", self.bin.display()))
+            .env("GIT_CONFIG_COUNT", (self.env.len() / 2).to_string())
+            .envs(self.env.iter().map(|(key, value)| (key, value)));
+        Ok(cmd)
+    }
+}
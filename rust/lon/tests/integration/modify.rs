@@ -0,0 +1,363 @@
+use std::{fs, path::Path, process::Command as StdCommand};
+
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::{init, lon};
+
+fn git(directory: &Path, args: &[&str]) -> Result<String> {
+    let output = StdCommand::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args(["-c", "user.name=test", "-c", "user.email=test@test"])
+        .args(args)
+        .output()?;
+    assert!(output.status.success());
+    Ok(String::from_utf8(output.stdout)?.trim().into())
+}
+
+#[test]
+fn submodules_only_for_git_sources() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+    fs::copy("tests/lon.lock", tmpdir.path().join("lon.lock"))?;
+
+    let output = lon(tmpdir.path(), ["modify", "nixpkgs", "--submodules"])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("Only git sources support submodules"));
+
+    Ok(())
+}
+
+#[test]
+fn url_only_for_url_sources() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+    fs::copy("tests/lon.lock", tmpdir.path().join("lon.lock"))?;
+
+    let output = lon(
+        tmpdir.path(),
+        [
+            "modify",
+            "nixpkgs",
+            "--url",
+            "https://example.com/foo.tar.gz",
+        ],
+    )?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("Only URL sources can lock another URL"));
+
+    Ok(())
+}
+
+#[test]
+fn github_release_only_locks_newest_release() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+    fs::write(
+        tmpdir.path().join("lon.lock"),
+        indoc::indoc! {r#"
+            {
+              "version": "1",
+              "sources": {
+                "foo": {
+                  "type": "GitHubRelease",
+                  "fetchType": "file",
+                  "owner": "example",
+                  "repo": "foo",
+                  "tag": "v1.2.3",
+                  "asset": "foo-*.tar.gz",
+                  "revision": "0f8e9ab4bcc1a7e1d9c6d3a2d3f5cfe1a4d5b0c7",
+                  "url": "https://github.com/example/foo/releases/download/v1.2.3/foo-1.2.3.tar.gz",
+                  "hash": "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+                }
+              }
+            }"#},
+    )?;
+    let lock = fs::read_to_string(tmpdir.path().join("lon.lock"))?;
+
+    for args in [
+        ["modify", "foo", "--branch", "main"],
+        [
+            "modify",
+            "foo",
+            "--revision",
+            "a9858885e197f984d92d7fe64e9fff6b2e488d40",
+        ],
+    ] {
+        let output = lon(tmpdir.path(), args)?;
+        assert!(!output.status.success());
+        assert!(String::from_utf8(output.stderr)?.contains("always lock the newest release"));
+    }
+
+    // Nothing is reported as changed before the source is rejected.
+    let output = lon(
+        tmpdir.path(),
+        [
+            "set",
+            "foo",
+            "--revision",
+            "a9858885e197f984d92d7fe64e9fff6b2e488d40",
+            "--hash",
+            "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=",
+        ],
+    )?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("lock a release, not a revision"));
+    assert!(!stderr.contains("Updated revision"));
+    assert_eq!(fs::read_to_string(tmpdir.path().join("lon.lock"))?, lock);
+
+    Ok(())
+}
+
+#[test]
+fn set_and_remove_labels() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+    fs::copy("tests/lon.lock", tmpdir.path().join("lon.lock"))?;
+
+    let output = lon(
+        tmpdir.path(),
+        [
+            "modify",
+            "nixpkgs",
+            "--label",
+            "high-priority",
+            "--label",
+            "nixpkgs",
+        ],
+    )?;
+    assert!(output.status.success());
+    let lock = fs::read_to_string(tmpdir.path().join("lon.lock"))?;
+    let lock = serde_json::from_str::<serde_json::Value>(&lock)?;
+    assert_eq!(
+        lock["sources"]["nixpkgs"]["labels"],
+        serde_json::json!(["high-priority", "nixpkgs"])
+    );
+
+    let output = lon(tmpdir.path(), ["modify", "nixpkgs", "--no-labels"])?;
+    assert!(output.status.success());
+    let lock = fs::read_to_string(tmpdir.path().join("lon.lock"))?;
+    assert!(!lock.contains("labels"));
+
+    Ok(())
+}
+
+#[test]
+fn unlock_refuses_frozen_source() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+    fs::copy("tests/lon.lock", tmpdir.path().join("lon.lock"))?;
+
+    let output = lon(tmpdir.path(), ["freeze", "nixpkgs"])?;
+    assert!(output.status.success());
+
+    let output = lon(tmpdir.path(), ["modify", "nixpkgs", "--unlock"])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("Unfreeze it first"));
+
+    let output = lon(
+        tmpdir.path(),
+        ["modify", "nixpkgs", "--unlock", "--revision", "abc"],
+    )?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("cannot be used with"));
+
+    Ok(())
+}
+
+#[test]
+fn resolve_ref_passed_as_revision() -> Result<()> {
+    let tmpdir = tempdir()?;
+    let upstream = tmpdir.path().join("upstream");
+    let project = tmpdir.path().join("project");
+
+    fs::create_dir(&upstream)?;
+    git(&upstream, &["init", "--quiet", "--initial-branch", "main"])?;
+    git(
+        &upstream,
+        &["commit", "--quiet", "--allow-empty", "--message", "first"],
+    )?;
+    git(
+        &upstream,
+        &["tag", "--annotate", "v1.0.0", "--message", "Release 1.0.0"],
+    )?;
+    let tagged = git(&upstream, &["rev-parse", "HEAD"])?;
+    git(
+        &upstream,
+        &["commit", "--quiet", "--allow-empty", "--message", "second"],
+    )?;
+
+    fs::create_dir(&project)?;
+    init(&project)?;
+
+    let url = format!("file://{}", upstream.display());
+    let output = assert_cmd::Command::cargo_bin("lon")?
+        .arg("--directory")
+        .arg(&project)
+        .args(["--hasher", "builtin", "add", "git", &url, "main"])
+        .args(["--name", "upstream"])
+        .output()?;
+    assert!(output.status.success());
+
+    let output = lon(
+        &project,
+        [
+            "--hasher",
+            "builtin",
+            "modify",
+            "upstream",
+            "--revision",
+            "v1.0.0",
+        ],
+    )?;
+    assert!(output.status.success());
+
+    let lock = fs::read_to_string(project.join("lon.lock"))?;
+    let lock = serde_json::from_str::<serde_json::Value>(&lock)?;
+    assert_eq!(lock["sources"]["upstream"]["revision"], tagged.as_str());
+    assert_eq!(lock["sources"]["upstream"]["reference"], "refs/tags/v1.0.0");
+
+    Ok(())
+}
+
+#[test]
+fn change_branch_and_revision() -> Result<()> {
+    let tmpdir = tempdir()?;
+    let upstream = tmpdir.path().join("upstream");
+    let project = tmpdir.path().join("project");
+
+    fs::create_dir(&upstream)?;
+    git(&upstream, &["init", "--quiet", "--initial-branch", "main"])?;
+    git(
+        &upstream,
+        &["commit", "--quiet", "--allow-empty", "--message", "first"],
+    )?;
+    git(&upstream, &["branch", "release"])?;
+    let on_release = git(&upstream, &["rev-parse", "HEAD"])?;
+    git(
+        &upstream,
+        &["commit", "--quiet", "--allow-empty", "--message", "second"],
+    )?;
+    let only_on_main = git(&upstream, &["rev-parse", "HEAD"])?;
+
+    fs::create_dir(&project)?;
+    init(&project)?;
+
+    let url = format!("file://{}", upstream.display());
+    let output = assert_cmd::Command::cargo_bin("lon")?
+        .arg("--directory")
+        .arg(&project)
+        .args(["--hasher", "builtin", "add", "git", &url, "main"])
+        .args(["--name", "upstream"])
+        .output()?;
+    assert!(output.status.success());
+
+    let modify = |revision: &str| -> Result<_> {
+        Ok(assert_cmd::Command::cargo_bin("lon")?
+            .arg("--directory")
+            .arg(&project)
+            .args(["--hasher", "builtin", "modify", "upstream"])
+            .args(["--branch", "release", "--revision", revision])
+            .output()?)
+    };
+
+    // The revision is not part of the new branch.
+    let output = modify(&only_on_main)?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains(&format!(
+        "The revision {only_on_main} is not on the branch release"
+    )));
+    let lock = fs::read_to_string(project.join("lon.lock"))?;
+    let lock = serde_json::from_str::<serde_json::Value>(&lock)?;
+    assert_eq!(lock["sources"]["upstream"]["branch"], "main");
+
+    let output = modify(&on_release)?;
+    assert!(output.status.success());
+    let lock = fs::read_to_string(project.join("lon.lock"))?;
+    let lock = serde_json::from_str::<serde_json::Value>(&lock)?;
+    assert_eq!(lock["sources"]["upstream"]["branch"], "release");
+    assert_eq!(lock["sources"]["upstream"]["revision"], on_release.as_str());
+
+    Ok(())
+}
+
+#[test]
+fn refuse_partial_submodules() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+    fs::copy("tests/lon.lock", tmpdir.path().join("lon.lock"))?;
+
+    let output = lon(
+        tmpdir.path(),
+        ["modify", "nixpkgs", "--submodule", "vendor/foo"],
+    )?;
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8(output.stderr)?
+            .contains("Fetching only some submodules (vendor/foo) is not supported")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn set_revision_and_hash_without_fetching() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+    fs::copy("tests/lon.lock", tmpdir.path().join("lon.lock"))?;
+
+    let revision = "2d068ae5c6516b2d04562de50a58c682540de9bf";
+    let hash = "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=";
+
+    // Invalid hashes are rejected before anything is written.
+    let output = lon(
+        tmpdir.path(),
+        [
+            "--offline",
+            "set",
+            "nixpkgs",
+            "--revision",
+            revision,
+            "--hash",
+            "sha256-abc",
+        ],
+    )?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("Invalid hash"));
+
+    let output = lon(
+        tmpdir.path(),
+        [
+            "--offline",
+            "set",
+            "nixpkgs",
+            "--revision",
+            revision,
+            "--hash",
+            hash,
+        ],
+    )?;
+    assert!(output.status.success());
+
+    let lock = fs::read_to_string(tmpdir.path().join("lon.lock"))?;
+    let lock = serde_json::from_str::<serde_json::Value>(&lock)?;
+    let source = &lock["sources"]["nixpkgs"];
+    assert_eq!(source["revision"], revision);
+    assert_eq!(source["hash"], hash);
+    assert_eq!(
+        source["url"],
+        format!("https://github.com/nixos/nixpkgs/archive/{revision}.tar.gz")
+    );
+    assert!(source.get("reference").is_none());
+
+    Ok(())
+}
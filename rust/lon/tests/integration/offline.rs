@@ -0,0 +1,44 @@
+use std::fs;
+
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::{init, lon};
+
+#[test]
+fn add_fails_offline() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+
+    let output = lon(
+        tmpdir.path(),
+        ["--offline", "add", "github", "nixos/nixpkgs", "master"],
+    )?;
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("Cannot query a remote repository in offline mode"));
+
+    Ok(())
+}
+
+#[test]
+fn update_reports_totals_offline() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+    fs::copy("tests/lon.lock", tmpdir.path().join("lon.lock"))?;
+
+    let output = lon(tmpdir.path(), ["--offline", "update"])?;
+    assert!(!output.status.success());
+
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "Updated 0, unchanged 0, frozen 1, failed 3\n"
+    );
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("Failed to update lanzaboote, nixpkgs, python-bitbucket"));
+
+    Ok(())
+}
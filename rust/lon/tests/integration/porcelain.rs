@@ -0,0 +1,34 @@
+use std::fs;
+
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::{init, lon};
+
+#[test]
+fn porcelain_output() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+    fs::copy("tests/lon.lock", tmpdir.path().join("lon.lock"))?;
+
+    let output0 = lon(tmpdir.path(), ["--porcelain", "freeze", "nixpkgs"])?;
+    assert!(output0.status.success());
+    assert_eq!(String::from_utf8(output0.stdout)?, "frozen\tnixpkgs\n");
+
+    let output1 = lon(tmpdir.path(), ["--porcelain", "remove", "lanzaboote-2"])?;
+    assert!(output1.status.success());
+    assert_eq!(
+        String::from_utf8(output1.stdout)?,
+        "removed\tlanzaboote-2\n"
+    );
+
+    let output2 = lon(tmpdir.path(), ["--porcelain", "--offline", "update"])?;
+    assert!(!output2.status.success());
+    assert_eq!(
+        String::from_utf8(output2.stdout)?,
+        "failed\tlanzaboote\nfailed\tpython-bitbucket\n"
+    );
+
+    Ok(())
+}
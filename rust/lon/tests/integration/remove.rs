@@ -90,3 +90,38 @@ fn remove() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn remove_all() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+    mock_lock(tmpdir.path())?;
+
+    // There is no terminal to confirm it.
+    let output = lon(tmpdir.path(), ["remove", "--all"])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("Supply --yes"));
+
+    let output = lon(tmpdir.path(), ["--porcelain", "remove", "--all", "--yes"])?;
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "removed\tlanzaboote\nremoved\tnixpkgs\n"
+    );
+
+    let actual = fs::read_to_string(tmpdir.path().join("lon.lock"))?;
+    let expected = expect![[r#"
+        {
+          "version": "1",
+          "sources": {}
+        }
+    "#]];
+    expected.assert_eq(&actual);
+
+    let output = lon(tmpdir.path(), ["remove", "--all", "--yes"])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("There are no sources to remove"));
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use std::fs;
+
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::{init, lon};
+
+#[test]
+fn show_locked_hashes() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+    let lock = fs::read_to_string("tests/lon.lock")?;
+    let mut lock = serde_json::from_str::<serde_json::Value>(&lock)?;
+    lock["sources"]["nixpkgs"]["narHash"] =
+        "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".into();
+    fs::write(
+        tmpdir.path().join("lon.lock"),
+        serde_json::to_string_pretty(&lock)?,
+    )?;
+
+    let output = lon(tmpdir.path(), ["show", "nixpkgs"])?;
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        indoc::indoc! {"
+            type: github
+            branch: master
+            locked: a9858885e197f984d92d7fe64e9fff6b2e488d40
+            hash: sha256-h1zQVhXuYoKTgJWqgVa7veoCJlbuG+xyzLQAar1Np5Y=
+            narHash: sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=
+        "}
+    );
+
+    // Without a narHash, only the hash is shown.
+    let output = lon(tmpdir.path(), ["show", "python-bitbucket"])?;
+    assert!(output.status.success());
+    assert!(!String::from_utf8(output.stdout)?.contains("narHash"));
+
+    let output = lon(tmpdir.path(), ["show", "missing"])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("Source missing doesn't exist"));
+
+    Ok(())
+}
@@ -0,0 +1,489 @@
+use std::{env, fs, os::unix::fs::PermissionsExt, process::Command as StdCommand};
+
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::{Fetchers, git, init, lon};
+
+#[test]
+fn prune_sources_that_are_gone() -> Result<()> {
+    let tmpdir = tempdir()?;
+    let upstream = tmpdir.path().join("upstream");
+    let deleted = tmpdir.path().join("deleted");
+    let project = tmpdir.path().join("project");
+
+    for repo in [&upstream, &deleted] {
+        fs::create_dir(repo)?;
+        git(repo, &["init", "--quiet", "--initial-branch", "main"])?;
+        git(
+            repo,
+            &["commit", "--quiet", "--allow-empty", "--message", "init"],
+        )?;
+    }
+    git(&upstream, &["branch", "feature"])?;
+
+    fs::create_dir(&project)?;
+    init(&project)?;
+
+    let upstream_url = format!("file://{}", upstream.display());
+    let deleted_url = format!("file://{}", deleted.display());
+    for (url, branch, name) in [
+        (&upstream_url, "main", "kept"),
+        (&upstream_url, "feature", "feature"),
+        (&deleted_url, "main", "deleted"),
+    ] {
+        let output = assert_cmd::Command::cargo_bin("lon")?
+            .arg("--directory")
+            .arg(&project)
+            .args([
+                "--hasher", "builtin", "add", "git", url, branch, "--name", name,
+            ])
+            .output()?;
+        assert!(output.status.success());
+    }
+
+    git(&upstream, &["branch", "--quiet", "--delete", "feature"])?;
+    fs::remove_dir_all(&deleted)?;
+
+    // Without --prune, the sources that are gone fail to update.
+    let output = lon(&project, ["--hasher", "builtin", "update"])?;
+    assert!(!output.status.success());
+
+    let output = lon(&project, ["--hasher", "builtin", "update", "--prune"])?;
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "Updated 0, unchanged 1, frozen 0, pruned 2, failed 0\n"
+    );
+
+    let lock = fs::read_to_string(project.join("lon.lock"))?;
+    let lock = serde_json::from_str::<serde_json::Value>(&lock)?;
+    let names = lock["sources"]
+        .as_object()
+        .map(|sources| sources.keys().cloned().collect::<Vec<_>>());
+    assert_eq!(names, Some(vec!["kept".to_string()]));
+
+    Ok(())
+}
+
+#[test]
+fn only_write_partial_updates_with_keep_going() -> Result<()> {
+    let tmpdir = tempdir()?;
+    let upstream = tmpdir.path().join("upstream");
+    let deleted = tmpdir.path().join("deleted");
+    let project = tmpdir.path().join("project");
+
+    for repo in [&upstream, &deleted] {
+        fs::create_dir(repo)?;
+        git(repo, &["init", "--quiet", "--initial-branch", "main"])?;
+        git(
+            repo,
+            &["commit", "--quiet", "--allow-empty", "--message", "init"],
+        )?;
+    }
+
+    fs::create_dir(&project)?;
+    init(&project)?;
+
+    for repo in [&upstream, &deleted] {
+        let output = assert_cmd::Command::cargo_bin("lon")?
+            .arg("--directory")
+            .arg(&project)
+            .args(["--hasher", "builtin", "add", "git"])
+            .arg(format!("file://{}", repo.display()))
+            .arg("main")
+            .output()?;
+        assert!(output.status.success());
+    }
+    let lock = fs::read_to_string(project.join("lon.lock"))?;
+
+    git(
+        &upstream,
+        &["commit", "--quiet", "--allow-empty", "--message", "Fix it"],
+    )?;
+    fs::remove_dir_all(&deleted)?;
+
+    let output = lon(&project, ["--hasher", "builtin", "update"])?;
+    assert!(!output.status.success());
+    assert_eq!(fs::read_to_string(project.join("lon.lock"))?, lock);
+
+    let output = lon(&project, ["--hasher", "builtin", "update", "--keep-going"])?;
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8(output.stdout)?.contains("Updated 1, unchanged 0, frozen 0, failed 1")
+    );
+    assert_ne!(fs::read_to_string(project.join("lon.lock"))?, lock);
+
+    Ok(())
+}
+
+#[test]
+fn prune_keeps_sources_on_network_failures() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+    fs::copy("tests/lon.lock", tmpdir.path().join("lon.lock"))?;
+
+    let output = lon(tmpdir.path(), ["--offline", "update", "--prune"])?;
+    assert!(!output.status.success());
+    assert_eq!(
+        fs::read_to_string(tmpdir.path().join("lon.lock"))?,
+        fs::read_to_string("tests/lon.lock")?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn print_commit_message_without_updating() -> Result<()> {
+    let tmpdir = tempdir()?;
+    let upstream = tmpdir.path().join("upstream");
+    let project = tmpdir.path().join("project");
+
+    fs::create_dir(&upstream)?;
+    git(&upstream, &["init", "--quiet", "--initial-branch", "main"])?;
+    git(
+        &upstream,
+        &["commit", "--quiet", "--allow-empty", "--message", "init"],
+    )?;
+
+    fs::create_dir(&project)?;
+    init(&project)?;
+
+    let url = format!("file://{}", upstream.display());
+    let output = assert_cmd::Command::cargo_bin("lon")?
+        .arg("--directory")
+        .arg(&project)
+        .args(["--hasher", "builtin", "add", "git", &url, "main"])
+        .output()?;
+    assert!(output.status.success());
+    let lock = fs::read_to_string(project.join("lon.lock"))?;
+
+    git(
+        &upstream,
+        &["commit", "--quiet", "--allow-empty", "--message", "Fix it"],
+    )?;
+
+    let output = lon(&project, ["commit-message", "upstream", "--commits", "5"])?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.starts_with("lon: update upstream\n\n"));
+    assert!(stdout.contains("Last 1 commits:"));
+    assert!(stdout.contains("Fix it"));
+
+    // Nothing was updated.
+    assert_eq!(fs::read_to_string(project.join("lon.lock"))?, lock);
+
+    Ok(())
+}
+
+#[test]
+fn check_frozen_sources_without_changes() -> Result<()> {
+    let tmpdir = tempdir()?;
+    let upstream = tmpdir.path().join("upstream");
+    let project = tmpdir.path().join("project");
+
+    fs::create_dir(&upstream)?;
+    git(&upstream, &["init", "--quiet", "--initial-branch", "main"])?;
+    git(
+        &upstream,
+        &["commit", "--quiet", "--allow-empty", "--message", "init"],
+    )?;
+
+    fs::create_dir(&project)?;
+    init(&project)?;
+
+    let output = assert_cmd::Command::cargo_bin("lon")?
+        .arg("--directory")
+        .arg(&project)
+        .args(["--hasher", "builtin", "add", "git", "--frozen"])
+        .arg(format!("file://{}", upstream.display()))
+        .args(["main", "--name", "frozen"])
+        .output()?;
+    assert!(output.status.success());
+
+    // Nothing to report while the source is at the newest revision.
+    let output = lon(&project, ["update", "--only-frozen-check"])?;
+    assert_eq!(output.status.code(), Some(3));
+
+    git(
+        &upstream,
+        &["commit", "--quiet", "--allow-empty", "--message", "new"],
+    )?;
+    let lock = fs::read_to_string(project.join("lon.lock"))?;
+
+    let output = lon(&project, ["--porcelain", "update", "--only-frozen-check"])?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.starts_with("behind\tfrozen\t"));
+
+    // The source stays frozen at its revision.
+    assert_eq!(fs::read_to_string(project.join("lon.lock"))?, lock);
+
+    Ok(())
+}
+
+#[test]
+fn list_commits_in_commit_message() -> Result<()> {
+    let tmpdir = tempdir()?;
+    let upstream = tmpdir.path().join("upstream");
+    let project = tmpdir.path().join("project");
+
+    fs::create_dir(&upstream)?;
+    git(&upstream, &["init", "--quiet", "--initial-branch", "main"])?;
+    git(
+        &upstream,
+        &["commit", "--quiet", "--allow-empty", "--message", "init"],
+    )?;
+    // Only the commits are fetched to list them.
+    git(&upstream, &["config", "uploadpack.allowFilter", "true"])?;
+
+    fs::create_dir(&project)?;
+    git(&project, &["init", "--quiet"])?;
+    init(&project)?;
+
+    let output = assert_cmd::Command::cargo_bin("lon")?
+        .arg("--directory")
+        .arg(&project)
+        .args(["--hasher", "builtin", "add", "git"])
+        .arg(format!("file://{}", upstream.display()))
+        .args(["main", "--name", "upstream"])
+        .output()?;
+    assert!(output.status.success());
+    git(&project, &["add", "."])?;
+    git(
+        &project,
+        &["commit", "--quiet", "--message", "add upstream"],
+    )?;
+
+    fs::write(upstream.join("file"), "content")?;
+    git(&upstream, &["add", "file"])?;
+    git(&upstream, &["commit", "--quiet", "--message", "Add a file"])?;
+
+    let output = StdCommand::new(env!("CARGO_BIN_EXE_lon"))
+        .arg("--directory")
+        .arg(&project)
+        .args(["--hasher", "builtin", "update", "--commit"])
+        .args(["--list-commits", "5"])
+        .env("GIT_AUTHOR_NAME", "test")
+        .env("GIT_AUTHOR_EMAIL", "test@test")
+        .env("GIT_COMMITTER_NAME", "test")
+        .env("GIT_COMMITTER_EMAIL", "test@test")
+        .output()?;
+    assert!(output.status.success());
+
+    let message = StdCommand::new("git")
+        .arg("-C")
+        .arg(&project)
+        .args(["log", "-1", "--format=%B"])
+        .output()?;
+    assert!(String::from_utf8(message.stdout)?.contains("Add a file"));
+
+    Ok(())
+}
+
+#[test]
+fn list_commits_when_negotiation_is_rejected() -> Result<()> {
+    let tmpdir = tempdir()?;
+    let upstream = tmpdir.path().join("upstream");
+    let project = tmpdir.path().join("project");
+    let bin = tmpdir.path().join("bin");
+
+    fs::create_dir(&upstream)?;
+    git(&upstream, &["init", "--quiet", "--initial-branch", "main"])?;
+    git(
+        &upstream,
+        &["commit", "--quiet", "--allow-empty", "--message", "init"],
+    )?;
+
+    fs::create_dir(&project)?;
+    init(&project)?;
+
+    let output = assert_cmd::Command::cargo_bin("lon")?
+        .arg("--directory")
+        .arg(&project)
+        .args(["--hasher", "builtin", "add", "git"])
+        .arg(format!("file://{}", upstream.display()))
+        .args(["main", "--name", "upstream"])
+        .output()?;
+    assert!(output.status.success());
+
+    git(
+        &upstream,
+        &["commit", "--quiet", "--allow-empty", "--message", "Retried"],
+    )?;
+
+    // A git that fails every fetch with a negotiation tip like a flaky server.
+    fs::create_dir(&bin)?;
+    let shim = bin.join("git");
+    fs::write(
+        &shim,
+        indoc::indoc! {r#"
+            #!/bin/sh
+            for arg in "$@"; do
+              if [ "$arg" = "--negotiation-tip" ]; then
+                echo "fatal: remote error: upload-pack: not our ref" >&2
+                exit 128
+              fi
+            done
+            PATH="$REAL_PATH" exec git "$@"
+        "#},
+    )?;
+    fs::set_permissions(&shim, fs::Permissions::from_mode(0o755))?;
+
+    let path = env::var("PATH")?;
+    let output = StdCommand::new(env!("CARGO_BIN_EXE_lon"))
+        .arg("--directory")
+        .arg(&project)
+        .args(["diff", "upstream", "--commits", "5"])
+        .env("PATH", format!("{}:{path}", bin.display()))
+        .env("REAL_PATH", &path)
+        .output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?.contains("Retried"));
+
+    Ok(())
+}
+
+#[test]
+fn skip_manual_sources() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+    fs::copy("tests/lon.lock", tmpdir.path().join("lon.lock"))?;
+
+    let output = lon(tmpdir.path(), ["--porcelain", "set-manual", "lanzaboote"])?;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout)?, "manual\tlanzaboote\n");
+
+    let output = lon(tmpdir.path(), ["--offline", "update"])?;
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "Updated 0, unchanged 0, frozen 1, skipped 1, failed 2\n"
+    );
+
+    // A manual source is still updated when it is named explicitly.
+    let output = lon(
+        tmpdir.path(),
+        ["--porcelain", "--offline", "update", "lanzaboote"],
+    )?;
+    assert_eq!(String::from_utf8(output.stdout)?, "failed\tlanzaboote\n");
+
+    let output = lon(tmpdir.path(), ["--porcelain", "unset-manual", "lanzaboote"])?;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout)?, "automatic\tlanzaboote\n");
+
+    Ok(())
+}
+
+#[test]
+fn channel_source_locks_releases_only() -> Result<()> {
+    let tmpdir = tempdir()?;
+
+    init(tmpdir.path())?;
+    fs::write(
+        tmpdir.path().join("lon.lock"),
+        indoc::indoc! {r#"
+            {
+              "version": "1",
+              "sources": {
+                "nixpkgs": {
+                  "type": "Channel",
+                  "fetchType": "tarball",
+                  "channel": "nixos-24.05",
+                  "revision": "b134951a4c9f3c995fd7be05f3243f8ecd65d798",
+                  "url": "https://releases.nixos.org/nixos/24.05/nixos-24.05.7376.b134951a4c9f/nixexprs.tar.xz",
+                  "hash": "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+                }
+              }
+            }"#},
+    )?;
+
+    let output = lon(
+        tmpdir.path(),
+        [
+            "modify",
+            "nixpkgs",
+            "--revision",
+            "a9858885e197f984d92d7fe64e9fff6b2e488d40",
+        ],
+    )?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("Supply --branch to switch channels"));
+
+    let output = lon(
+        tmpdir.path(),
+        [
+            "set",
+            "nixpkgs",
+            "--revision",
+            "a9858885e197f984d92d7fe64e9fff6b2e488d40",
+            "--hash",
+            "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=",
+        ],
+    )?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("lock a release, not a revision"));
+
+    // The current release is resolved via the network.
+    let output = lon(tmpdir.path(), ["--offline", "update"])?;
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "Updated 0, unchanged 0, frozen 0, failed 1\n"
+    );
+
+    let output = lon(tmpdir.path(), ["freeze", "nixpkgs"])?;
+    assert!(output.status.success());
+    let output = lon(tmpdir.path(), ["--offline", "update"])?;
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "Updated 0, unchanged 0, frozen 1, failed 0\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn keep_revision_with_unchanged_content() -> Result<()> {
+    let tmpdir = tempdir()?;
+    let project = tmpdir.path().join("project");
+    let remotes = tmpdir.path().join("remotes");
+    let upstream = remotes.join("forgejo/forgejo.git");
+
+    fs::create_dir_all(&upstream)?;
+    git(&upstream, &["init", "--quiet", "--initial-branch", "main"])?;
+    git(
+        &upstream,
+        &["commit", "--quiet", "--allow-empty", "--message", "init"],
+    )?;
+
+    let mut fetchers = Fetchers::new(&tmpdir.path().join("bin"))?;
+    fetchers.redirect_git("https://codeberg.org/", &remotes);
+    // Every archive has the same content.
+    fetchers.add(
+        "nix-prefetch-url",
+        indoc::indoc! {r#"
+            #!/bin/sh
+            printf 'content' | sha256sum | cut -d ' ' -f 1
+        "#},
+    )?;
+
+    fs::create_dir(&project)?;
+    init(&project)?;
+    let output = fetchers.lon(&project, ["add", "forgejo", "forgejo/forgejo", "main"])?;
+    assert!(output.status.success());
+    let added = fs::read_to_string(project.join("lon.lock"))?;
+
+    git(
+        &upstream,
+        &["commit", "--quiet", "--allow-empty", "--message", "Empty"],
+    )?;
+
+    let output = fetchers.lon(&project, ["update"])?;
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "Updated 0, unchanged 1, frozen 0, failed 0\n"
+    );
+    assert_eq!(fs::read_to_string(project.join("lon.lock"))?, added);
+
+    Ok(())
+}
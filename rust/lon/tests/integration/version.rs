@@ -0,0 +1,27 @@
+use std::fs;
+
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::lon;
+
+#[test]
+fn version_json() -> Result<()> {
+    let tmpdir = tempdir()?;
+    // An invalid lon.toml doesn't prevent printing the version.
+    fs::write(tmpdir.path().join("lon.toml"), "nix-style = 1")?;
+
+    let output = lon(tmpdir.path(), ["version", "--json"])?;
+    assert!(output.status.success());
+
+    let version = serde_json::from_slice::<serde_json::Value>(&output.stdout)?;
+    assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+    assert!(version.get("git_sha").is_some());
+    assert!(
+        version["rustc"]
+            .as_str()
+            .is_some_and(|rustc| rustc.starts_with("rustc "))
+    );
+
+    Ok(())
+}